@@ -0,0 +1,12 @@
+use std::env;
+
+// Sets `cfg(os)` when the standard library is enabled and the target has threads and a
+// clock, which blocking waits, spawned threads and timestamps depend on. wasm32 has neither.
+fn main() {
+    let std = env::var_os("CARGO_FEATURE_STD").is_some();
+    let wasm = env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "wasm32");
+    if std && !wasm {
+        println!("cargo:rustc-cfg=os");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
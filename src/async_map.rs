@@ -0,0 +1,189 @@
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+use crate::{InsertError, ObservableMap, ObserverMap, WaitError};
+
+/// The async counterpart of [`ObservableMap`], whose methods never block the calling thread.
+pub trait AsyncObservableMap<K, V> {
+    fn insert(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> impl Future<Output = Result<(), InsertError<V>>> + Send;
+    fn get(&self, key: K) -> impl Future<Output = Option<V>> + Send;
+    fn wait(&mut self, key: K) -> impl Future<Output = Result<V, WaitError>> + Send;
+
+    /// Waits for the next value of `key`, returning early with [`WaitError::Cancelled`] once
+    /// `token` is cancelled.
+    fn wait_with_cancel(
+        &mut self,
+        key: K,
+        token: &CancellationToken,
+    ) -> impl Future<Output = Result<V, WaitError>> + Send;
+
+    /// Waits for the next value of `key`, failing with [`WaitError::Timeout`] once
+    /// `deadline` has passed.
+    fn wait_deadline(
+        &mut self,
+        key: K,
+        deadline: Instant,
+    ) -> impl Future<Output = Result<V, WaitError>> + Send;
+}
+
+#[derive(Clone)]
+pub struct AsyncObserverMap<K, V> {
+    inner: Arc<RwLock<ObserverMap<K, V>>>,
+}
+
+impl<K, V> AsyncObserverMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ObserverMap::new())),
+        }
+    }
+
+    /// Closes the map for every handle. See [`ObserverMap::close`].
+    pub async fn close(&self) {
+        self.inner.write().await.close()
+    }
+
+    pub async fn is_closed(&self) -> bool {
+        self.inner.read().await.closed
+    }
+}
+
+impl<K, V> AsyncObservableMap<K, V> for AsyncObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Send + Sync,
+    V: Clone + Send + Sync,
+{
+    async fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.inner.write().await.insert(key, value)
+    }
+
+    async fn get(&self, key: K) -> Option<V> {
+        self.inner.read().await.get(key)
+    }
+
+    async fn wait(&mut self, key: K) -> Result<V, WaitError> {
+        let mut rx = self.inner.write().await.watch(key)?;
+        if rx.changed().await.is_err() {
+            return Err(if self.inner.read().await.closed {
+                WaitError::Closed
+            } else {
+                WaitError::Disconnected
+            });
+        }
+        let value = rx.borrow().clone();
+        value.ok_or(WaitError::Disconnected)
+    }
+
+    async fn wait_with_cancel(
+        &mut self,
+        key: K,
+        token: &CancellationToken,
+    ) -> Result<V, WaitError> {
+        tokio::select! {
+            value = self.wait(key) => value,
+            _ = token.cancelled() => Err(WaitError::Cancelled),
+        }
+    }
+
+    async fn wait_deadline(&mut self, key: K, deadline: Instant) -> Result<V, WaitError> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        tokio::time::timeout_at(deadline, self.wait(key))
+            .await
+            .unwrap_or(Err(WaitError::Timeout))
+    }
+}
+
+impl<K, V> Default for AsyncObserverMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn insert_and_get() {
+        let mut map = AsyncObserverMap::new();
+
+        map.insert("key".to_string(), 1u32).await.unwrap();
+        assert_eq!(map.get("key".to_string()).await, Some(1));
+        assert_eq!(map.get("not_a_key".to_string()).await, None);
+    }
+
+    #[tokio::test]
+    async fn wait_for_next_value() {
+        let mut map = AsyncObserverMap::new();
+
+        map.insert("key".to_string(), 1u64).await.unwrap();
+
+        {
+            let mut map = map.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                map.insert("key".to_string(), 2).await.unwrap();
+            });
+        }
+
+        assert_eq!(map.wait("key".to_string()).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn wait_with_cancel_returns_when_token_is_cancelled() {
+        let mut map: AsyncObserverMap<String, u32> = AsyncObserverMap::new();
+        let token = CancellationToken::new();
+
+        {
+            let token = token.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                token.cancel();
+            });
+        }
+
+        assert_eq!(
+            map.wait_with_cancel("key".to_string(), &token).await,
+            Err(WaitError::Cancelled)
+        );
+    }
+
+    #[tokio::test]
+    async fn close_wakes_waiter() {
+        let mut map: AsyncObserverMap<String, u32> = AsyncObserverMap::new();
+
+        {
+            let map = map.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                map.close().await;
+            });
+        }
+
+        assert_eq!(map.wait("key".to_string()).await, Err(WaitError::Closed));
+        assert!(map.is_closed().await);
+    }
+
+    #[tokio::test]
+    async fn wait_deadline_times_out() {
+        let mut map: AsyncObserverMap<String, u32> = AsyncObserverMap::new();
+        let deadline = Instant::now() + Duration::from_millis(50);
+
+        assert_eq!(
+            map.wait_deadline("key".to_string(), deadline).await,
+            Err(WaitError::Timeout)
+        );
+        assert!(Instant::now() >= deadline);
+    }
+}
@@ -0,0 +1,79 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::RecvError;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// The receiving half of an async subscription, returned by
+/// [`ObservableMap::observe_async`](crate::ObservableMap::observe_async).
+///
+/// Receiving is cancel-safe: if a [`recv`](AsyncReceiver::recv) future is dropped before it
+/// completes, for example because another branch of `tokio::select!` won, no value is lost.
+#[derive(Debug)]
+pub struct AsyncReceiver<T> {
+    rx: Inner<T>,
+}
+
+#[derive(Debug)]
+enum Inner<T> {
+    Unbounded(UnboundedReceiver<T>),
+    Bounded(mpsc::Receiver<T>),
+}
+
+impl<T> Inner<T> {
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self {
+            Inner::Unbounded(rx) => rx.poll_recv(cx),
+            Inner::Bounded(rx) => rx.poll_recv(cx),
+        }
+    }
+}
+
+impl<T> AsyncReceiver<T> {
+    pub(crate) fn new(rx: UnboundedReceiver<T>) -> Self {
+        Self {
+            rx: Inner::Unbounded(rx),
+        }
+    }
+
+    pub(crate) fn bounded(rx: mpsc::Receiver<T>) -> Self {
+        Self {
+            rx: Inner::Bounded(rx),
+        }
+    }
+
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { rx: &mut self.rx }
+    }
+
+    pub fn try_recv(&mut self) -> Option<T> {
+        match &mut self.rx {
+            Inner::Unbounded(rx) => rx.try_recv().ok(),
+            Inner::Bounded(rx) => rx.try_recv().ok(),
+        }
+    }
+}
+
+impl<T> Stream for AsyncReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Future returned by [`AsyncReceiver::recv`].
+#[derive(Debug)]
+pub struct Recv<'a, T> {
+    rx: &'a mut Inner<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.rx.poll_recv(cx).map(|value| value.ok_or(RecvError))
+    }
+}
@@ -0,0 +1,75 @@
+use alloc::sync::Arc;
+use core::hash::Hash;
+
+use crate::{
+    channel, InsertError, ObserveError, Observer, ObserverMap, Receiver, ThreadSafeObserverMap,
+};
+
+/// Who last wrote a key, and how often it has been written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteInfo {
+    /// The writer of the current value, if it was inserted with
+    /// [`insert_as`](ObserverMap::insert_as).
+    pub last_writer: Option<Arc<str>>,
+    /// The number of values inserted for the key.
+    pub writes: u64,
+}
+
+/// A value delivered by [`observe_attributed`](ObserverMap::observe_attributed), along with
+/// the writer that inserted it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attributed<V> {
+    pub value: V,
+    pub writer: Option<Arc<str>>,
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// Inserts a value on behalf of `writer`, which is recorded as the key's last writer and
+    /// delivered alongside the value to observers registered with
+    /// [`observe_attributed`](ObserverMap::observe_attributed).
+    pub fn insert_as(&mut self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, Some(writer))
+    }
+
+    /// Who last wrote `key` and how many times it has been written, or `None` if it never has.
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        let item = self.hashmap.get(&key)?;
+        (item.writes > 0).then(|| WriteInfo {
+            last_writer: item.writer().cloned(),
+            writes: item.writes,
+        })
+    }
+
+    /// Observes every update to `key` until the receiver is dropped, along with the writer of
+    /// each value. Values queue up without limit if the receiver falls behind.
+    pub fn observe_attributed(&mut self, key: K) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.add_observer(key, Observer::Attributed(tx))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObserverMap::insert_as`].
+    pub fn insert_as(&self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.write_unleased(&key).insert_as(key, value, writer)
+    }
+
+    /// See [`ObserverMap::write_info`].
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        self.read().write_info(key)
+    }
+
+    /// See [`ObserverMap::observe_attributed`].
+    pub fn observe_attributed(&self, key: K) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        self.write().observe_attributed(key)
+    }
+}
@@ -0,0 +1,253 @@
+use std::hash::Hash;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::channel::{self, Sender};
+use crate::observer::Delivery;
+use crate::project::Projection;
+use crate::{ObserveError, Observer, ObserverMap, Receiver, ThreadSafeObserverMap};
+
+// The values accumulated during one batching window.
+trait Window<T>: Default + Send {
+    type Output;
+
+    fn add(&mut self, value: &T);
+    fn is_empty(&self) -> bool;
+    fn take(&mut self) -> Option<Self::Output>;
+}
+
+impl<T: Clone + Send> Window<T> for Vec<T> {
+    type Output = Vec<T>;
+
+    fn add(&mut self, value: &T) {
+        self.push(value.clone());
+    }
+
+    fn is_empty(&self) -> bool {
+        Vec::is_empty(self)
+    }
+
+    fn take(&mut self) -> Option<Vec<T>> {
+        (!self.is_empty()).then(|| mem::take(self))
+    }
+}
+
+struct Latest<T>(Option<T>);
+
+impl<T> Default for Latest<T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<T: Clone + Send> Window<T> for Latest<T> {
+    type Output = T;
+
+    fn add(&mut self, value: &T) {
+        self.0 = Some(value.clone());
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn take(&mut self) -> Option<T> {
+        self.0.take()
+    }
+}
+
+struct State<W> {
+    window: W,
+    // Cleared once the map unregisters the observer.
+    open: bool,
+}
+
+struct Shared<W> {
+    state: Mutex<State<W>>,
+    // Signalled when a window opens or the observer is unregistered.
+    ready: Condvar,
+}
+
+impl<W> Shared<W> {
+    fn lock(&self) -> MutexGuard<'_, State<W>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Accumulates values on the inserting thread without waking the receiver, which a flushing
+// thread sends them to once the window closes.
+struct Batcher<W, O> {
+    shared: Arc<Shared<W>>,
+    tx: Sender<O>,
+}
+
+impl<W, T> Projection<T> for Batcher<W, W::Output>
+where
+    W: Window<T>,
+    W::Output: Send,
+{
+    fn send(&self, value: &T) -> Delivery {
+        let mut state = self.shared.lock();
+        let opened = state.window.is_empty();
+        state.window.add(value);
+        if opened {
+            self.shared.ready.notify_one();
+        }
+        Delivery::Delivered
+    }
+
+    fn receivers(&self) -> usize {
+        usize::from(!self.tx.is_disconnected())
+    }
+
+    fn pending(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<W, O> Drop for Batcher<W, O> {
+    fn drop(&mut self) {
+        self.shared.lock().open = false;
+        self.shared.ready.notify_one();
+    }
+}
+
+// Sends each window's values `interval` after the first of them arrives, until the observer
+// is unregistered or the receiver is dropped.
+fn flush<W, T>(shared: Arc<Shared<W>>, tx: Sender<W::Output>, interval: Duration)
+where
+    W: Window<T>,
+{
+    loop {
+        let mut state = shared.lock();
+        while state.window.is_empty() {
+            if !state.open {
+                return;
+            }
+            state = shared
+                .ready
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        drop(state);
+        thread::sleep(interval);
+        let batch = shared.lock().window.take();
+        if batch.is_some_and(|batch| tx.send(batch).is_err()) {
+            return;
+        }
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone + Send + 'static,
+{
+    /// Observes every update to `key` in batches: the values inserted within `interval` of the
+    /// first are delivered together once it has passed, so a receiver of a key updated at a
+    /// very high rate is woken once per batch rather than once per value. Values are cloned
+    /// into the batch on the inserting thread. Batches queue up without limit if the receiver
+    /// falls behind.
+    ///
+    /// A thread per observer sends the batches, timed in real time even if the map has a
+    /// [`MockClock`](crate::MockClock).
+    pub fn observe_batched(
+        &mut self,
+        key: K,
+        interval: Duration,
+    ) -> Result<Receiver<Vec<V>>, ObserveError> {
+        self.observe_windowed::<Vec<V>>(key, interval)
+    }
+
+    /// Like [`observe_batched`](ObserverMap::observe_batched), but delivers only the latest
+    /// value of each batch.
+    pub fn observe_batched_latest(
+        &mut self,
+        key: K,
+        interval: Duration,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.observe_windowed::<Latest<V>>(key, interval)
+    }
+
+    fn observe_windowed<W>(
+        &mut self,
+        key: K,
+        interval: Duration,
+    ) -> Result<Receiver<W::Output>, ObserveError>
+    where
+        W: Window<V> + 'static,
+        W::Output: Send + 'static,
+    {
+        let (tx, rx) = channel::unbounded();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                window: W::default(),
+                open: true,
+            }),
+            ready: Condvar::new(),
+        });
+        let batcher = Batcher {
+            shared: shared.clone(),
+            tx: tx.clone(),
+        };
+        self.add_observer(key, Observer::Projected(Box::new(batcher)))?;
+        thread::spawn(move || flush(shared, tx, interval));
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone + Send + 'static,
+{
+    /// See [`ObserverMap::observe_batched`].
+    pub fn observe_batched(
+        &self,
+        key: K,
+        interval: Duration,
+    ) -> Result<Receiver<Vec<V>>, ObserveError> {
+        self.write().observe_batched(key, interval)
+    }
+
+    /// See [`ObserverMap::observe_batched_latest`].
+    pub fn observe_batched_latest(
+        &self,
+        key: K,
+        interval: Duration,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_batched_latest(key, interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn values_within_an_interval_are_delivered_together() {
+        let mut map = ObserverMap::new();
+        let batches = map
+            .observe_batched("px", Duration::from_millis(50))
+            .unwrap();
+        let latest = map
+            .observe_batched_latest("px", Duration::from_millis(50))
+            .unwrap();
+        for px in 1..=3 {
+            map.insert("px", px).unwrap();
+        }
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(batches.recv_timeout(timeout), Ok(vec![1, 2, 3]));
+        assert_eq!(latest.recv_timeout(timeout), Ok(3));
+        map.insert("px", 4).unwrap();
+        assert_eq!(batches.recv_timeout(timeout), Ok(vec![4]));
+
+        drop((batches, latest));
+        map.insert("px", 5).unwrap();
+        assert_eq!(map.observer_count("px"), 0);
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::sync::Arc;
+use core::hash::Hash;
+use core::time::Duration;
+
+#[cfg(feature = "mock-clock")]
+use crate::MockClock;
+use crate::{EvictionPolicy, Metrics, ObserverMap, SizeOf};
+
+/// Configures an [`ObserverMap`] before it is used, returned by [`ObserverMap::builder`].
+/// Each option matches one of the map's constructors or setters, which document it fully.
+/// Convert the built map with [`ThreadSafeObserverMap::from`](crate::ThreadSafeObserverMap)
+/// to share it between threads.
+pub struct ObserverMapBuilder<K, V> {
+    map: ObserverMap<K, V>,
+}
+
+impl<K, V> ObserverMap<K, V> {
+    pub fn builder() -> ObserverMapBuilder<K, V> {
+        ObserverMapBuilder {
+            map: ObserverMap::new(),
+        }
+    }
+}
+
+impl<K, V> ObserverMapBuilder<K, V> {
+    /// See [`ObserverMap::with_capacity`].
+    pub fn capacity(mut self, capacity: usize) -> Self
+    where
+        K: Hash + Eq,
+    {
+        self.map.reserve(capacity);
+        self
+    }
+
+    /// See [`ObserverMap::with_observer_limit`].
+    pub fn observer_limit(mut self, limit: usize) -> Self {
+        self.map.observer_limit = Some(limit);
+        self
+    }
+
+    /// See [`ObserverMap::write_once`].
+    pub fn write_once(mut self) -> Self {
+        self.map.write_once = true;
+        self
+    }
+
+    /// See [`ObserverMap::set_send_timeout`].
+    pub fn send_timeout(mut self, timeout: Duration) -> Self {
+        self.map.set_send_timeout(timeout);
+        self
+    }
+
+    /// See [`ObserverMap::evict_slow_observers`].
+    pub fn evict_slow_observers<F>(mut self, strikes: u32, on_evict: F) -> Self
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.map.evict_slow_observers(strikes, on_evict);
+        self
+    }
+
+    /// See [`ObserverMap::enable_history`].
+    pub fn history(mut self, capacity: usize) -> Self {
+        self.map.enable_history(capacity);
+        self
+    }
+
+    /// See [`ObserverMap::expire_after_idle`].
+    pub fn expire_after_idle(mut self, timeout: Duration) -> Self {
+        self.map.expire_after_idle(timeout);
+        self
+    }
+
+    /// See [`ObserverMap::enable_journal`].
+    pub fn journal(mut self, capacity: usize) -> Self
+    where
+        K: Hash + Eq + Clone,
+        V: Clone,
+    {
+        self.map.enable_journal(capacity);
+        self
+    }
+
+    /// See [`ObserverMap::set_memory_quota`].
+    pub fn memory_quota(mut self, max_bytes: usize, policy: EvictionPolicy) -> Self
+    where
+        K: Hash + Eq + Clone,
+        V: Clone + SizeOf + 'static,
+    {
+        self.map.set_memory_quota(max_bytes, policy);
+        self
+    }
+
+    /// See [`ObserverMap::set_metrics`].
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.map.set_metrics(metrics);
+        self
+    }
+
+    /// See [`ObserverMap::set_clock`].
+    #[cfg(feature = "mock-clock")]
+    pub fn clock(mut self, clock: MockClock) -> Self {
+        self.map.set_clock(clock);
+        self
+    }
+
+    pub fn build(self) -> ObserverMap<K, V> {
+        self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InsertError, ObservableMap, ObserveError, ThreadSafeObserverMap};
+
+    #[test]
+    fn builds_a_configured_map() {
+        let metrics = Arc::new(Metrics::new());
+        let mut map = ObserverMap::builder()
+            .capacity(100)
+            .observer_limit(1)
+            .write_once()
+            .history(2)
+            .journal(4)
+            .memory_quota(1 << 20, EvictionPolicy::Largest)
+            .metrics(metrics.clone())
+            .build();
+        assert!(map.capacity() >= 100);
+
+        let _rx = map.observe("a").unwrap();
+        assert!(matches!(
+            map.observe("a"),
+            Err(ObserveError::TooManyObservers)
+        ));
+        map.insert("a", 1).unwrap();
+        assert!(matches!(
+            map.insert("a", 2),
+            Err(InsertError::AlreadySet(2))
+        ));
+        assert_eq!(map.journal_since(0).unwrap().len(), 1);
+        assert_eq!(metrics.snapshot().inserts, 1);
+        assert_eq!(map.memory_quota_used(), Some(4));
+
+        let shared = ThreadSafeObserverMap::from(map);
+        assert_eq!(shared.get("a"), Some(1));
+    }
+}
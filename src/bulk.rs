@@ -0,0 +1,90 @@
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::{InsertError, ObservableMap, ObserverMap, ThreadSafeObserverMap};
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// Inserts every entry in order, notifying observers of each as
+    /// [`insert`](ObservableMap::insert) would. Stops at the first entry that fails to insert,
+    /// leaving the entries after it uninserted.
+    pub fn insert_many(
+        &mut self,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), InsertError<V>> {
+        let entries = entries.into_iter();
+        self.hashmap.reserve(entries.size_hint().0);
+        for (key, value) in entries {
+            self.insert(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObserverMap::insert_many`]. The entries are inserted under one lock, once none of
+    /// their keys is held by a [`KeyGuard`](crate::KeyGuard), which is much faster than
+    /// inserting them one at a time, for example whilst warming up a large map. Readers
+    /// block until every entry is inserted.
+    pub fn insert_many(
+        &self,
+        entries: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), InsertError<V>> {
+        let entries: Vec<_> = entries.into_iter().collect();
+        let _leases = self
+            .leases
+            .wait_until_all_free(entries.iter().map(|(key, _)| key));
+        self.write().insert_many(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn inserts_every_entry_and_notifies_observers() {
+        let map = ThreadSafeObserverMap::new();
+        let rx = map.clone().observe_unbounded(7).unwrap();
+        map.insert_many((0..100_000).map(|i| (i, i * 2))).unwrap();
+
+        assert_eq!(map.snapshot().len(), 100_000);
+        assert_eq!(map.get(99_999), Some(199_998));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![14]);
+    }
+
+    #[test]
+    fn waits_for_leased_keys() {
+        let map = ThreadSafeObserverMap::new();
+        let guard = map.lock_key("b");
+        let writer = map.clone();
+        let handle = thread::spawn(move || writer.insert_many([("a", 1), ("b", 2)]));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(map.get("a"), None);
+
+        guard.insert(1).unwrap();
+        drop(guard);
+        handle.join().unwrap().unwrap();
+        assert_eq!(map.get("b"), Some(2));
+    }
+
+    #[test]
+    fn stops_at_the_first_entry_that_fails() {
+        let mut map = ObserverMap::write_once();
+        map.insert("b", 0).unwrap();
+        let result = map.insert_many([("a", 1), ("b", 2), ("c", 3)]);
+
+        assert!(matches!(result, Err(InsertError::AlreadySet(2))));
+        assert_eq!((map.get("a"), map.get("c")), (Some(1), None));
+    }
+}
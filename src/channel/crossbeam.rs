@@ -0,0 +1,190 @@
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use crossbeam_channel as cb;
+
+pub(super) fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    wrap(cb::bounded(capacity))
+}
+
+pub(super) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    wrap(cb::unbounded())
+}
+
+// The sender keeps a clone of the receiver so that it can evict queued values, which means the
+// channel itself never reports the receiver as disconnected. Instead, the receiver holds the
+// only sender of a `closed` channel that becomes disconnected when the receiver is dropped.
+fn wrap<T>((tx, rx): (cb::Sender<T>, cb::Receiver<T>)) -> (Sender<T>, Receiver<T>) {
+    let (open, closed) = cb::bounded(0);
+    (
+        Sender {
+            tx,
+            rx: rx.clone(),
+            closed,
+        },
+        Receiver {
+            rx,
+            _open: open,
+            peeked: Mutex::new(None),
+        },
+    )
+}
+
+pub(crate) struct Sender<T> {
+    tx: cb::Sender<T>,
+    rx: cb::Receiver<T>,
+    closed: cb::Receiver<()>,
+}
+
+impl<T> Sender<T> {
+    // Blocks until there is space in the channel or the receiver is dropped.
+    pub(crate) fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if self.is_disconnected() {
+            return Err(SendError(value));
+        }
+        let mut select = cb::Select::new();
+        let send = select.send(&self.tx);
+        select.recv(&self.closed);
+        let operation = select.select();
+        if operation.index() == send {
+            operation
+                .send(&self.tx, value)
+                .map_err(|cb::SendError(value)| SendError(value))
+        } else {
+            let _ = operation.recv(&self.closed);
+            Err(SendError(value))
+        }
+    }
+
+    // Blocks until there is space in the channel or the receiver is dropped, failing with
+    // `Full` if there is still no space once `timeout` has elapsed.
+    pub(crate) fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), TrySendError<T>> {
+        if self.is_disconnected() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        let mut select = cb::Select::new();
+        let send = select.send(&self.tx);
+        select.recv(&self.closed);
+        match select.select_timeout(timeout) {
+            Ok(operation) if operation.index() == send => operation
+                .send(&self.tx, value)
+                .map_err(|cb::SendError(value)| TrySendError::Disconnected(value)),
+            Ok(operation) => {
+                let _ = operation.recv(&self.closed);
+                Err(TrySendError::Disconnected(value))
+            }
+            Err(cb::SelectTimeoutError) => Err(TrySendError::Full(value)),
+        }
+    }
+
+    pub(crate) fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.is_disconnected() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.tx.try_send(value).map_err(|error| match error {
+            cb::TrySendError::Full(value) => TrySendError::Full(value),
+            cb::TrySendError::Disconnected(value) => TrySendError::Disconnected(value),
+        })
+    }
+
+    // Never blocks: if the channel is full the oldest queued value is evicted and returned.
+    pub(crate) fn force_send(&self, mut value: T) -> Result<Option<T>, SendError<T>> {
+        if self.is_disconnected() {
+            return Err(SendError(value));
+        }
+        let mut evicted = None;
+        loop {
+            match self.tx.try_send(value) {
+                Ok(()) => return Ok(evicted),
+                Err(cb::TrySendError::Full(rejected)) => {
+                    value = rejected;
+                    evicted = self.rx.try_recv().ok().or(evicted);
+                }
+                Err(cb::TrySendError::Disconnected(rejected)) => return Err(SendError(rejected)),
+            }
+        }
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        matches!(self.closed.try_recv(), Err(cb::TryRecvError::Disconnected))
+    }
+
+    // The number of values queued for the receiver.
+    pub(crate) fn len(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            rx: self.rx.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+pub(super) struct Receiver<T> {
+    rx: cb::Receiver<T>,
+    _open: cb::Sender<()>,
+    peeked: Mutex<Option<T>>,
+}
+
+impl<T> Receiver<T> {
+    // A value taken from the channel by `peek_with`, which is received before those still
+    // queued.
+    fn take_peeked(&self) -> Option<T> {
+        self.peeked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    // The channel can't be inspected in place, so the next value is taken from it and held
+    // until it is received.
+    pub(super) fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let mut peeked = self.peeked.lock().unwrap_or_else(PoisonError::into_inner);
+        if peeked.is_none() {
+            *peeked = self.rx.try_recv().ok();
+        }
+        peeked.as_ref().map(f)
+    }
+
+    pub(super) fn recv(&self) -> Result<T, RecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.rx.recv().map_err(|cb::RecvError| RecvError)
+    }
+
+    // Changes whenever a value is sent, whilst the receiver is waiting.
+    pub(super) fn version(&self) -> usize {
+        self.rx.len()
+    }
+
+    pub(super) fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.rx.try_recv().map_err(|error| match error {
+            cb::TryRecvError::Empty => TryRecvError::Empty,
+            cb::TryRecvError::Disconnected => TryRecvError::Disconnected,
+        })
+    }
+
+    pub(super) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.rx.recv_timeout(timeout).map_err(|error| match error {
+            cb::RecvTimeoutError::Timeout => RecvTimeoutError::Timeout,
+            cb::RecvTimeoutError::Disconnected => RecvTimeoutError::Disconnected,
+        })
+    }
+
+    pub(super) fn as_crossbeam(&self) -> &cb::Receiver<T> {
+        &self.rx
+    }
+}
@@ -0,0 +1,224 @@
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+// How often a blocked sender checks whether the receiver has been dropped.
+const DISCONNECT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub(super) fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    wrap(::flume::bounded(capacity))
+}
+
+pub(super) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    wrap(::flume::unbounded())
+}
+
+// The sender keeps a clone of the receiver so that it can evict queued values, which means the
+// channel itself never reports the receiver as disconnected. Instead, the receiver holds the
+// only sender of a `closed` channel that becomes disconnected when the receiver is dropped.
+fn wrap<T>((tx, rx): (::flume::Sender<T>, ::flume::Receiver<T>)) -> (Sender<T>, Receiver<T>) {
+    let (open, closed) = ::flume::bounded(0);
+    (
+        Sender {
+            tx,
+            rx: rx.clone(),
+            closed,
+        },
+        Receiver {
+            rx,
+            _open: open,
+            peeked: Mutex::new(None),
+        },
+    )
+}
+
+pub(crate) struct Sender<T> {
+    tx: ::flume::Sender<T>,
+    rx: ::flume::Receiver<T>,
+    closed: ::flume::Receiver<()>,
+}
+
+impl<T> Sender<T> {
+    // Blocks until there is space in the channel or the receiver is dropped.
+    pub(crate) fn send(&self, mut value: T) -> Result<(), SendError<T>> {
+        loop {
+            if self.is_disconnected() {
+                return Err(SendError(value));
+            }
+            match self.tx.send_timeout(value, DISCONNECT_POLL_INTERVAL) {
+                Ok(()) => return Ok(()),
+                Err(
+                    ::flume::SendTimeoutError::Timeout(rejected)
+                    | ::flume::SendTimeoutError::Disconnected(rejected),
+                ) => value = rejected,
+            }
+        }
+    }
+
+    // Blocks until there is space in the channel or the receiver is dropped, failing with
+    // `Full` if there is still no space once `timeout` has elapsed. A timeout too long to
+    // represent as a deadline never elapses.
+    pub(crate) fn send_timeout(
+        &self,
+        mut value: T,
+        timeout: Duration,
+    ) -> Result<(), TrySendError<T>> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self
+                .send(value)
+                .map_err(|SendError(value)| TrySendError::Disconnected(value));
+        };
+        loop {
+            if self.is_disconnected() {
+                return Err(TrySendError::Disconnected(value));
+            }
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                return Err(TrySendError::Full(value));
+            };
+            match self
+                .tx
+                .send_timeout(value, remaining.min(DISCONNECT_POLL_INTERVAL))
+            {
+                Ok(()) => return Ok(()),
+                Err(
+                    ::flume::SendTimeoutError::Timeout(rejected)
+                    | ::flume::SendTimeoutError::Disconnected(rejected),
+                ) => value = rejected,
+            }
+        }
+    }
+
+    pub(crate) fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.is_disconnected() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.tx.try_send(value).map_err(|error| match error {
+            ::flume::TrySendError::Full(value) => TrySendError::Full(value),
+            ::flume::TrySendError::Disconnected(value) => TrySendError::Disconnected(value),
+        })
+    }
+
+    // Never blocks: if the channel is full the oldest queued value is evicted and returned.
+    pub(crate) fn force_send(&self, mut value: T) -> Result<Option<T>, SendError<T>> {
+        if self.is_disconnected() {
+            return Err(SendError(value));
+        }
+        let mut evicted = None;
+        loop {
+            match self.tx.try_send(value) {
+                Ok(()) => return Ok(evicted),
+                Err(::flume::TrySendError::Full(rejected)) => {
+                    value = rejected;
+                    evicted = self.rx.try_recv().ok().or(evicted);
+                }
+                Err(::flume::TrySendError::Disconnected(rejected)) => {
+                    return Err(SendError(rejected))
+                }
+            }
+        }
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.closed.is_disconnected()
+    }
+
+    // The number of values queued for the receiver.
+    pub(crate) fn len(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            rx: self.rx.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+pub(super) struct Receiver<T> {
+    rx: ::flume::Receiver<T>,
+    _open: ::flume::Sender<()>,
+    peeked: Mutex<Option<T>>,
+}
+
+impl<T> Receiver<T> {
+    // A value taken from the channel by `peek_with`, which is received before those still
+    // queued.
+    fn take_peeked(&self) -> Option<T> {
+        self.peeked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    // The channel can't be inspected in place, so the next value is taken from it and held
+    // until it is received.
+    pub(super) fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let mut peeked = self.peeked.lock().unwrap_or_else(PoisonError::into_inner);
+        if peeked.is_none() {
+            *peeked = self.rx.try_recv().ok();
+        }
+        peeked.as_ref().map(f)
+    }
+
+    pub(super) fn recv(&self) -> Result<T, RecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.rx
+            .recv()
+            .map_err(|::flume::RecvError::Disconnected| RecvError)
+    }
+
+    // Changes whenever a value is sent or the last sender is dropped, whilst the receiver is
+    // waiting.
+    pub(super) fn version(&self) -> usize {
+        self.rx.len() + usize::from(self.rx.is_disconnected())
+    }
+
+    pub(super) fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.rx.try_recv().map_err(|error| match error {
+            ::flume::TryRecvError::Empty => TryRecvError::Empty,
+            ::flume::TryRecvError::Disconnected => TryRecvError::Disconnected,
+        })
+    }
+
+    // flume panics on a timeout too long to represent as a deadline, so such a timeout waits
+    // without one.
+    pub(super) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self
+                .recv()
+                .map_err(|RecvError| RecvTimeoutError::Disconnected);
+        };
+        self.rx
+            .recv_deadline(deadline)
+            .map_err(|error| match error {
+                ::flume::RecvTimeoutError::Timeout => RecvTimeoutError::Timeout,
+                ::flume::RecvTimeoutError::Disconnected => RecvTimeoutError::Disconnected,
+            })
+    }
+
+    pub(super) async fn recv_async(&self) -> Result<T, RecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.rx
+            .recv_async()
+            .await
+            .map_err(|::flume::RecvError::Disconnected| RecvError)
+    }
+
+    pub(super) fn as_flume(&self) -> &::flume::Receiver<T> {
+        &self.rx
+    }
+}
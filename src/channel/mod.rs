@@ -0,0 +1,327 @@
+use core::fmt;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use crate::clock::Instant;
+
+use crate::sync::{Arc, AtomicUsize};
+
+#[cfg(not(feature = "std"))]
+mod error;
+
+/// The errors receivers report: those of `std::sync::mpsc`, or the same ones without the
+/// standard library.
+#[cfg(feature = "std")]
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+#[cfg(not(feature = "std"))]
+pub use self::error::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+#[cfg(all(feature = "crossbeam", feature = "flume"))]
+compile_error!("the `crossbeam` and `flume` features are mutually exclusive");
+
+// Each backend provides `Sender`, `Receiver`, `bounded` and `unbounded`. Without the standard
+// library, only the default backend is available, and its channels are all queues.
+#[cfg(all(
+    not(loom),
+    feature = "std",
+    not(any(feature = "crossbeam", feature = "flume"))
+))]
+mod mpsc;
+#[cfg(not(any(feature = "crossbeam", feature = "flume")))]
+mod queue;
+#[cfg(not(any(feature = "crossbeam", feature = "flume")))]
+mod standard;
+#[cfg(not(any(feature = "crossbeam", feature = "flume")))]
+use standard as backend;
+
+#[cfg(feature = "crossbeam")]
+mod crossbeam;
+#[cfg(feature = "crossbeam")]
+use self::crossbeam as backend;
+
+#[cfg(feature = "flume")]
+mod flume;
+#[cfg(feature = "flume")]
+use self::flume as backend;
+
+pub(crate) use backend::Sender;
+
+pub(crate) fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be non-zero");
+    let (tx, rx) = backend::bounded(capacity);
+    (
+        tx,
+        Receiver {
+            inner: rx,
+            unregister: None,
+        },
+    )
+}
+
+pub(crate) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = backend::unbounded();
+    (
+        tx,
+        Receiver {
+            inner: rx,
+            unregister: None,
+        },
+    )
+}
+
+/// The receiving half of a subscription returned by an [`ObservableMap`](crate::ObservableMap).
+///
+/// The API mirrors `std::sync::mpsc::Receiver` and reports errors using the same types,
+/// whichever channel backend is enabled. Without the `std` feature, the errors are this
+/// module's copies of them.
+pub struct Receiver<T> {
+    inner: backend::Receiver<T>,
+    // Dropped after `inner`, so the sender is already disconnected when the map is told.
+    unregister: Option<Unregister>,
+}
+
+impl<T> Receiver<T> {
+    // Counts this receiver in `dropped` once it is dropped, so the map knows to unregister
+    // its sender.
+    pub(crate) fn unregister_on_drop(mut self, dropped: &Arc<AtomicUsize>) -> Self {
+        self.unregister = Some(Unregister(dropped.clone()));
+        self
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.inner.recv()
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.inner.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.inner.recv_timeout(timeout)
+    }
+
+    /// Like [`recv`](Receiver::recv), but first spins for up to `spin` watching for a value
+    /// before parking the thread, so a value sent soon after is received without waiting to
+    /// be rescheduled. Spinning occupies a core, so keep `spin` short.
+    pub fn recv_spinning(&self, spin: Duration) -> Result<T, RecvError> {
+        let version = self.inner.version();
+        match self.try_recv() {
+            Ok(value) => return Ok(value),
+            Err(TryRecvError::Disconnected) => return Err(RecvError),
+            Err(TryRecvError::Empty) => {}
+        }
+        let started = Instant::now();
+        while self.inner.version() == version && started.elapsed() < spin {
+            core::hint::spin_loop();
+        }
+        self.recv()
+    }
+
+    /// A clone of the value the next [`try_recv`](Receiver::try_recv) would return, without
+    /// receiving it, so a consumer can decide whether to process it yet. `None` if no value is
+    /// waiting. With the `crossbeam` or `flume` backend the value is taken from the
+    /// underlying channel and held by the receiver, so it isn't seen by the channel returned
+    /// by `as_crossbeam` or `as_flume`, and doesn't count towards the channel's capacity.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.peek_with(T::clone)
+    }
+
+    /// Like [`peek`](Receiver::peek), but inspects the value in place with `f`, so it needn't
+    /// be cloned.
+    pub fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.inner.peek_with(f)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { rx: self }
+    }
+
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { rx: self }
+    }
+
+    /// The underlying `crossbeam-channel` receiver, for use with `crossbeam_channel::select!`.
+    #[cfg(feature = "crossbeam")]
+    pub fn as_crossbeam(&self) -> &::crossbeam_channel::Receiver<T> {
+        self.inner.as_crossbeam()
+    }
+
+    /// Waits for the next value without blocking the thread, so the same subscription can be
+    /// consumed from async code.
+    #[cfg(feature = "flume")]
+    pub async fn recv_async(&self) -> Result<T, RecvError> {
+        self.inner.recv_async().await
+    }
+
+    /// The underlying `flume` receiver.
+    #[cfg(feature = "flume")]
+    pub fn as_flume(&self) -> &::flume::Receiver<T> {
+        self.inner.as_flume()
+    }
+}
+
+struct Unregister(Arc<AtomicUsize>);
+
+impl Drop for Unregister {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+pub struct Iter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+pub struct TryIter<'a, T> {
+    rx: &'a Receiver<T>,
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+}
+
+pub struct IntoIter<T> {
+    rx: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { rx: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn force_send_evicts_oldest_value() {
+        let (tx, rx) = bounded(2);
+
+        assert_eq!(tx.force_send(1).unwrap(), None);
+        assert_eq!(tx.force_send(2).unwrap(), None);
+        assert_eq!(tx.force_send(3).unwrap(), Some(1));
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn peek_leaves_the_value_to_be_received() {
+        let (tx, rx) = unbounded();
+        assert_eq!(rx.peek(), None);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+
+        assert_eq!(rx.peek(), Some(1));
+        assert_eq!(rx.peek_with(|value| value * 10), Some(10));
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.peek(), Some(2));
+        drop(tx);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(rx.peek(), None);
+    }
+
+    #[test]
+    fn blocked_sender_wakes_when_receiver_is_dropped() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+
+        let handle = thread::spawn(move || tx.send(2));
+
+        thread::sleep(Duration::from_millis(100));
+        drop(rx);
+
+        assert_eq!(handle.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn recv_timeout_times_out_then_disconnects() {
+        let (tx, rx) = unbounded::<u32>();
+
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        drop(tx);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn timeouts_too_long_to_represent_never_elapse() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+
+        let sender = thread::spawn(move || tx.send_timeout(2, Duration::MAX));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(rx.recv_timeout(Duration::MAX), Ok(1));
+        assert!(sender.join().unwrap().is_ok());
+        assert_eq!(rx.recv_timeout(Duration::MAX), Ok(2));
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[cfg(feature = "flume")]
+    #[tokio::test]
+    async fn recv_async_receives_value_sent_from_another_thread() {
+        let (tx, rx) = bounded(1);
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            tx.send(1).unwrap();
+        });
+
+        assert_eq!(rx.recv_async().await, Ok(1));
+        assert_eq!(rx.recv_async().await, Err(RecvError));
+    }
+}
@@ -0,0 +1,200 @@
+// Unbounded channels of the default backend, which are `std::sync::mpsc` channels. As they're
+// never full, nothing is ever evicted, so the channel itself needn't be inspected: the queued
+// values are counted alongside it, and the receiver holds a peeked value as the `crossbeam`
+// backend does.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+pub(super) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = mpsc::channel();
+    let side = Arc::new(Side {
+        sent: AtomicUsize::new(0),
+        received: AtomicUsize::new(0),
+        receiver: AtomicBool::new(true),
+    });
+    (
+        Sender {
+            tx,
+            side: side.clone(),
+        },
+        Receiver {
+            rx: Mutex::new(rx),
+            peeked: Mutex::new(None),
+            side,
+        },
+    )
+}
+
+struct Side {
+    // Counted before each send, so that `sent - received` never underflows.
+    sent: AtomicUsize,
+    // Counted as values leave the channel, including into the receiver's peeked slot.
+    received: AtomicUsize,
+    // Cleared when the receiver is dropped, which `mpsc::Sender` only reports on a send.
+    receiver: AtomicBool,
+}
+
+pub(crate) struct Sender<T> {
+    tx: mpsc::Sender<T>,
+    side: Arc<Side>,
+}
+
+impl<T> Sender<T> {
+    // Never blocks, as the channel is never full.
+    pub(crate) fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if self.is_disconnected() {
+            return Err(SendError(value));
+        }
+        self.side.sent.fetch_add(1, Ordering::AcqRel);
+        self.tx.send(value)
+    }
+
+    pub(crate) fn send_timeout(&self, value: T, _timeout: Duration) -> Result<(), TrySendError<T>> {
+        self.try_send(value)
+    }
+
+    pub(crate) fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.send(value)
+            .map_err(|SendError(value)| TrySendError::Disconnected(value))
+    }
+
+    pub(crate) fn force_send(&self, value: T) -> Result<Option<T>, SendError<T>> {
+        self.send(value).map(|()| None)
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        !self.side.receiver.load(Ordering::Acquire)
+    }
+
+    // The number of values queued for the receiver.
+    pub(crate) fn len(&self) -> usize {
+        let received = self.side.received.load(Ordering::Acquire);
+        self.side
+            .sent
+            .load(Ordering::Acquire)
+            .saturating_sub(received)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            side: self.side.clone(),
+        }
+    }
+}
+
+pub(super) struct Receiver<T> {
+    // Behind a mutex so that the receiver can be shared between threads like the others.
+    rx: Mutex<mpsc::Receiver<T>>,
+    peeked: Mutex<Option<T>>,
+    side: Arc<Side>,
+}
+
+impl<T> Receiver<T> {
+    // A value taken from the channel by `peek_with`, which is received before those still
+    // queued.
+    fn take_peeked(&self) -> Option<T> {
+        self.peeked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    fn received<E>(&self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_ok() {
+            self.side.received.fetch_add(1, Ordering::AcqRel);
+        }
+        result
+    }
+
+    fn lock(&self) -> MutexGuard<'_, mpsc::Receiver<T>> {
+        self.rx.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // The channel can't be inspected in place, so the next value is taken from it and held
+    // until it is received.
+    pub(super) fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let mut peeked = self.peeked.lock().unwrap_or_else(PoisonError::into_inner);
+        if peeked.is_none() {
+            *peeked = self.received(self.lock().try_recv()).ok();
+        }
+        peeked.as_ref().map(f)
+    }
+
+    pub(super) fn recv(&self) -> Result<T, RecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.received(self.lock().recv())
+    }
+
+    // Changes whenever a value is sent.
+    pub(super) fn version(&self) -> usize {
+        self.side.sent.load(Ordering::Acquire)
+    }
+
+    pub(super) fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.received(self.lock().try_recv())
+    }
+
+    pub(super) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        if let Some(value) = self.take_peeked() {
+            return Ok(value);
+        }
+        self.received(self.lock().recv_timeout(timeout))
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.side.receiver.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queued_values_are_counted_until_received() {
+        let (tx, rx) = unbounded();
+        tx.send(1).unwrap();
+        tx.clone().send(2).unwrap();
+        assert_eq!(tx.len(), 2);
+        assert_eq!(rx.version(), 2);
+
+        assert_eq!(rx.peek_with(|value| *value), Some(1));
+        assert_eq!(tx.len(), 1);
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(tx.len(), 0);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn dropping_either_half_disconnects_the_other() {
+        let (tx, rx) = unbounded();
+        let other = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        assert_eq!(rx.recv(), Ok(1));
+        drop(other);
+        assert_eq!(rx.recv(), Err(RecvError));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        let (tx, rx) = unbounded();
+        assert!(!tx.is_disconnected());
+        drop(rx);
+        assert!(tx.is_disconnected());
+        assert_eq!(tx.send(1), Err(SendError(1)));
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+        assert_eq!(tx.force_send(3), Err(SendError(3)));
+    }
+}
@@ -0,0 +1,386 @@
+// Bounded channels of the default backend: a queue behind a mutex, with condition variables
+// for blocked senders and receivers. `std::sync::mpsc::sync_channel` can't back them, since it
+// can't evict the oldest queued value for `Backpressure::DropOldest`, peek at queued values, or
+// count them for slow observer tracking. Being built on `crate::sync`, the queue also backs
+// unbounded channels under loom, which models it in `loom_tests`, and without the standard
+// library.
+use alloc::collections::VecDeque;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use super::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use crate::clock::Instant;
+use crate::sync::lock::PoisonError;
+use crate::sync::{Arc, AtomicUsize, Condvar, Mutex, MutexGuard};
+
+pub(super) fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    new(Some(capacity))
+}
+
+#[cfg(any(loom, not(feature = "std")))]
+pub(super) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    new(None)
+}
+
+fn new<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            capacity,
+            senders: 1,
+            receiver: true,
+        }),
+        ready: Condvar::new(),
+        space: Condvar::new(),
+        version: AtomicUsize::new(0),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // Signalled when a value is pushed or the last sender is dropped.
+    ready: Condvar,
+    // Signalled when a value is popped or the receiver is dropped.
+    space: Condvar,
+    // Bumped alongside each signal of `ready`, so a spinning receiver needn't lock `state`.
+    version: AtomicUsize,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn pushed(&self) {
+        self.version.fetch_add(1, Ordering::Release);
+        self.ready.notify_one();
+    }
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: Option<usize>,
+    senders: usize,
+    receiver: bool,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        matches!(self.capacity, Some(capacity) if self.queue.len() >= capacity)
+    }
+}
+
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    // Blocks until there is space in the channel.
+    pub(crate) fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        loop {
+            if !state.receiver {
+                return Err(SendError(value));
+            }
+            if !state.is_full() {
+                state.queue.push_back(value);
+                drop(state);
+                self.shared.pushed();
+                return Ok(());
+            }
+            state = self
+                .shared
+                .space
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    // Blocks until there is space in the channel, failing with `Full` if there is still none
+    // once `timeout` has elapsed. A timeout too long to represent as a deadline never elapses.
+    pub(crate) fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), TrySendError<T>> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self
+                .send(value)
+                .map_err(|SendError(value)| TrySendError::Disconnected(value));
+        };
+        let mut state = self.shared.lock();
+        loop {
+            if !state.receiver {
+                return Err(TrySendError::Disconnected(value));
+            }
+            if !state.is_full() {
+                state.queue.push_back(value);
+                drop(state);
+                self.shared.pushed();
+                return Ok(());
+            }
+            let remaining = match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Err(TrySendError::Full(value)),
+            };
+            state = self
+                .shared
+                .space
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    pub(crate) fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(TrySendError::Disconnected(value));
+        }
+        if state.is_full() {
+            return Err(TrySendError::Full(value));
+        }
+        state.queue.push_back(value);
+        drop(state);
+        self.shared.pushed();
+        Ok(())
+    }
+
+    // Never blocks: if the channel is full the oldest queued value is evicted and returned.
+    pub(crate) fn force_send(&self, value: T) -> Result<Option<T>, SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(SendError(value));
+        }
+        let evicted = if state.is_full() {
+            state.queue.pop_front()
+        } else {
+            None
+        };
+        state.queue.push_back(value);
+        drop(state);
+        self.shared.pushed();
+        Ok(evicted)
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        !self.shared.lock().receiver
+    }
+
+    // The number of values queued for the receiver.
+    pub(crate) fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.version.fetch_add(1, Ordering::Release);
+            self.shared.ready.notify_all();
+        }
+    }
+}
+
+pub(super) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub(super) fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(value) = state.queue.pop_front() {
+                drop(state);
+                self.shared.space.notify_one();
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+            state = self
+                .shared
+                .ready
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    pub(super) fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.shared.lock().queue.front().map(f)
+    }
+
+    // Changes whenever a value is sent or the last sender is dropped.
+    pub(super) fn version(&self) -> usize {
+        self.shared.version.load(Ordering::Acquire)
+    }
+
+    pub(super) fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.queue.pop_front() {
+            Some(value) => {
+                drop(state);
+                self.shared.space.notify_one();
+                Ok(value)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    // Like `recv`, but fails with `Timeout` once `timeout` has elapsed. A timeout too long to
+    // represent as a deadline never elapses.
+    pub(super) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self
+                .recv()
+                .map_err(|RecvError| RecvTimeoutError::Disconnected);
+        };
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(value) = state.queue.pop_front() {
+                drop(state);
+                self.shared.space.notify_one();
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Err(RecvTimeoutError::Timeout),
+            };
+            state = self
+                .shared
+                .ready
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let queue = {
+            let mut state = self.shared.lock();
+            state.receiver = false;
+            core::mem::take(&mut state.queue)
+        };
+        self.shared.space.notify_all();
+        drop(queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[cfg(os)]
+    #[test]
+    fn send_timeout_fails_when_full_until_space_is_freed() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+
+        assert_eq!(
+            tx.send_timeout(2, Duration::from_millis(10)),
+            Err(TrySendError::Full(2))
+        );
+        let receiver = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let first = rx.recv();
+            (first, rx.recv())
+        });
+        assert_eq!(tx.send_timeout(3, Duration::from_secs(10)), Ok(()));
+        assert_eq!(receiver.join().unwrap(), (Ok(1), Ok(3)));
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[test]
+    fn timeouts_elapse_at_once_without_a_clock() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+        assert_eq!(
+            tx.send_timeout(2, Duration::MAX),
+            Err(TrySendError::Full(2))
+        );
+        assert_eq!(rx.recv_timeout(Duration::MAX), Ok(1));
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Timeout)
+        );
+    }
+
+    #[test]
+    fn force_send_evicts_the_oldest_value_when_full() {
+        let (tx, rx) = bounded(2);
+        assert_eq!(tx.force_send(1), Ok(None));
+        assert_eq!(tx.force_send(2), Ok(None));
+        assert_eq!(tx.force_send(3), Ok(Some(1)));
+        assert_eq!(tx.len(), 2);
+
+        assert_eq!(rx.peek_with(|value| *value), Some(2));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn dropping_the_receiver_disconnects_senders_and_discards_values() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+        assert!(!tx.is_disconnected());
+        drop(rx);
+
+        assert!(tx.is_disconnected());
+        assert_eq!(tx.len(), 0);
+        assert_eq!(tx.send(2), Err(SendError(2)));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+        assert_eq!(
+            tx.send_timeout(4, Duration::from_millis(10)),
+            Err(TrySendError::Disconnected(4))
+        );
+        assert_eq!(tx.force_send(5), Err(SendError(5)));
+    }
+
+    #[test]
+    fn dropping_every_sender_disconnects_the_receiver_once_drained() {
+        let (tx, rx) = bounded(2);
+        let other = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        let receiver = thread::spawn(move || rx.recv());
+        thread::sleep(Duration::from_millis(50));
+        drop(other);
+        assert_eq!(receiver.join().unwrap(), Err(RecvError));
+
+        let (tx, rx) = bounded::<u32>(1);
+        assert_eq!(rx.version(), 0);
+        drop(tx);
+        // So that a spinning receiver stops spinning.
+        assert_eq!(rx.version(), 1);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
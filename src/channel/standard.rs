@@ -0,0 +1,141 @@
+// The default channel backend. Bounded channels are `queue`s, since `std::sync::mpsc` can't
+// evict their oldest value for `Backpressure::DropOldest`, and unbounded ones are
+// `std::sync::mpsc` channels, except under loom, which can only model the queue, and without
+// the standard library.
+use core::time::Duration;
+
+#[cfg(all(not(loom), feature = "std"))]
+use super::mpsc;
+use super::queue;
+use super::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+pub(super) fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = queue::bounded(capacity);
+    (Sender::Queue(tx), Receiver::Queue(rx))
+}
+
+#[cfg(all(not(loom), feature = "std"))]
+pub(super) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = mpsc::unbounded();
+    (Sender::Mpsc(tx), Receiver::Mpsc(rx))
+}
+
+#[cfg(any(loom, not(feature = "std")))]
+pub(super) fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = queue::unbounded();
+    (Sender::Queue(tx), Receiver::Queue(rx))
+}
+
+pub(crate) enum Sender<T> {
+    Queue(queue::Sender<T>),
+    #[cfg(all(not(loom), feature = "std"))]
+    Mpsc(mpsc::Sender<T>),
+}
+
+impl<T> Sender<T> {
+    pub(crate) fn send(&self, value: T) -> Result<(), SendError<T>> {
+        match self {
+            Sender::Queue(tx) => tx.send(value),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => tx.send(value),
+        }
+    }
+
+    pub(crate) fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), TrySendError<T>> {
+        match self {
+            Sender::Queue(tx) => tx.send_timeout(value, timeout),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => tx.send_timeout(value, timeout),
+        }
+    }
+
+    pub(crate) fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        match self {
+            Sender::Queue(tx) => tx.try_send(value),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => tx.try_send(value),
+        }
+    }
+
+    pub(crate) fn force_send(&self, value: T) -> Result<Option<T>, SendError<T>> {
+        match self {
+            Sender::Queue(tx) => tx.force_send(value),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => tx.force_send(value),
+        }
+    }
+
+    pub(crate) fn is_disconnected(&self) -> bool {
+        match self {
+            Sender::Queue(tx) => tx.is_disconnected(),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => tx.is_disconnected(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Sender::Queue(tx) => tx.len(),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => tx.len(),
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Sender::Queue(tx) => Sender::Queue(tx.clone()),
+            #[cfg(all(not(loom), feature = "std"))]
+            Sender::Mpsc(tx) => Sender::Mpsc(tx.clone()),
+        }
+    }
+}
+
+pub(super) enum Receiver<T> {
+    Queue(queue::Receiver<T>),
+    #[cfg(all(not(loom), feature = "std"))]
+    Mpsc(mpsc::Receiver<T>),
+}
+
+impl<T> Receiver<T> {
+    pub(super) fn recv(&self) -> Result<T, RecvError> {
+        match self {
+            Receiver::Queue(rx) => rx.recv(),
+            #[cfg(all(not(loom), feature = "std"))]
+            Receiver::Mpsc(rx) => rx.recv(),
+        }
+    }
+
+    pub(super) fn try_recv(&self) -> Result<T, TryRecvError> {
+        match self {
+            Receiver::Queue(rx) => rx.try_recv(),
+            #[cfg(all(not(loom), feature = "std"))]
+            Receiver::Mpsc(rx) => rx.try_recv(),
+        }
+    }
+
+    pub(super) fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        match self {
+            Receiver::Queue(rx) => rx.recv_timeout(timeout),
+            #[cfg(all(not(loom), feature = "std"))]
+            Receiver::Mpsc(rx) => rx.recv_timeout(timeout),
+        }
+    }
+
+    pub(super) fn peek_with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        match self {
+            Receiver::Queue(rx) => rx.peek_with(f),
+            #[cfg(all(not(loom), feature = "std"))]
+            Receiver::Mpsc(rx) => rx.peek_with(f),
+        }
+    }
+
+    pub(super) fn version(&self) -> usize {
+        match self {
+            Receiver::Queue(rx) => rx.version(),
+            #[cfg(all(not(loom), feature = "std"))]
+            Receiver::Mpsc(rx) => rx.version(),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+#[cfg(any(feature = "mock-clock", os))]
+use core::time::Duration;
+#[cfg(os)]
+use std::sync::mpsc::RecvTimeoutError;
+#[cfg(all(feature = "mock-clock", os))]
+use std::sync::mpsc::TryRecvError;
+#[cfg(feature = "mock-clock")]
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(feature = "std")]
+pub(crate) use std::time::{Instant, SystemTime};
+
+#[cfg(not(feature = "std"))]
+pub use self::unclocked::{Instant, SystemTime};
+
+#[cfg(os)]
+use crate::Receiver;
+
+// How often a wait on a mock clock rechecks whether its deadline has passed.
+#[cfg(all(feature = "mock-clock", os))]
+const MOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// The source of time for a map's timestamps, timeouts and throttling: real time, unless a
+// `MockClock` has been set.
+#[derive(Clone, Default)]
+pub(crate) struct Clock {
+    #[cfg(feature = "mock-clock")]
+    mock: Option<MockClock>,
+}
+
+impl Clock {
+    #[cfg(feature = "mock-clock")]
+    pub(crate) fn mock(clock: MockClock) -> Self {
+        Self { mock: Some(clock) }
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        #[cfg(feature = "mock-clock")]
+        if let Some(mock) = &self.mock {
+            return mock.now();
+        }
+        Instant::now()
+    }
+
+    pub(crate) fn system_now(&self) -> SystemTime {
+        #[cfg(feature = "mock-clock")]
+        if let Some(mock) = &self.mock {
+            return mock.system_now();
+        }
+        SystemTime::now()
+    }
+
+    // Receives from `rx`, failing once `timeout` has passed on this clock.
+    #[cfg(os)]
+    pub(crate) fn recv_timeout<T>(
+        &self,
+        rx: &Receiver<T>,
+        timeout: Duration,
+    ) -> Result<T, RecvTimeoutError> {
+        #[cfg(feature = "mock-clock")]
+        if let Some(mock) = &self.mock {
+            // A timeout too long to represent as a deadline never passes.
+            let deadline = mock.now().checked_add(timeout);
+            loop {
+                match rx.recv_timeout(MOCK_POLL_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout)
+                        if deadline.is_some_and(|deadline| mock.now() >= deadline) =>
+                    {
+                        // A value sent before the clock was advanced still arrives in time.
+                        return rx.try_recv().map_err(|error| match error {
+                            TryRecvError::Empty => RecvTimeoutError::Timeout,
+                            TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+                        });
+                    }
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    result => return result,
+                }
+            }
+        }
+        rx.recv_timeout(timeout)
+    }
+}
+
+/// A clock that only moves when [`advance`](MockClock::advance)d, for testing timeouts,
+/// throttling and timestamps without waiting in real time. Set it on a map with
+/// [`ObserverMap::set_clock`](crate::ObserverMap::set_clock). Clones share the same time.
+///
+/// Send timeouts on observer channels still use real time.
+#[cfg(feature = "mock-clock")]
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    start: Instant,
+    system_start: SystemTime,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+#[cfg(feature = "mock-clock")]
+impl MockClock {
+    /// Creates a clock stopped at the current time.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            system_start: SystemTime::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the clock forward by `by`, expiring any timeouts that have now passed.
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    pub fn system_now(&self) -> SystemTime {
+        self.system_start + self.elapsed()
+    }
+}
+
+#[cfg(feature = "mock-clock")]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Without the standard library there is no clock, so `HAS_CLOCK` is false and nothing is
+// timestamped. These stand in for the standard library's types where a map still handles
+// times, with every instant the same, so a timeout elapses as soon as it is checked.
+#[cfg(not(feature = "std"))]
+mod unclocked {
+    use core::ops::{Add, Sub};
+    use core::time::Duration;
+
+    /// Stands in for `std::time::Instant` without the `std` feature. There is no clock, so
+    /// every instant is the same.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Instant(());
+
+    impl Instant {
+        pub fn now() -> Self {
+            Self(())
+        }
+
+        pub fn elapsed(&self) -> Duration {
+            Duration::ZERO
+        }
+
+        pub fn checked_duration_since(&self, _earlier: Instant) -> Option<Duration> {
+            Some(Duration::ZERO)
+        }
+
+        pub fn saturating_duration_since(&self, _earlier: Instant) -> Duration {
+            Duration::ZERO
+        }
+
+        pub fn checked_add(&self, _duration: Duration) -> Option<Instant> {
+            Some(*self)
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Instant;
+
+        fn add(self, _duration: Duration) -> Instant {
+            self
+        }
+    }
+
+    impl Sub<Duration> for Instant {
+        type Output = Instant;
+
+        fn sub(self, _duration: Duration) -> Instant {
+            self
+        }
+    }
+
+    impl Sub for Instant {
+        type Output = Duration;
+
+        fn sub(self, _earlier: Instant) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    /// Stands in for `std::time::SystemTime` without the `std` feature. There is no clock, so
+    /// every time is the Unix epoch.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct SystemTime(());
+
+    impl SystemTime {
+        pub const UNIX_EPOCH: SystemTime = SystemTime(());
+
+        pub fn now() -> Self {
+            Self::UNIX_EPOCH
+        }
+    }
+
+    impl Add<Duration> for SystemTime {
+        type Output = SystemTime;
+
+        fn add(self, _duration: Duration) -> SystemTime {
+            self
+        }
+    }
+
+    impl Sub<Duration> for SystemTime {
+        type Output = SystemTime;
+
+        fn sub(self, _duration: Duration) -> SystemTime {
+            self
+        }
+    }
+}
@@ -0,0 +1,49 @@
+// The hash maps and sets the crate keeps keys in: the standard library's, or without it
+// `hashbrown`'s. Those are hashed with FNV-1a, as there is no source of random keys, so a
+// map whose keys an attacker chooses can be made to collide.
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{hash_map, HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use self::fnv::{hash_map, HashSet};
+#[cfg(not(feature = "std"))]
+pub use self::fnv::{FnvHasher, HashMap};
+
+#[cfg(not(feature = "std"))]
+mod fnv {
+    use core::hash::{BuildHasherDefault, Hasher};
+
+    /// The hash map that [snapshots](crate::ObserverMap::snapshot) are returned in without
+    /// the `std` feature.
+    pub type HashMap<K, V> = hashbrown::HashMap<K, V, BuildHasherDefault<FnvHasher>>;
+
+    pub(crate) type HashSet<T> = hashbrown::HashSet<T, BuildHasherDefault<FnvHasher>>;
+
+    pub(crate) mod hash_map {
+        pub(crate) use hashbrown::hash_map::{IntoIter, Iter};
+    }
+
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    /// The FNV-1a hasher of [`HashMap`]s without the `std` feature.
+    pub struct FnvHasher(u64);
+
+    impl Default for FnvHasher {
+        fn default() -> Self {
+            Self(OFFSET_BASIS)
+        }
+    }
+
+    impl Hasher for FnvHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for byte in bytes {
+                self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(PRIME);
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// A byte buffer kept LZ4-compressed whilst it is larger than a threshold, for maps holding
+/// large blobs. Clones share the stored bytes, so observers are notified without copying the
+/// buffer, and each reader decompresses with [`to_vec`](CompressedBytes::to_vec) when it
+/// needs the bytes.
+#[derive(Clone)]
+pub struct CompressedBytes(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Raw(Arc<[u8]>),
+    Lz4 { data: Arc<[u8]>, len: usize },
+}
+
+impl CompressedBytes {
+    /// The size above which [`new`](CompressedBytes::new) compresses a buffer.
+    pub const DEFAULT_THRESHOLD: usize = 1024;
+
+    pub fn new(bytes: &[u8]) -> Self {
+        Self::with_threshold(bytes, Self::DEFAULT_THRESHOLD)
+    }
+
+    /// Compresses `bytes` if they're larger than `threshold` and compression makes them
+    /// smaller.
+    pub fn with_threshold(bytes: &[u8], threshold: usize) -> Self {
+        if bytes.len() > threshold {
+            let data = lz4_flex::compress(bytes);
+            if data.len() < bytes.len() {
+                return Self(Repr::Lz4 {
+                    data: data.into(),
+                    len: bytes.len(),
+                });
+            }
+        }
+        Self(Repr::Raw(bytes.into()))
+    }
+
+    /// The original bytes, decompressed if need be.
+    pub fn to_vec(&self) -> Vec<u8> {
+        match &self.0 {
+            Repr::Raw(bytes) => bytes.to_vec(),
+            Repr::Lz4 { data, len } => {
+                lz4_flex::decompress(data, *len).expect("bytes were compressed on construction")
+            }
+        }
+    }
+
+    /// The length of the original bytes.
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            Repr::Raw(bytes) => bytes.len(),
+            Repr::Lz4 { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of bytes held in memory.
+    pub fn stored_len(&self) -> usize {
+        match &self.0 {
+            Repr::Raw(bytes) => bytes.len(),
+            Repr::Lz4 { data, .. } => data.len(),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.0, Repr::Lz4 { .. })
+    }
+}
+
+impl From<&[u8]> for CompressedBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<Vec<u8>> for CompressedBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(&bytes)
+    }
+}
+
+impl PartialEq for CompressedBytes {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Repr::Raw(a), Repr::Raw(b)) => a == b,
+            _ => self.len() == other.len() && self.to_vec() == other.to_vec(),
+        }
+    }
+}
+
+impl Eq for CompressedBytes {}
+
+impl fmt::Debug for CompressedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedBytes")
+            .field("len", &self.len())
+            .field("stored_len", &self.stored_len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObservableMap, ObserverMap};
+
+    #[test]
+    fn large_values_are_stored_compressed() {
+        let blob = b"order book level ".repeat(1000);
+        let mut map = ObserverMap::new();
+        let rx = map.observe("book").unwrap();
+        map.insert("book", CompressedBytes::from(blob.clone()))
+            .unwrap();
+        map.insert("small", CompressedBytes::from(&b"tiny"[..]))
+            .unwrap();
+
+        let stored = map.get("book").unwrap();
+        assert!(stored.is_compressed());
+        assert!(stored.stored_len() < blob.len() / 10);
+        assert_eq!(stored.to_vec(), blob);
+        assert_eq!(rx.recv().unwrap(), stored);
+        assert!(!map.get("small").unwrap().is_compressed());
+    }
+}
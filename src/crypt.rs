@@ -0,0 +1,68 @@
+use std::fmt;
+use std::io;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+const NONCE_LEN: usize = 12;
+
+/// A 256-bit key encrypting the files written by the persistence features with
+/// ChaCha20-Poly1305. Each file or log record is encrypted with a fresh random nonce, and
+/// reading one back with the wrong key, or after it has been tampered with, fails with
+/// [`io::ErrorKind::InvalidData`].
+#[derive(Clone)]
+pub struct EncryptionKey(ChaCha20Poly1305);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(ChaCha20Poly1305::new(&key.into()))
+    }
+
+    // The nonce followed by the ciphertext.
+    #[cfg_attr(not(any(feature = "persist", feature = "wal")), allow(dead_code))]
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .expect("plaintext is within ChaCha20-Poly1305's length limit");
+        [nonce.as_slice(), &ciphertext].concat()
+    }
+
+    #[cfg_attr(not(any(feature = "persist", feature = "wal")), allow(dead_code))]
+    pub(crate) fn decrypt(&self, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated ciphertext",
+            ));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "failed to decrypt"))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypts_only_with_the_same_key() {
+        let key = EncryptionKey::new([7; 32]);
+        let sealed = key.encrypt(b"secret");
+        assert!(!sealed.windows(6).any(|window| window == b"secret"));
+        assert_eq!(key.decrypt(&sealed).unwrap(), b"secret");
+
+        let other = EncryptionKey::new([8; 32]);
+        let error = other.decrypt(&sealed).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,115 @@
+use alloc::boxed::Box;
+use core::hash::Hash;
+
+use crate::channel::{self, Receiver, Sender};
+use crate::observer::{Delivery, Observer};
+use crate::{ObserveError, ObserverMap, ThreadSafeObserverMap};
+
+/// A value whose changes can be described more cheaply than by the whole new value, such as an
+/// order book whose updates touch a few price levels. Observers registered with
+/// [`ObserverMap::observe_deltas`] receive deltas rather than clones of each value.
+pub trait Diffable {
+    type Delta;
+
+    /// The change from `old` to `new`. With no previous value, the delta must describe all of
+    /// `new`.
+    fn delta(old: Option<&Self>, new: &Self) -> Self::Delta;
+}
+
+// An observer of deltas, whose type is erased so the map's observers only depend on `T`.
+pub(crate) trait DeltaObserver<T>: Send + Sync {
+    fn send(&self, old: Option<&T>, new: &T) -> Delivery;
+    fn receivers(&self) -> usize;
+    fn pending(&self) -> usize;
+}
+
+impl<T> DeltaObserver<T> for Sender<T::Delta>
+where
+    T: Diffable,
+    T::Delta: Send,
+{
+    fn send(&self, old: Option<&T>, new: &T) -> Delivery {
+        match Sender::send(self, T::delta(old, new)) {
+            Ok(()) => Delivery::Delivered,
+            Err(_) => Delivery::Disconnected,
+        }
+    }
+
+    fn receivers(&self) -> usize {
+        usize::from(!self.is_disconnected())
+    }
+
+    fn pending(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone + Diffable + 'static,
+    V::Delta: Send + 'static,
+{
+    /// Observes every update to `key` as the delta from the key's previous value, without
+    /// cloning the value. Deltas queue up without limit if the receiver falls behind.
+    pub fn observe_deltas(&mut self, key: K) -> Result<Receiver<V::Delta>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.add_observer(key, Observer::Delta(Box::new(tx)))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone + Diffable + 'static,
+    V::Delta: Send + 'static,
+{
+    /// See [`ObserverMap::observe_deltas`].
+    pub fn observe_deltas(&self, key: K) -> Result<Receiver<V::Delta>, ObserveError> {
+        self.write().observe_deltas(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Book(Vec<u32>);
+
+    impl Diffable for Book {
+        // The levels that changed, with their new sizes.
+        type Delta = Vec<(usize, u32)>;
+
+        fn delta(old: Option<&Self>, new: &Self) -> Self::Delta {
+            let old = old.map_or(&[][..], |old| &old.0);
+            new.0
+                .iter()
+                .enumerate()
+                .filter(|&(level, size)| old.get(level) != Some(size))
+                .map(|(level, &size)| (level, size))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn delta_observers_receive_changes_only() {
+        let mut map = ObserverMap::new();
+        let deltas = map.observe_deltas("btc").unwrap();
+        let values = map.observe_unbounded("btc").unwrap();
+
+        map.insert("btc", Book(vec![5, 3])).unwrap();
+        map.insert("btc", Book(vec![5, 4])).unwrap();
+
+        assert_eq!(
+            deltas.try_iter().collect::<Vec<_>>(),
+            vec![vec![(0, 5), (1, 3)], vec![(1, 4)]]
+        );
+        assert_eq!(values.try_iter().count(), 2);
+        drop(deltas);
+        map.insert("btc", Book(vec![6, 4])).unwrap();
+        assert_eq!(map.observer_count("btc"), 1);
+    }
+}
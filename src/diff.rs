@@ -0,0 +1,158 @@
+use crate::collections::HashMap;
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::{ObserverMap, ThreadSafeObserverMap};
+
+/// How one key differs between two maps, returned by [`ObserverMap::diff`]. Applying every
+/// entry to the first map makes it match the second.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiffEntry<K, V> {
+    /// The key only has a value in the second map.
+    Added(K, V),
+    /// The key only has a value in the first map.
+    Removed(K, V),
+    Changed {
+        key: K,
+        old: V,
+        new: V,
+    },
+}
+
+// The current values of a map or snapshot.
+trait Values<K, V> {
+    fn value(&self, key: &K) -> Option<&V>;
+    fn values<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a;
+}
+
+impl<K, V> Values<K, V> for HashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    fn value(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn values<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter()
+    }
+}
+
+impl<K, V> Values<K, V> for ObserverMap<K, V>
+where
+    K: Hash + Eq,
+{
+    fn value(&self, key: &K) -> Option<&V> {
+        self.read(self.hashmap.get(key)?)
+    }
+
+    fn values<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter()
+    }
+}
+
+fn diff<K, V>(old: &impl Values<K, V>, new: &impl Values<K, V>) -> Vec<DiffEntry<K, V>>
+where
+    K: Clone,
+    V: Clone + PartialEq,
+{
+    let changed = new
+        .values()
+        .filter_map(|(key, value)| match old.value(key) {
+            None => Some(DiffEntry::Added(key.clone(), value.clone())),
+            Some(old) if old != value => Some(DiffEntry::Changed {
+                key: key.clone(),
+                old: old.clone(),
+                new: value.clone(),
+            }),
+            Some(_) => None,
+        });
+    let removed = old
+        .values()
+        .filter(|(key, _)| new.value(key).is_none())
+        .map(|(key, value)| DiffEntry::Removed(key.clone(), value.clone()));
+    changed.chain(removed).collect()
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + PartialEq,
+{
+    /// The keys whose values differ between this map and `other`, for example to reconcile a
+    /// replica with its source after a disconnect. Keys without a value are treated as absent.
+    pub fn diff(&self, other: &ObserverMap<K, V>) -> Vec<DiffEntry<K, V>> {
+        diff(self, other)
+    }
+
+    /// Like [`diff`](ObserverMap::diff), against a [`snapshot`](ObserverMap::snapshot).
+    pub fn diff_snapshot(&self, snapshot: &HashMap<K, V>) -> Vec<DiffEntry<K, V>> {
+        diff(self, snapshot)
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + PartialEq,
+{
+    /// See [`ObserverMap::diff`]. `other` is copied before this map is read, so the two maps
+    /// are never locked at once.
+    pub fn diff(&self, other: &ThreadSafeObserverMap<K, V>) -> Vec<DiffEntry<K, V>> {
+        let snapshot = other.snapshot();
+        self.diff_snapshot(&snapshot)
+    }
+
+    /// See [`ObserverMap::diff_snapshot`].
+    pub fn diff_snapshot(&self, snapshot: &HashMap<K, V>) -> Vec<DiffEntry<K, V>> {
+        self.read().diff_snapshot(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn reports_added_removed_and_changed_keys() {
+        let mut replica = ObserverMap::new();
+        replica.insert("same", 1).unwrap();
+        replica.insert("changed", 2).unwrap();
+        replica.insert("removed", 3).unwrap();
+        let _rx = replica.observe("unset").unwrap();
+
+        let mut source = ObserverMap::new();
+        source.insert("same", 1).unwrap();
+        source.insert("changed", 20).unwrap();
+        source.insert("added", 4).unwrap();
+
+        let mut diff = replica.diff(&source);
+        diff.sort_by_key(|entry| format!("{entry:?}"));
+        assert_eq!(
+            diff,
+            vec![
+                DiffEntry::Added("added", 4),
+                DiffEntry::Changed {
+                    key: "changed",
+                    old: 2,
+                    new: 20
+                },
+                DiffEntry::Removed("removed", 3),
+            ]
+        );
+        assert!(source.diff_snapshot(&source.snapshot()).is_empty());
+    }
+}
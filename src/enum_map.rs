@@ -0,0 +1,422 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::marker::PhantomData;
+use core::time::Duration;
+#[cfg(os)]
+use std::time::Instant;
+
+#[cfg(os)]
+use crate::clock::Clock;
+use crate::registry::{self, Items, Registry};
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+#[cfg(os)]
+use crate::{throttle, wait_if_unset, WaitError};
+use crate::{
+    Attributed, Backpressure, DeliveryReport, InsertError, Item, Metrics, ObservableMap,
+    ObserveError, Receiver, WriteInfo,
+};
+
+/// A key of an [`EnumObserverMap`]: one of a small, fixed set of values, each with its own
+/// index. Implement it for a fieldless enum with [`enum_key!`](crate::enum_key).
+pub trait EnumKey: Copy {
+    /// The number of keys.
+    const COUNT: usize;
+
+    /// The key's index, less than [`COUNT`](EnumKey::COUNT) and unique to the key.
+    fn index(self) -> usize;
+}
+
+/// Declares a fieldless enum implementing [`EnumKey`], indexing each variant by its
+/// discriminant. The enum must derive `Clone` and `Copy`, and its variants mustn't have
+/// explicit discriminants.
+///
+/// ```
+/// observable_maps::enum_key! {
+///     #[derive(Clone, Copy, Debug)]
+///     pub enum Instrument {
+///         Btc,
+///         Eth,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! enum_key {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($variant:ident),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($variant),*
+        }
+
+        impl $crate::EnumKey for $name {
+            const COUNT: usize = [$(stringify!($variant)),*].len();
+
+            fn index(self) -> usize {
+                self as usize
+            }
+        }
+    };
+}
+
+/// An [`ObservableMap`] keyed by an [`EnumKey`], storing each key's value and observers in a
+/// fixed array indexed by the key, so reads and writes never hash. It supports the
+/// [`ObservableMap`] API only, without the extensions of [`ObserverMap`](crate::ObserverMap).
+pub struct EnumObserverMap<K, V> {
+    registry: Registry<Slots<K, V>>,
+}
+
+// Each key's item, at the key's index.
+struct Slots<K, V> {
+    items: Box<[Item<V>]>,
+    key: PhantomData<K>,
+}
+
+impl<K, V> Items for Slots<K, V>
+where
+    K: EnumKey,
+{
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: &K) -> Option<&Item<V>> {
+        Some(&self.items[key.index()])
+    }
+
+    fn get_or_insert(&mut self, key: K) -> &mut Item<V> {
+        &mut self.items[key.index()]
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Item<V>)) {
+        self.items.iter().for_each(f)
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(&mut Item<V>)) {
+        self.items.iter_mut().for_each(f)
+    }
+}
+
+impl<K, V> EnumObserverMap<K, V>
+where
+    K: EnumKey,
+{
+    pub fn new() -> Self {
+        let slots = Slots {
+            items: (0..K::COUNT).map(|_| Item::empty()).collect(),
+            key: PhantomData,
+        };
+        Self {
+            registry: Registry::new(slots),
+        }
+    }
+
+    /// See [`ObserverMap::close`](crate::ObserverMap::close).
+    pub fn close(&mut self) {
+        self.registry.close()
+    }
+
+    /// See [`ObserverMap::set_send_timeout`](crate::ObserverMap::set_send_timeout).
+    pub fn set_send_timeout(&mut self, timeout: Duration) {
+        self.registry.set_send_timeout(timeout)
+    }
+
+    /// See [`ObserverMap::set_metrics`](crate::ObserverMap::set_metrics).
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.registry.set_metrics(metrics)
+    }
+}
+
+impl<K, V> Default for EnumObserverMap<K, V>
+where
+    K: EnumKey,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> EnumObserverMap<K, V>
+where
+    K: EnumKey,
+    V: Clone,
+{
+    fn insert_checked(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<(), InsertError<V>> {
+        let report = self.registry.insert(key, value.clone(), group, writer)?;
+        registry::reject_if_full(report, value)
+    }
+
+    /// See [`ObserverMap::insert_as`](crate::ObserverMap::insert_as).
+    pub fn insert_as(&mut self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, Some(writer))
+    }
+
+    /// See [`ObserverMap::write_info`](crate::ObserverMap::write_info).
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        self.registry.write_info(&key)
+    }
+
+    /// See [`ObserverMap::observe_attributed`](crate::ObserverMap::observe_attributed).
+    pub fn observe_attributed(&mut self, key: K) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        self.registry.observe_attributed(key)
+    }
+}
+
+impl<K, V> ObservableMap<K, V> for EnumObserverMap<K, V>
+where
+    K: EnumKey,
+    V: Clone,
+{
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, None)
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        self.registry.insert(key, value, None, None)
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, Some(group), None)
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        self.registry.record_get();
+        self.registry.items.items[key.index()].value.clone()
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let current = match self.get(key) {
+            Some(value) => Ok(value),
+            None => Err(self.observe(key)?),
+        };
+        wait_if_unset(self, current, started)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.registry.is_closed()
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.registry.observer_count(&key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.registry.total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.registry.metrics()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_backpressure(key, backpressure)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_capacity(key, capacity)
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_unbounded(key)
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let current = self.get(key);
+        self.registry.observe_with_initial(key, current)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(&mut self, key: K, interval: Duration) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        let rx = self.observe_unbounded(key)?;
+        Ok(throttle(rx, interval, Clock::default()))
+    }
+
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_group(key, group)
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.on_update_with_priority(key, 0, callback)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.registry
+            .on_update_with_priority(key, priority, callback)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_priority(key, priority)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        let current = self.get(key);
+        self.registry.watch(key, current)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        self.registry.broadcast(key, capacity)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async_with_capacity(key, capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(os)]
+    use std::thread;
+
+    crate::enum_key! {
+        #[derive(Clone, Copy, Debug)]
+        enum Instrument {
+            Btc,
+            Eth,
+            Sol,
+        }
+    }
+
+    #[test]
+    fn stores_and_notifies_each_variant_separately() {
+        assert_eq!(Instrument::COUNT, 3);
+        let mut map = EnumObserverMap::new();
+        let btc = map.observe_unbounded(Instrument::Btc).unwrap();
+        let eth = map.observe(Instrument::Eth).unwrap();
+
+        map.insert(Instrument::Btc, 1).unwrap();
+        map.insert(Instrument::Btc, 2).unwrap();
+        map.insert(Instrument::Sol, 3).unwrap();
+
+        assert_eq!(map.get(Instrument::Btc), Some(2));
+        assert_eq!(map.get(Instrument::Eth), None);
+        assert_eq!(map.get(Instrument::Sol), Some(3));
+        assert_eq!(btc.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert!(eth.try_recv().is_err());
+        assert_eq!(map.observer_count(Instrument::Eth), 1);
+    }
+
+    #[test]
+    fn observers_see_inserts_in_priority_order_and_attributed() {
+        let mut map = EnumObserverMap::new();
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+        for priority in [1, 2] {
+            let order = order.clone();
+            map.on_update_with_priority(Instrument::Eth, priority, move |value: &u32| {
+                order.lock().unwrap().push((priority, *value))
+            })
+            .unwrap();
+        }
+        let attributed = map.observe_attributed(Instrument::Eth).unwrap();
+        map.insert(Instrument::Eth, 1).unwrap();
+        let initial = map.observe_with_initial(Instrument::Eth).unwrap();
+
+        map.insert_as(Instrument::Eth, 2, "feed").unwrap();
+
+        assert_eq!(*order.lock().unwrap(), vec![(2, 1), (1, 1), (2, 2), (1, 2)]);
+        assert_eq!(initial.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        let writers: Vec<_> = attributed
+            .try_iter()
+            .map(|update| update.writer.as_deref().map(str::to_string))
+            .collect();
+        assert_eq!(writers, vec![None, Some("feed".to_string())]);
+        assert_eq!(map.write_info(Instrument::Eth).unwrap().writes, 2);
+
+        drop(initial);
+        map.insert(Instrument::Eth, 3).unwrap();
+        assert_eq!(map.observer_count(Instrument::Eth), 3);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn waits_for_the_next_insert() {
+        let mut map = EnumObserverMap::new();
+        map.insert(Instrument::Btc, 1).unwrap();
+        assert_eq!(map.get_or_wait(Instrument::Btc), Ok(1));
+
+        let rx = map.observe(Instrument::Sol).unwrap();
+        let waiter = thread::spawn(move || rx.recv());
+        map.insert(Instrument::Sol, 2).unwrap();
+
+        assert_eq!(waiter.join().unwrap(), Ok(2));
+        assert_eq!(map.observer_count(Instrument::Sol), 0);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn close_disconnects_observers_and_rejects_writes() {
+        let mut map = EnumObserverMap::new();
+        map.insert(Instrument::Btc, 1).unwrap();
+        let rx = map.observe(Instrument::Eth).unwrap();
+
+        map.close();
+
+        assert!(map.is_closed());
+        assert!(rx.recv().is_err());
+        assert_eq!(map.total_observers(), 0);
+        assert_eq!(map.insert(Instrument::Btc, 2), Err(InsertError::Closed(2)));
+        assert_eq!(map.get(Instrument::Btc), Some(1));
+        assert_eq!(map.wait(Instrument::Eth), Err(WaitError::Closed));
+        assert_eq!(
+            map.observe(Instrument::Eth).unwrap_err(),
+            ObserveError::Closed
+        );
+    }
+}
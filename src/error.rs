@@ -0,0 +1,282 @@
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::error::Error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// An error returned when inserting a value. The rejected value can be recovered with
+/// [`InsertError::into_value`].
+#[derive(Clone, PartialEq, Eq)]
+pub enum InsertError<V> {
+    /// The map has been closed.
+    Closed(V),
+    /// An observer with [`Backpressure::Error`](crate::Backpressure::Error) had no room for
+    /// the value. The value is still stored and delivered to every other observer.
+    Full(V),
+    /// The value couldn't be written to durable storage, so it wasn't inserted.
+    #[cfg(feature = "std")]
+    Persist(V, io::ErrorKind),
+    /// The key had been written since the version the insert expected. The key's current
+    /// version is given alongside the value.
+    VersionConflict(V, u64),
+    /// An interceptor rejected the value for the given reason, so it wasn't inserted.
+    Invalid(V, Rejection),
+    /// The map is [write-once](crate::ObserverMap::write_once) and the key already has a
+    /// value, so it wasn't inserted.
+    AlreadySet(V),
+    /// The key is new and the map has no room for another key.
+    MapFull(V),
+}
+
+impl<V> InsertError<V> {
+    pub fn into_value(self) -> V {
+        match self {
+            InsertError::Closed(value)
+            | InsertError::Full(value)
+            | InsertError::VersionConflict(value, _)
+            | InsertError::Invalid(value, _)
+            | InsertError::AlreadySet(value)
+            | InsertError::MapFull(value) => value,
+            #[cfg(feature = "std")]
+            InsertError::Persist(value, _) => value,
+        }
+    }
+}
+
+impl<V> fmt::Debug for InsertError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::Closed(_) => f.write_str("Closed(..)"),
+            InsertError::Full(_) => f.write_str("Full(..)"),
+            #[cfg(feature = "std")]
+            InsertError::Persist(_, kind) => write!(f, "Persist(.., {:?})", kind),
+            InsertError::VersionConflict(_, version) => {
+                write!(f, "VersionConflict(.., {:?})", version)
+            }
+            InsertError::Invalid(_, reason) => write!(f, "Invalid(.., {:?})", reason),
+            InsertError::AlreadySet(_) => f.write_str("AlreadySet(..)"),
+            InsertError::MapFull(_) => f.write_str("MapFull(..)"),
+        }
+    }
+}
+
+impl<V> fmt::Display for InsertError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::Closed(_) => f.write_str("inserting into a closed map"),
+            InsertError::Full(_) => f.write_str("observer channel full"),
+            #[cfg(feature = "std")]
+            InsertError::Persist(_, kind) => write!(f, "failed to persist value: {}", kind),
+            InsertError::VersionConflict(_, version) => {
+                write!(
+                    f,
+                    "key was written concurrently, now at version {}",
+                    version
+                )
+            }
+            InsertError::Invalid(_, reason) => write!(f, "invalid value: {}", reason),
+            InsertError::AlreadySet(_) => f.write_str("key is write-once and already set"),
+            InsertError::MapFull(_) => f.write_str("no room in map for another key"),
+        }
+    }
+}
+
+impl<V> Error for InsertError<V> {}
+
+/// Why a value was rejected, carried by [`InsertError::Invalid`], such as the error returned
+/// by an [interceptor](crate::ObserverMap::add_interceptor). Rejections are equal if their
+/// messages are.
+#[derive(Clone)]
+pub struct Rejection(Arc<dyn Error + Send + Sync>);
+
+impl Rejection {
+    pub fn new(error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self(Arc::from(error.into()))
+    }
+
+    /// The error the value was rejected with, which can be downcast to its concrete type.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.0
+    }
+}
+
+impl PartialEq for Rejection {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for Rejection {}
+
+impl fmt::Debug for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for Rejection {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// An error returned when observing a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ObserveError {
+    /// The map has been closed.
+    Closed,
+    /// The key already has as many observers as the map's observer limit allows.
+    TooManyObservers,
+    /// The key is new and the map has no room for another key.
+    MapFull,
+}
+
+impl fmt::Display for ObserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObserveError::Closed => f.write_str("observing a closed map"),
+            ObserveError::TooManyObservers => f.write_str("too many observers of key"),
+            ObserveError::MapFull => f.write_str("no room in map for another key"),
+        }
+    }
+}
+
+impl Error for ObserveError {}
+
+/// An error returned when removing values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoveError {
+    /// The map has been closed.
+    Closed,
+    /// The removal couldn't be written to durable storage, so nothing was removed.
+    #[cfg(feature = "std")]
+    Persist(io::ErrorKind),
+}
+
+impl fmt::Display for RemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoveError::Closed => f.write_str("removing from a closed map"),
+            #[cfg(feature = "std")]
+            RemoveError::Persist(kind) => write!(f, "failed to persist removal: {}", kind),
+        }
+    }
+}
+
+impl Error for RemoveError {}
+
+/// An error returned by [`ObserverMap::apply_event`](crate::ObserverMap::apply_event).
+#[derive(Clone, PartialEq, Eq)]
+pub enum ApplyError<V> {
+    /// The change inserted a value, and inserting it failed.
+    Insert(InsertError<V>),
+    /// The change removed a value, and removing it failed.
+    Remove(RemoveError),
+}
+
+impl<V> From<InsertError<V>> for ApplyError<V> {
+    fn from(error: InsertError<V>) -> Self {
+        ApplyError::Insert(error)
+    }
+}
+
+impl<V> From<RemoveError> for ApplyError<V> {
+    fn from(error: RemoveError) -> Self {
+        ApplyError::Remove(error)
+    }
+}
+
+impl<V> fmt::Debug for ApplyError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::Insert(error) => write!(f, "Insert({:?})", error),
+            ApplyError::Remove(error) => write!(f, "Remove({:?})", error),
+        }
+    }
+}
+
+impl<V> fmt::Display for ApplyError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::Insert(error) => fmt::Display::fmt(error, f),
+            ApplyError::Remove(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+impl<V> Error for ApplyError<V> {}
+
+/// An error returned when waiting for a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitError {
+    /// The map has been closed.
+    Closed,
+    /// The observer was disconnected before a value was inserted.
+    Disconnected,
+    /// No value was inserted before the timeout elapsed.
+    Timeout,
+    /// The wait was cancelled before a value was inserted.
+    Cancelled,
+    /// The key already has as many observers as the map's observer limit allows.
+    TooManyObservers,
+    /// The key is new and the map has no room for another key.
+    MapFull,
+}
+
+impl From<ObserveError> for WaitError {
+    fn from(error: ObserveError) -> Self {
+        match error {
+            ObserveError::Closed => WaitError::Closed,
+            ObserveError::TooManyObservers => WaitError::TooManyObservers,
+            ObserveError::MapFull => WaitError::MapFull,
+        }
+    }
+}
+
+impl fmt::Display for WaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaitError::Closed => f.write_str("map closed before a value was inserted"),
+            WaitError::Disconnected => {
+                f.write_str("observer disconnected before a value was inserted")
+            }
+            WaitError::Timeout => f.write_str("timed out waiting for a value"),
+            WaitError::Cancelled => f.write_str("wait cancelled before a value was inserted"),
+            WaitError::TooManyObservers => f.write_str("too many observers of key"),
+            WaitError::MapFull => f.write_str("no room in map for another key"),
+        }
+    }
+}
+
+impl Error for WaitError {}
+
+/// An error returned when reading a map's journal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JournalError {
+    /// The map isn't keeping a journal.
+    Disabled,
+    /// The requested events have been evicted from the journal. `oldest` is the sequence
+    /// number of the oldest event still retained.
+    Truncated { oldest: u64 },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Disabled => f.write_str("map has no journal"),
+            JournalError::Truncated { oldest } => {
+                write!(f, "journal truncated, oldest retained event is {}", oldest)
+            }
+        }
+    }
+}
+
+impl Error for JournalError {}
@@ -0,0 +1,319 @@
+//! A C ABI for publishing and observing values from other languages in the same process.
+//!
+//! A map is created with [`om_new`] and freed with [`om_free`]. Keys are NUL-terminated UTF-8
+//! strings and values are byte buffers, copied in on insert and out into caller-provided
+//! buffers. Every function returns one of the `OM_` status codes, and a panic inside the
+//! library is reported as [`OM_PANICKED`] rather than unwinding into the caller. Build the
+//! crate as a `staticlib` or `cdylib` to link against it, for example with
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+use crate::{InsertError, ObservableMap, ObserveError, ThreadSafeObserverMap, WaitError};
+
+pub const OM_OK: c_int = 0;
+/// The key has no value.
+pub const OM_NOT_FOUND: c_int = 1;
+/// The map has been closed.
+pub const OM_CLOSED: c_int = 2;
+/// No value was inserted before the timeout elapsed.
+pub const OM_TIMEOUT: c_int = 3;
+/// The value didn't fit in the buffer. The value's length is written to `len`.
+pub const OM_BUFFER_TOO_SMALL: c_int = 4;
+/// A pointer was null, or a key wasn't valid UTF-8.
+pub const OM_INVALID_ARGUMENT: c_int = 5;
+/// The insert or observer was refused, for example by an observer limit.
+pub const OM_REFUSED: c_int = 6;
+/// The library panicked. The map is still usable, but the call may not have taken effect.
+pub const OM_PANICKED: c_int = 7;
+
+/// An opaque handle to a map of string keys to byte values, shared by every thread given it.
+pub struct OmMap(ThreadSafeObserverMap<String, Vec<u8>>);
+
+/// Called with each value inserted for an observed key, and the `user_data` given when it was
+/// registered. The value is only valid for the duration of the call.
+pub type OmCallback = extern "C" fn(user_data: *mut c_void, value: *const u8, len: usize);
+
+// The caller of `om_on_update` vouches that its user data can be used from any thread.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+/// Creates a map, or returns null if it can't be created.
+#[no_mangle]
+pub extern "C" fn om_new() -> *mut OmMap {
+    panic::catch_unwind(|| Box::into_raw(Box::new(OmMap(ThreadSafeObserverMap::new()))))
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Frees a map created with [`om_new`], closing it.
+///
+/// # Safety
+///
+/// `map` must be null or returned by [`om_new`], and not used again.
+#[no_mangle]
+pub unsafe extern "C" fn om_free(map: *mut OmMap) {
+    if !map.is_null() {
+        // Nothing can be reported to the caller, but a panic mustn't unwind into it.
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| Box::from_raw(map).0.close()));
+    }
+}
+
+/// Closes the map. See [`ObserverMap::close`](crate::ObserverMap::close).
+///
+/// # Safety
+///
+/// `map` must be null or a live map returned by [`om_new`].
+#[no_mangle]
+pub unsafe extern "C" fn om_close(map: *mut OmMap) -> c_int {
+    catch_panic(|| match map.as_ref() {
+        Some(map) => {
+            map.0.close();
+            OM_OK
+        }
+        None => OM_INVALID_ARGUMENT,
+    })
+}
+
+/// Inserts a copy of the `len` bytes at `value` for `key`, notifying its observers.
+///
+/// # Safety
+///
+/// `map` must be a live map returned by [`om_new`], `key` a NUL-terminated string, and
+/// `value` valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn om_insert(
+    map: *mut OmMap,
+    key: *const c_char,
+    value: *const u8,
+    len: usize,
+) -> c_int {
+    catch_panic(|| {
+        let (Some(map), Some(key)) = (map.as_ref(), read_key(key)) else {
+            return OM_INVALID_ARGUMENT;
+        };
+        if value.is_null() && len > 0 {
+            return OM_INVALID_ARGUMENT;
+        }
+        let value = match len {
+            0 => Vec::new(),
+            _ => slice::from_raw_parts(value, len).to_vec(),
+        };
+        match map.0.clone().insert(key, value) {
+            Ok(()) | Err(InsertError::Full(_)) => OM_OK,
+            Err(InsertError::Closed(_)) => OM_CLOSED,
+            Err(_) => OM_REFUSED,
+        }
+    })
+}
+
+/// Copies the current value of `key` into the `capacity` bytes at `buf`, writing its length
+/// to `len`.
+///
+/// # Safety
+///
+/// `map` must be a live map returned by [`om_new`], `key` a NUL-terminated string, `buf`
+/// valid for writes of `capacity` bytes, and `len` valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn om_get(
+    map: *mut OmMap,
+    key: *const c_char,
+    buf: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> c_int {
+    catch_panic(|| {
+        let (Some(map), Some(key)) = (map.as_ref(), read_key(key)) else {
+            return OM_INVALID_ARGUMENT;
+        };
+        match map.0.get(key) {
+            Some(value) => write_value(&value, buf, capacity, len),
+            None => OM_NOT_FOUND,
+        }
+    })
+}
+
+/// Waits up to `timeout_ms` milliseconds for the next value of `key`, copying it as
+/// [`om_get`] does. If the buffer is too small the value can still be read with [`om_get`].
+/// Pass `UINT64_MAX` to wait indefinitely.
+///
+/// # Safety
+///
+/// As for [`om_get`].
+#[no_mangle]
+pub unsafe extern "C" fn om_wait_timeout(
+    map: *mut OmMap,
+    key: *const c_char,
+    timeout_ms: u64,
+    buf: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> c_int {
+    catch_panic(|| {
+        let (Some(map), Some(key)) = (map.as_ref(), read_key(key)) else {
+            return OM_INVALID_ARGUMENT;
+        };
+        match map
+            .0
+            .clone()
+            .wait_timeout(key, Duration::from_millis(timeout_ms))
+        {
+            Ok(value) => write_value(&value, buf, capacity, len),
+            Err(WaitError::Timeout) => OM_TIMEOUT,
+            Err(WaitError::Closed) => OM_CLOSED,
+            Err(_) => OM_REFUSED,
+        }
+    })
+}
+
+/// Calls `callback` with every value subsequently inserted for `key`, until the map is closed.
+/// The callback runs on the inserting thread whilst the map is locked, so it must not call
+/// back into the map. Returns `OM_INVALID_ARGUMENT` if `callback` is NULL.
+///
+/// # Safety
+///
+/// `map` must be a live map returned by [`om_new`] and `key` a NUL-terminated string.
+/// `user_data` must be safe to pass to `callback` from any thread until the map is freed.
+#[no_mangle]
+pub unsafe extern "C" fn om_on_update(
+    map: *mut OmMap,
+    key: *const c_char,
+    callback: Option<OmCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    catch_panic(|| {
+        let (Some(map), Some(key), Some(callback)) = (map.as_ref(), read_key(key), callback) else {
+            return OM_INVALID_ARGUMENT;
+        };
+        let user_data = UserData(user_data);
+        let result = map.0.clone().on_update(key, move |value: &Vec<u8>| {
+            let user_data = &user_data;
+            callback(user_data.0, value.as_ptr(), value.len())
+        });
+        match result {
+            Ok(()) => OM_OK,
+            Err(ObserveError::Closed) => OM_CLOSED,
+            Err(_) => OM_REFUSED,
+        }
+    })
+}
+
+// Runs the body of an exported function, reporting a panic as `OM_PANICKED`, since unwinding
+// out of an `extern "C"` function aborts the process. The map recovers from a panic whilst it
+// was locked, so it's safe to keep using.
+fn catch_panic(body: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(OM_PANICKED)
+}
+
+unsafe fn read_key(key: *const c_char) -> Option<String> {
+    if key.is_null() {
+        return None;
+    }
+    CStr::from_ptr(key).to_str().ok().map(str::to_string)
+}
+
+unsafe fn write_value(value: &[u8], buf: *mut u8, capacity: usize, len: *mut usize) -> c_int {
+    if len.is_null() || (buf.is_null() && capacity > 0) {
+        return OM_INVALID_ARGUMENT;
+    }
+    *len = value.len();
+    if value.len() > capacity {
+        return OM_BUFFER_TOO_SMALL;
+    }
+    if !value.is_empty() {
+        ptr::copy_nonoverlapping(value.as_ptr(), buf, value.len());
+    }
+    OM_OK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    extern "C" fn count_bytes(user_data: *mut c_void, _value: *const u8, len: usize) {
+        let total = unsafe { &*(user_data as *const AtomicUsize) };
+        total.fetch_add(len, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn insert_get_and_observe_through_c_abi() {
+        let key = c"prices.btc".as_ptr();
+        let total = AtomicUsize::new(0);
+        let mut buf = [0u8; 4];
+        let mut len = 0;
+        unsafe {
+            let map = om_new();
+            assert_eq!(
+                om_get(map, key, buf.as_mut_ptr(), 4, &mut len),
+                OM_NOT_FOUND
+            );
+            let user_data = &total as *const AtomicUsize as *mut c_void;
+            assert_eq!(om_on_update(map, key, Some(count_bytes), user_data), OM_OK);
+
+            assert_eq!(om_insert(map, key, b"42".as_ptr(), 2), OM_OK);
+            assert_eq!(om_get(map, key, buf.as_mut_ptr(), 4, &mut len), OM_OK);
+            assert_eq!(&buf[..len], b"42");
+
+            assert_eq!(om_insert(map, key, b"12345".as_ptr(), 5), OM_OK);
+            assert_eq!(
+                om_get(map, key, buf.as_mut_ptr(), 4, &mut len),
+                OM_BUFFER_TOO_SMALL
+            );
+            assert_eq!(len, 5);
+            assert_eq!(
+                om_wait_timeout(map, key, 10, buf.as_mut_ptr(), 4, &mut len),
+                OM_TIMEOUT
+            );
+            om_free(map);
+        }
+        assert_eq!(total.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn wait_timeout_of_uint64_max_waits_indefinitely() {
+        let key = c"prices.btc";
+        let map = om_new();
+        assert!(!map.is_null());
+        let address = map as usize;
+        let inserter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            unsafe { om_insert(address as *mut OmMap, key.as_ptr(), b"42".as_ptr(), 2) }
+        });
+
+        let mut buf = [0u8; 4];
+        let mut len = 0;
+        unsafe {
+            assert_eq!(
+                om_wait_timeout(map, key.as_ptr(), u64::MAX, buf.as_mut_ptr(), 4, &mut len),
+                OM_OK
+            );
+            assert_eq!(&buf[..len], b"42");
+            assert_eq!(inserter.join().unwrap(), OM_OK);
+            om_free(map);
+        }
+    }
+
+    #[test]
+    fn null_callbacks_are_rejected() {
+        let map = om_new();
+        unsafe {
+            assert_eq!(
+                om_on_update(map, c"key".as_ptr(), None, ptr::null_mut()),
+                OM_INVALID_ARGUMENT
+            );
+            om_free(map);
+        }
+    }
+
+    #[test]
+    fn panics_are_reported_instead_of_unwinding_into_the_caller() {
+        assert_eq!(catch_panic(|| panic!("boom")), OM_PANICKED);
+        assert_eq!(catch_panic(|| OM_OK), OM_OK);
+    }
+}
@@ -0,0 +1,81 @@
+use alloc::boxed::Box;
+use core::hash::Hash;
+
+use crate::{
+    channel, Backpressure, ObserveError, Observer, ObserverMap, Receiver, Registration,
+    ThreadSafeObserverMap,
+};
+
+// Decides, from a key's previous and new values, whether an observer is sent the new value.
+pub(crate) type Filter<T> = Box<dyn Fn(Option<&T>, &T) -> bool + Send + Sync>;
+
+impl<T> Registration<T> {
+    pub(crate) fn accepts(&self, previous: Option<&T>, value: &T) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter(previous, value))
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// Observes the updates to `key` for which `predicate` returns `true`, given the key's
+    /// previous value, if any, and its new value. The predicate runs on the inserting thread
+    /// whilst the map is locked, so values it rejects are never sent or cloned, and the
+    /// receiver isn't woken for them. It should be quick and must not access the map.
+    ///
+    /// Values queue up without limit if the receiver falls behind.
+    pub fn observe_filtered<F>(&mut self, key: K, predicate: F) -> Result<Receiver<V>, ObserveError>
+    where
+        F: Fn(Option<&V>, &V) -> bool + Send + Sync + 'static,
+    {
+        let (tx, rx) = channel::unbounded();
+        self.register(
+            key,
+            Registration {
+                filter: Some(Box::new(predicate)),
+                ..Registration::new(Observer::stream(tx, Backpressure::Block))
+            },
+        )?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// See [`ObserverMap::observe_filtered`].
+    pub fn observe_filtered<F>(&self, key: K, predicate: F) -> Result<Receiver<V>, ObserveError>
+    where
+        F: Fn(Option<&V>, &V) -> bool + Send + Sync + 'static,
+    {
+        self.write().observe_filtered(key, predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn rejected_values_are_never_sent() {
+        let mut map = ObserverMap::new();
+        let crossings = map
+            .observe_filtered("px", |previous, value| {
+                previous.is_some_and(|previous| *previous < 100) && *value >= 100
+            })
+            .unwrap();
+        for px in [90, 101, 102, 95, 100] {
+            map.insert("px", px).unwrap();
+        }
+
+        assert_eq!(crossings.try_iter().collect::<Vec<_>>(), vec![101, 100]);
+        assert_eq!(map.observer_count("px"), 1);
+    }
+}
@@ -0,0 +1,132 @@
+use core::hash::Hash;
+
+use heapless::{Entry, FnvIndexMap, Vec};
+
+use crate::{InsertError, ObserveError};
+
+/// An observable map that never allocates, for microcontrollers and other targets without a
+/// heap. It holds at most `N` keys, which must be a power of two, and at most `OBS` observers
+/// per key. Observers are plain functions, run synchronously with each value inserted for
+/// their key, so any state they keep must live in statics.
+pub struct FixedObserverMap<K, V, const N: usize, const OBS: usize> {
+    slots: FnvIndexMap<K, Slot<V, OBS>, N>,
+    closed: bool,
+}
+
+struct Slot<V, const OBS: usize> {
+    value: Option<V>,
+    observers: Vec<fn(&V), OBS>,
+}
+
+impl<V, const OBS: usize> Slot<V, OBS> {
+    const fn new() -> Self {
+        Self {
+            value: None,
+            observers: Vec::new(),
+        }
+    }
+}
+
+impl<K, V, const N: usize, const OBS: usize> FixedObserverMap<K, V, N, OBS> {
+    pub const fn new() -> Self {
+        Self {
+            slots: FnvIndexMap::new(),
+            closed: false,
+        }
+    }
+
+    /// Closes the map, removing every observer. Subsequent inserts and observers fail.
+    pub fn close(&mut self) {
+        self.closed = true;
+        for slot in self.slots.values_mut() {
+            slot.observers.clear();
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+impl<K, V, const N: usize, const OBS: usize> FixedObserverMap<K, V, N, OBS>
+where
+    K: Hash + Eq,
+{
+    /// Stores `value` for `key`, then runs the key's observers with it. Fails with
+    /// [`InsertError::MapFull`] if the key is new and the map already holds `N` keys.
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        if self.closed {
+            return Err(InsertError::Closed(value));
+        }
+        let slot = match self.slots.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match entry.insert(Slot::new()) {
+                Ok(slot) => slot,
+                Err(_) => return Err(InsertError::MapFull(value)),
+            },
+        };
+        let value = slot.value.insert(value);
+        for observer in &slot.observers {
+            observer(value);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.slots.get(key)?.value.as_ref()
+    }
+
+    /// Runs `observer` with every value subsequently inserted for `key`. Fails with
+    /// [`ObserveError::TooManyObservers`] if the key already has `OBS` observers, or
+    /// [`ObserveError::MapFull`] if the key is new and the map already holds `N` keys.
+    pub fn observe(&mut self, key: K, observer: fn(&V)) -> Result<(), ObserveError> {
+        if self.closed {
+            return Err(ObserveError::Closed);
+        }
+        let slot = match self.slots.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry
+                .insert(Slot::new())
+                .map_err(|_| ObserveError::MapFull)?,
+        };
+        slot.observers
+            .push(observer)
+            .map_err(|_| ObserveError::TooManyObservers)
+    }
+
+    pub fn observer_count(&self, key: &K) -> usize {
+        self.slots.get(key).map_or(0, |slot| slot.observers.len())
+    }
+}
+
+impl<K, V, const N: usize, const OBS: usize> Default for FixedObserverMap<K, V, N, OBS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static LAST: AtomicU32 = AtomicU32::new(0);
+
+    #[test]
+    fn insert_and_observe_within_capacity() {
+        let mut map: FixedObserverMap<u8, u32, 2, 1> = FixedObserverMap::new();
+        map.observe(1, |value| LAST.store(*value, Ordering::Relaxed))
+            .unwrap();
+        assert_eq!(map.observe(1, |_| {}), Err(ObserveError::TooManyObservers));
+
+        map.insert(1, 10).unwrap();
+        map.insert(2, 20).unwrap();
+        assert_eq!(LAST.load(Ordering::Relaxed), 10);
+        assert_eq!(map.get(&2), Some(&20));
+
+        assert_eq!(map.insert(3, 30), Err(InsertError::MapFull(30)));
+        assert_eq!(map.observe(3, |_| {}), Err(ObserveError::MapFull));
+        map.insert(2, 21).unwrap();
+        assert_eq!(map.get(&2), Some(&21));
+    }
+}
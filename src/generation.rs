@@ -0,0 +1,188 @@
+use core::hash::Hash;
+use core::sync::atomic::Ordering;
+
+use crate::{
+    channel, sequence_change, Change, ObservableMap, ObserveError, Observer, ObserverMap, Receiver,
+    RemoveError, ThreadSafeObserverMap,
+};
+
+/// A value along with the generation of the map it was read from or inserted into, returned
+/// by [`ObserverMap::get_with_generation`] and
+/// [`ObserverMap::observe_with_generation`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Generational<V> {
+    pub value: V,
+    pub generation: u64,
+}
+
+impl<K, V> ObserverMap<K, V> {
+    /// The map's generation, which starts at 0 and increments whenever values are removed
+    /// other than by being overwritten: on [`remove`](ObserverMap::remove), on
+    /// [`clear`](ObserverMap::clear), on `restore`, when
+    /// [`expire_idle`](ObserverMap::expire_idle) removes values, and when the
+    /// [memory quota](ObserverMap::set_memory_quota) evicts them, once per insert. Consumers
+    /// caching assumptions about which keys exist can compare generations to detect that
+    /// they're stale. Inserts that evict nothing don't change it.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn next_generation(&mut self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// Removes every value, starting a new [`generation`](ObserverMap::generation). Each
+    /// removal is recorded in the journal and sent to event subscribers as a
+    /// [`Change::Removed`]. Observers stay registered and aren't notified, and no
+    /// [tombstones](ObserverMap::keep_tombstones) are kept, any already kept being discarded.
+    /// Fails if the map is closed or the clear can't be written to the map's write-ahead log,
+    /// in which case nothing is removed.
+    pub fn clear(&mut self) -> Result<(), RemoveError> {
+        if self.closed {
+            return Err(RemoveError::Closed);
+        }
+        #[cfg(feature = "wal")]
+        self.log_removal(crate::wal::Record::Clear)?;
+        let (seq, journal, subscribers) = (&mut self.seq, &mut self.journal, &mut self.subscribers);
+        self.hashmap.retain(|key, item| {
+            if item.value.take().is_some() {
+                sequence_change(seq, journal, subscribers, key, || Change::Removed);
+            }
+            !item.is_empty()
+        });
+        if let Some(quota) = &mut self.quota {
+            quota.clear();
+        }
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.clear();
+        }
+        self.next_generation();
+        Ok(())
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// The key's value along with the generation it was read in.
+    pub fn get_with_generation(&self, key: K) -> Generational<Option<V>> {
+        Generational {
+            value: self.get(key),
+            generation: self.generation(),
+        }
+    }
+
+    /// Observes every update to `key` along with the generation it was inserted in. Values
+    /// queue up without limit if the receiver falls behind.
+    pub fn observe_with_generation(
+        &mut self,
+        key: K,
+    ) -> Result<Receiver<Generational<V>>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        let generation = self.generation.clone();
+        self.add_observer(key, Observer::Generational(tx, generation))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V> {
+    /// See [`ObserverMap::generation`].
+    pub fn generation(&self) -> u64 {
+        self.read().generation()
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// See [`ObserverMap::clear`].
+    pub fn clear(&self) -> Result<(), RemoveError> {
+        self.write().clear()
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// See [`ObserverMap::get_with_generation`]. The value and generation are read under
+    /// one lock, so the value belongs to that generation.
+    pub fn get_with_generation(&self, key: K) -> Generational<Option<V>> {
+        self.read().get_with_generation(key)
+    }
+
+    /// See [`ObserverMap::observe_with_generation`].
+    pub fn observe_with_generation(
+        &self,
+        key: K,
+    ) -> Result<Receiver<Generational<V>>, ObserveError> {
+        self.write().observe_with_generation(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn structural_changes_start_a_new_generation() {
+        let mut map = ThreadSafeObserverMap::new();
+        let rx = map.observe_with_generation("a").unwrap();
+        map.insert("a", 1).unwrap();
+        map.insert("b", 2).unwrap();
+        assert_eq!(map.generation(), 0);
+
+        map.clear().unwrap();
+        assert_eq!(
+            map.get_with_generation("a"),
+            Generational {
+                value: None,
+                generation: 1
+            }
+        );
+        map.insert("a", 3).unwrap();
+        let received: Vec<_> = rx.try_iter().map(|g| (g.value, g.generation)).collect();
+        assert_eq!(received, vec![(1, 0), (3, 1)]);
+        assert_eq!(map.observer_count("a"), 1);
+        assert_eq!(map.get("b"), None);
+    }
+
+    #[test]
+    fn clearing_records_each_removal() {
+        let mut map = ObserverMap::new();
+        map.insert("a", 1).unwrap();
+        let events = map.events();
+        map.insert("b", 2).unwrap();
+        map.clear().unwrap();
+
+        let mut removed: Vec<_> = events
+            .try_iter()
+            .filter(|event| event.change == Change::Removed)
+            .map(|event| event.key)
+            .collect();
+        removed.sort();
+        assert_eq!(removed, vec!["a", "b"]);
+
+        let mut replica = ObserverMap::new();
+        replica.insert("a", 1).unwrap();
+        replica
+            .apply_event(crate::MapEvent {
+                seq: 5,
+                key: "a",
+                change: Change::Removed,
+            })
+            .unwrap();
+        assert_eq!(replica.get("a"), None);
+    }
+}
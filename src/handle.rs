@@ -0,0 +1,222 @@
+use crate::collections::hash_map;
+use crate::collections::HashMap;
+use alloc::vec::Vec;
+use core::hash::Hash;
+#[cfg(os)]
+use core::time::Duration;
+
+#[cfg(os)]
+use crate::WaitError;
+use crate::{
+    DeliveryReport, InsertError, ObservableMap, ObserveError, Receiver, ThreadSafeObserverMap,
+    WriteInfo,
+};
+
+/// The only handle able to insert into a map split with
+/// [`ThreadSafeObserverMap::split`]. It can't be cloned, so the writer is known at compile
+/// time; share it behind a lock to allow a few writers.
+pub struct Writer<K, V> {
+    map: ThreadSafeObserverMap<K, V>,
+}
+
+/// A handle that reads and observes a map split with [`ThreadSafeObserverMap::split`], but
+/// can't insert. Clones share the map.
+pub struct Reader<K, V> {
+    map: ThreadSafeObserverMap<K, V>,
+}
+
+// Another handle to the map, without requiring the keys and values to be `Clone`.
+fn share<K, V>(map: &ThreadSafeObserverMap<K, V>) -> ThreadSafeObserverMap<K, V> {
+    ThreadSafeObserverMap {
+        inner: map.inner.clone(),
+        leases: map.leases.clone(),
+    }
+}
+
+impl<K, V> Clone for Reader<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            map: share(&self.map),
+        }
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V> {
+    /// Splits the map into its only [`Writer`] and a [`Reader`] that can be cloned freely.
+    /// Taking the map by value ensures no handle that can insert remains, provided it hasn't
+    /// been cloned already.
+    pub fn split(self) -> (Writer<K, V>, Reader<K, V>) {
+        let reader = Reader { map: share(&self) };
+        (Writer { map: self }, reader)
+    }
+}
+
+impl<K, V> Writer<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObservableMap::insert`].
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.map.insert(key, value)
+    }
+
+    /// See [`ObservableMap::insert_reporting`].
+    pub fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        self.map.insert_reporting(key, value)
+    }
+
+    /// See [`ObservableMap::insert_for_group`].
+    pub fn insert_for_group(
+        &mut self,
+        key: K,
+        value: V,
+        group: &str,
+    ) -> Result<(), InsertError<V>> {
+        self.map.insert_for_group(key, value, group)
+    }
+
+    /// See [`ObserverMap::insert_as`](crate::ObserverMap::insert_as).
+    pub fn insert_as(&mut self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.map.insert_as(key, value, writer)
+    }
+
+    /// See [`ObserverMap::close`](crate::ObserverMap::close).
+    pub fn close(&self) {
+        self.map.close()
+    }
+
+    /// Another reader of the map.
+    pub fn reader(&self) -> Reader<K, V> {
+        Reader {
+            map: share(&self.map),
+        }
+    }
+}
+
+impl<K, V> Reader<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    pub fn get(&self, key: K) -> Option<V> {
+        self.map.get(key)
+    }
+
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        self.map.write_info(key)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.map.is_closed()
+    }
+
+    pub fn observer_count(&self, key: K) -> usize {
+        self.map.observer_count(key)
+    }
+
+    pub fn total_observers(&self) -> usize {
+        self.map.total_observers()
+    }
+
+    /// See [`ObservableMap::observe`].
+    pub fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe(key)
+    }
+
+    /// See [`ObservableMap::observe_unbounded`].
+    pub fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_unbounded(key)
+    }
+
+    /// See [`ObservableMap::observe_with_capacity`].
+    pub fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_with_capacity(key, capacity)
+    }
+
+    /// See [`ObservableMap::observe_with_initial`].
+    pub fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_with_initial(key)
+    }
+
+    /// See [`ObservableMap::on_update`].
+    pub fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.map.on_update(key, callback)
+    }
+
+    /// See [`ObservableMap::wait`].
+    #[cfg(os)]
+    pub fn wait(&mut self, key: K) -> Result<V, WaitError> {
+        self.map.wait(key)
+    }
+
+    /// See [`ObservableMap::wait_timeout`].
+    #[cfg(os)]
+    pub fn wait_timeout(&mut self, key: K, timeout: Duration) -> Result<V, WaitError> {
+        self.map.wait_timeout(key, timeout)
+    }
+
+    /// See [`ObservableMap::get_or_wait`].
+    #[cfg(os)]
+    pub fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        self.map.get_or_wait(key)
+    }
+}
+
+impl<K, V> Reader<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// See [`ThreadSafeObserverMap::snapshot`].
+    pub fn snapshot(&self) -> HashMap<K, V> {
+        self.map.snapshot()
+    }
+
+    /// See [`ThreadSafeObserverMap::keys_snapshot`].
+    pub fn keys_snapshot(&self) -> Vec<K> {
+        self.map.keys_snapshot()
+    }
+
+    /// See [`ThreadSafeObserverMap::iter_snapshot`].
+    pub fn iter_snapshot(&self) -> hash_map::IntoIter<K, V> {
+        self.map.iter_snapshot()
+    }
+}
+
+#[cfg(all(test, os))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn readers_observe_the_writers_inserts() {
+        let (mut writer, mut reader) = ThreadSafeObserverMap::new().split();
+        let rx = reader.observe_unbounded("a".to_string()).unwrap();
+
+        let mut other = writer.reader();
+        let waiter = thread::spawn(move || other.get_or_wait("b".to_string()));
+        writer.insert("a".to_string(), 1).unwrap();
+        writer.insert_as("b".to_string(), 2, "feed").unwrap();
+
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(waiter.join().unwrap(), Ok(2));
+        assert_eq!(
+            reader
+                .write_info("b".to_string())
+                .unwrap()
+                .last_writer
+                .as_deref(),
+            Some("feed")
+        );
+        writer.close();
+        assert!(reader.clone().is_closed());
+    }
+}
@@ -0,0 +1,237 @@
+use core::hash::Hash;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use crate::clock::Instant;
+
+use crate::clock::Clock;
+use crate::{sequence_change, Change, Item, ObserverMap, ThreadSafeObserverMap, HAS_CLOCK};
+
+// Expires values that haven't been read or written within `timeout`. Each item's last access
+// is stored atomically, as nanoseconds since `epoch`, so reads record it without locking the
+// map for writing.
+pub(crate) struct IdleExpiry {
+    timeout: Duration,
+    epoch: Instant,
+    // When expired values were last removed.
+    swept: Instant,
+}
+
+impl IdleExpiry {
+    fn since_epoch(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.epoch).as_nanos() as u64
+    }
+}
+
+impl<T> Item<T> {
+    // Every item with a value has extras once idle expiry is enabled.
+    fn touch(&self, idle: &IdleExpiry, now: Instant) {
+        if let Some(extras) = &self.extras {
+            extras
+                .accessed
+                .fetch_max(idle.since_epoch(now), Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn is_idle(&self, idle: &IdleExpiry, now: Instant) -> bool {
+        let accessed = self
+            .extras
+            .as_ref()
+            .map_or(0, |extras| extras.accessed.load(Ordering::Relaxed));
+        idle.since_epoch(now).saturating_sub(accessed) >= idle.timeout.as_nanos() as u64
+    }
+
+    // Records a write of the item, if idle expiry is enabled.
+    pub(crate) fn written(&mut self, idle: Option<&IdleExpiry>, clock: &Clock) {
+        if let Some(idle) = idle {
+            self.extras_mut();
+            self.touch(idle, clock.now());
+        }
+    }
+}
+
+impl<K, V> ObserverMap<K, V> {
+    /// Expires each key's value once it hasn't been read or written for `timeout`. Any read
+    /// that returns the value counts, including snapshots, iteration and serialization.
+    /// Expired values read as `None` everywhere, and are removed by the first insert a
+    /// `timeout` after the last removal, or by [`expire_idle`](ObserverMap::expire_idle).
+    /// Observers of expired keys stay registered.
+    ///
+    /// Reads record their access without locking a [`ThreadSafeObserverMap`] for writing.
+    /// Values aren't expired on `wasm32`, which has no clock, or without the `std` feature.
+    pub fn expire_after_idle(&mut self, timeout: Duration) {
+        if !HAS_CLOCK {
+            return;
+        }
+        let now = self.clock.now();
+        let idle = IdleExpiry {
+            timeout,
+            epoch: now,
+            swept: now,
+        };
+        for item in self.hashmap.values_mut() {
+            if item.value.is_some() {
+                item.written(Some(&idle), &self.clock);
+            }
+        }
+        self.idle = Some(idle);
+    }
+
+    // The item's value, unless it has expired, recording the read.
+    pub(crate) fn read<'a>(&self, item: &'a Item<V>) -> Option<&'a V> {
+        let value = item.value.as_ref()?;
+        if let Some(idle) = &self.idle {
+            let now = self.clock.now();
+            if item.is_idle(idle, now) {
+                return None;
+            }
+            item.touch(idle, now);
+        }
+        Some(value)
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// Removes the values that have expired, returning how many were removed. Keys left
+    /// without observers are removed too. Each removal is recorded in the journal and sent to
+    /// event subscribers as [`Change::Evicted`]. A value whose removal can't be written to the
+    /// map's write-ahead log is kept, still hidden from reads, until a later sweep.
+    pub fn expire_idle(&mut self) -> usize {
+        let Some(idle) = &mut self.idle else {
+            return 0;
+        };
+        let now = self.clock.now();
+        idle.swept = now;
+        let mut expired = 0;
+        let quota = &mut self.quota;
+        let (seq, journal, subscribers) = (&mut self.seq, &mut self.journal, &mut self.subscribers);
+        #[cfg(feature = "wal")]
+        let wal = &mut self.wal;
+        self.hashmap.retain(|key, item| {
+            if item.value.is_some() && item.is_idle(idle, now) {
+                #[cfg(feature = "wal")]
+                if let Some(wal) = wal {
+                    if wal.append(crate::wal::Record::Remove(key)).is_err() {
+                        return true;
+                    }
+                }
+                item.value = None;
+                expired += 1;
+                if let Some(quota) = quota {
+                    quota.removed(key);
+                }
+                sequence_change(seq, journal, subscribers, key, || Change::Evicted);
+            }
+            !item.is_empty()
+        });
+        if expired > 0 {
+            self.next_generation();
+        }
+        expired
+    }
+
+    // Removes expired values if a timeout has passed since they were last removed.
+    pub(crate) fn expire_idle_if_due(&mut self) {
+        let due = self
+            .idle
+            .as_ref()
+            .is_some_and(|idle| self.clock.now() - idle.swept >= idle.timeout);
+        if due {
+            self.expire_idle();
+        }
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// See [`ObserverMap::expire_after_idle`].
+    pub fn expire_after_idle(&self, timeout: Duration) {
+        self.write().expire_after_idle(timeout)
+    }
+
+    /// See [`ObserverMap::expire_idle`].
+    pub fn expire_idle(&self) -> usize {
+        self.write().expire_idle()
+    }
+}
+
+#[cfg(all(test, feature = "mock-clock"))]
+mod tests {
+    use super::*;
+    use crate::collections::HashMap;
+
+    use crate::{MockClock, ObservableMap};
+
+    #[test]
+    fn values_expire_once_idle() {
+        let clock = MockClock::new();
+        let mut map = ObserverMap::builder()
+            .clock(clock.clone())
+            .expire_after_idle(Duration::from_secs(10))
+            .build();
+        map.insert("read", 1).unwrap();
+        map.insert("written", 2).unwrap();
+        map.insert("idle", 3).unwrap();
+        let rx = map.observe_unbounded("idle").unwrap();
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(map.get("read"), Some(1));
+        map.insert("written", 4).unwrap();
+        clock.advance(Duration::from_secs(6));
+
+        assert_eq!(map.get("read"), Some(1));
+        assert_eq!(map.get("written"), Some(4));
+        assert_eq!(map.get("idle"), None);
+        assert_eq!(map.expire_idle(), 1);
+        assert_eq!(map.observer_count("idle"), 1);
+
+        clock.advance(Duration::from_secs(10));
+        map.insert("idle", 5).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![5]);
+        assert_eq!(map.get("read"), None);
+        assert_eq!(map.hashmap.len(), 1);
+    }
+
+    #[test]
+    fn expired_values_are_hidden_from_every_read_and_journalled() {
+        let clock = MockClock::new();
+        let mut map = ObserverMap::builder()
+            .clock(clock.clone())
+            .expire_after_idle(Duration::from_secs(10))
+            .build();
+        let events = map.events();
+        map.insert("idle", 1).unwrap();
+        map.insert_reporting("reported", 2).unwrap();
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(map["reported"], 2);
+        clock.advance(Duration::from_secs(6));
+
+        assert_eq!(map.snapshot(), HashMap::from([("reported", 2)]));
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&"reported", &2)]);
+        assert_eq!(map.get_versioned("idle"), None);
+        assert_eq!(map.expire_idle(), 1);
+        let changes = events
+            .try_iter()
+            .map(|event| (event.key, event.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("idle", Change::Inserted(1)),
+                ("reported", Change::Inserted(2)),
+                ("idle", Change::Evicted),
+            ]
+        );
+
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(map.into_iter().count(), 0);
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(os)]
+use std::time::Instant;
+
+#[cfg(os)]
+use crate::clock::Clock;
+use crate::registry::{self, Items, Registry};
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+#[cfg(os)]
+use crate::{throttle, wait_if_unset, WaitError};
+use crate::{
+    Attributed, Backpressure, DeliveryReport, InsertError, Item, Metrics, ObservableMap,
+    ObserveError, Receiver, WriteInfo,
+};
+
+/// An [`ObservableMap`] whose values are stored in a persistent hash array mapped trie from
+/// [`im`](https://docs.rs/im), which shares structure between versions of the map. A
+/// [`snapshot`](ImObserverMap::snapshot) is therefore O(1) however large the map is, and
+/// later inserts copy only the nodes they change, so analytics threads can take frequent
+/// consistent snapshots of a large map without cloning every value. Observers behave as they
+/// do for [`ObserverMap`](crate::ObserverMap).
+pub struct ImObserverMap<K, V> {
+    values: im::HashMap<K, V>,
+    registry: Registry<Observed<K, V>>,
+}
+
+// Each key's observers and write info. The items never hold the values.
+struct Observed<K, V>(HashMap<K, Item<V>>);
+
+impl<K, V> Items for Observed<K, V>
+where
+    K: Hash + Eq,
+{
+    type Key = K;
+    type Value = V;
+
+    const KEEPS_VALUES: bool = false;
+
+    fn get(&self, key: &K) -> Option<&Item<V>> {
+        self.0.get(key)
+    }
+
+    fn get_or_insert(&mut self, key: K) -> &mut Item<V> {
+        self.0.get_or_insert(key)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Item<V>)) {
+        self.0.for_each(f)
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(&mut Item<V>)) {
+        self.0.for_each_mut(f)
+    }
+}
+
+impl<K, V> ImObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            values: im::HashMap::new(),
+            registry: Registry::new(Observed(HashMap::new())),
+        }
+    }
+
+    /// Every key's current value, sharing the map's storage until either is modified.
+    pub fn snapshot(&self) -> im::HashMap<K, V> {
+        self.values.clone()
+    }
+
+    /// See [`ObserverMap::close`](crate::ObserverMap::close).
+    pub fn close(&mut self) {
+        self.registry.close()
+    }
+
+    /// See [`ObserverMap::set_send_timeout`](crate::ObserverMap::set_send_timeout).
+    pub fn set_send_timeout(&mut self, timeout: Duration) {
+        self.registry.set_send_timeout(timeout)
+    }
+
+    /// See [`ObserverMap::set_metrics`](crate::ObserverMap::set_metrics).
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.registry.set_metrics(metrics)
+    }
+
+    fn insert_notifying(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<DeliveryReport, InsertError<V>> {
+        let value = self.registry.check_open(value)?;
+        self.values.insert(key.clone(), value.clone());
+        Ok(self.registry.update(key, value, group, writer))
+    }
+
+    fn insert_checked(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<(), InsertError<V>> {
+        let report = self.insert_notifying(key, value.clone(), group, writer)?;
+        registry::reject_if_full(report, value)
+    }
+
+    /// See [`ObserverMap::insert_as`](crate::ObserverMap::insert_as).
+    pub fn insert_as(&mut self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, Some(writer))
+    }
+
+    /// See [`ObserverMap::write_info`](crate::ObserverMap::write_info).
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        self.registry.write_info(&key)
+    }
+
+    /// See [`ObserverMap::observe_attributed`](crate::ObserverMap::observe_attributed).
+    pub fn observe_attributed(&mut self, key: K) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        self.registry.observe_attributed(key)
+    }
+}
+
+impl<K, V> ObservableMap<K, V> for ImObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, None)
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        self.registry.insert(key, value, None, None)
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, Some(group), None)
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        self.registry.record_get();
+        self.values.get(&key).cloned()
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let current = match self.get(key.clone()) {
+            Some(value) => Ok(value),
+            None => Err(self.observe(key)?),
+        };
+        wait_if_unset(self, current, started)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.registry.is_closed()
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.registry.observer_count(&key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.registry.total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.registry.metrics()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_backpressure(key, backpressure)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_capacity(key, capacity)
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_unbounded(key)
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let current = self.get(key.clone());
+        self.registry.observe_with_initial(key, current)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(&mut self, key: K, interval: Duration) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        let rx = self.observe_unbounded(key)?;
+        Ok(throttle(rx, interval, Clock::default()))
+    }
+
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_group(key, group)
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.on_update_with_priority(key, 0, callback)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.registry
+            .on_update_with_priority(key, priority, callback)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_priority(key, priority)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        let current = self.get(key.clone());
+        self.registry.watch(key, current)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        self.registry.broadcast(key, capacity)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async_with_capacity(key, capacity)
+    }
+}
+
+impl<K, V> Default for ImObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_are_unaffected_by_later_inserts() {
+        let mut map = ImObserverMap::new();
+        let rx = map.observe_unbounded(0).unwrap();
+        for key in 0..1000 {
+            map.insert(key, key * 10).unwrap();
+        }
+        let snapshot = map.snapshot();
+
+        map.insert(0, 1).unwrap();
+        map.insert(1000, 1).unwrap();
+        assert_eq!(snapshot.len(), 1000);
+        assert_eq!(snapshot.get(&0), Some(&0));
+        assert_eq!(snapshot.get(&1000), None);
+        assert_eq!(map.get(0), Some(1));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![0, 1]);
+        assert!(map
+            .registry
+            .items
+            .0
+            .values()
+            .all(|item| item.value.is_none()));
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn snapshots_are_isolated_from_the_map_in_both_directions() {
+        let mut map = ImObserverMap::new();
+        for key in 0..100 {
+            map.insert(key, 0).unwrap();
+        }
+        let rx = map.observe_unbounded(0).unwrap();
+        let snapshot = map.snapshot();
+        let reader = std::thread::spawn(move || {
+            let mut snapshot = snapshot;
+            let sum: i32 = snapshot.values().sum();
+            snapshot.insert(0, -1);
+            (sum, snapshot)
+        });
+
+        for key in 0..100 {
+            map.insert(key, 1).unwrap();
+        }
+
+        let (sum, snapshot) = reader.join().unwrap();
+        assert_eq!(sum, 0);
+        assert_eq!(snapshot.get(&0), Some(&-1));
+        assert_eq!(snapshot.get(&1), Some(&0));
+        assert_eq!(map.get(0), Some(1));
+        assert_eq!(map.snapshot().values().sum::<i32>(), 100);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
+}
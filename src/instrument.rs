@@ -0,0 +1,41 @@
+use std::fmt;
+
+pub(crate) type KeyFormatter<K> = Box<dyn Fn(&K) -> String + Send + Sync>;
+
+// Displays a key in spans and events using the map's formatter, or as `_` without one.
+pub(crate) struct DisplayKey<'a, K> {
+    pub(crate) key: &'a K,
+    pub(crate) formatter: Option<&'a KeyFormatter<K>>,
+}
+
+impl<K> fmt::Display for DisplayKey<'_, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.formatter {
+            Some(formatter) => f.write_str(&formatter(self.key)),
+            None => f.write_str("_"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_key_with_formatter() {
+        let formatter: KeyFormatter<u32> = Box::new(|key| format!("key-{}", key));
+        let key = 7;
+
+        let without = DisplayKey {
+            key: &key,
+            formatter: None,
+        };
+        let with = DisplayKey {
+            key: &key,
+            formatter: Some(&formatter),
+        };
+
+        assert_eq!(without.to_string(), "_");
+        assert_eq!(with.to_string(), "key-7");
+    }
+}
@@ -0,0 +1,124 @@
+use crate::clock::Instant;
+use crate::collections::hash_map;
+use core::hash::Hash;
+
+use crate::idle::IdleExpiry;
+use crate::{Item, ObserverMap, ThreadSafeObserverMap};
+
+/// An iterator over the keys and values of an [`ObserverMap`], in arbitrary order, taking
+/// them out of the map. Keys without a value, or whose value has expired, are skipped, and
+/// observers are dropped with the map.
+pub struct IntoIter<K, V> {
+    items: hash_map::IntoIter<K, Item<V>>,
+    // The map's idle expiry, if any, and when the iterator was created.
+    expiry: Option<(IdleExpiry, Instant)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let expiry = &self.expiry;
+        self.items.find_map(|(key, item)| match expiry {
+            Some((idle, now)) if item.is_idle(idle, *now) => None,
+            _ => Some((key, item.value?)),
+        })
+    }
+}
+
+/// An iterator over references to the keys and values of an [`ObserverMap`], in arbitrary
+/// order. Keys without a value, or whose value has expired, are skipped.
+pub struct Iter<'a, K, V> {
+    items: hash_map::Iter<'a, K, Item<V>>,
+    map: &'a ObserverMap<K, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let map = self.map;
+        self.items
+            .find_map(|(key, item)| Some((key, map.read(item)?)))
+    }
+}
+
+impl<K, V> ObserverMap<K, V> {
+    /// The keys that have values, with their values, in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            items: self.hashmap.iter(),
+            map: self,
+        }
+    }
+}
+
+impl<K, V> IntoIterator for ObserverMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(mut self) -> IntoIter<K, V> {
+        let now = self.idle.as_ref().map(|_| self.clock.now());
+        IntoIter {
+            expiry: self.idle.take().zip(now),
+            items: self.hashmap.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a ObserverMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// See [`ThreadSafeObserverMap::iter_snapshot`].
+impl<K, V> IntoIterator for &ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+    type IntoIter = hash_map::IntoIter<K, V>;
+
+    fn into_iter(self) -> hash_map::IntoIter<K, V> {
+        self.iter_snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn iterates_over_keys_with_values() {
+        let mut map = ObserverMap::new();
+        map.insert("a", 1).unwrap();
+        map.insert("b", 2).unwrap();
+        let _rx = map.observe("unset").unwrap();
+
+        let mut borrowed: Vec<_> = (&map).into_iter().map(|(&k, &v)| (k, v)).collect();
+        borrowed.sort();
+        assert_eq!(borrowed, vec![("a", 1), ("b", 2)]);
+        assert_eq!(map.iter().map(|(_, v)| v).sum::<i32>(), 3);
+
+        let mut shared = ThreadSafeObserverMap::new();
+        shared.insert("a", 1).unwrap();
+        shared.insert("b", 2).unwrap();
+        let mut total = 0;
+        for (_, value) in &shared {
+            // The map isn't locked whilst the body runs.
+            shared.insert("c", 3).unwrap();
+            total += value;
+        }
+        assert_eq!(total, 3);
+
+        let mut owned: Vec<_> = map.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, vec![("a", 1), ("b", 2)]);
+    }
+}
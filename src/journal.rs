@@ -0,0 +1,174 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::observer::{Delivery, Observer};
+use crate::JournalError;
+
+/// A change to one key of a map, numbered in the order the map applied it. With the `serde`
+/// feature, events can be serialized to replicate a map across processes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapEvent<K, V> {
+    pub seq: u64,
+    pub key: K,
+    pub change: Change<V>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Change<V> {
+    Inserted(V),
+    /// The key's value was evicted to keep the map within its memory quota, or expired after
+    /// it was idle.
+    Evicted,
+    /// The key's value was removed with [`remove`](crate::ObserverMap::remove) or
+    /// [`clear`](crate::ObserverMap::clear).
+    Removed,
+}
+
+// The most recent `capacity` changes to a map, oldest first.
+pub(crate) struct Journal<K, V> {
+    events: VecDeque<MapEvent<K, V>>,
+    capacity: usize,
+    // Monomorphised when the journal is enabled, so that the map needn't require `K: Clone`.
+    clone_key: fn(&K) -> K,
+}
+
+impl<K, V> Journal<K, V>
+where
+    V: Clone,
+{
+    pub(crate) fn new(capacity: usize) -> Self
+    where
+        K: Clone,
+    {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            clone_key: K::clone,
+        }
+    }
+
+    pub(crate) fn record(&mut self, seq: u64, key: &K, change: Change<V>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(MapEvent {
+            seq,
+            key: (self.clone_key)(key),
+            change,
+        });
+    }
+
+    // The events numbered `seq` onwards, given that `next_seq` will number the next one. The
+    // numbers have gaps wherever the map's sequence jumped, as when it applies replicated
+    // events, so the events are searched rather than indexed by number.
+    pub(crate) fn since(
+        &self,
+        seq: u64,
+        next_seq: u64,
+    ) -> Result<Vec<MapEvent<K, V>>, JournalError> {
+        let oldest = self.events.front().map_or(next_seq, |event| event.seq);
+        if seq < oldest {
+            return Err(JournalError::Truncated { oldest });
+        }
+        let skip = self.events.partition_point(|event| event.seq < seq);
+        Ok(self
+            .events
+            .iter()
+            .skip(skip)
+            .map(|event| MapEvent {
+                seq: event.seq,
+                key: (self.clone_key)(&event.key),
+                change: event.change.clone(),
+            })
+            .collect())
+    }
+}
+
+// The observers of every change to a map, subscribed with `events`.
+pub(crate) struct EventSubscribers<K, V> {
+    observers: Vec<Observer<MapEvent<K, V>>>,
+    clone_key: fn(&K) -> K,
+}
+
+impl<K, V> EventSubscribers<K, V>
+where
+    V: Clone,
+{
+    pub(crate) fn new() -> Self
+    where
+        K: Clone,
+    {
+        Self {
+            observers: Vec::new(),
+            clone_key: K::clone,
+        }
+    }
+
+    pub(crate) fn subscribe(&mut self, observer: Observer<MapEvent<K, V>>) {
+        self.observers.push(observer);
+    }
+
+    pub(crate) fn publish(&mut self, seq: u64, key: &K, change: &Change<V>) {
+        let clone_key = self.clone_key;
+        self.observers.retain(|observer| {
+            let event = MapEvent {
+                seq,
+                key: clone_key(key),
+                change: change.clone(),
+            };
+            // Subscribers too far behind to take the event are dropped, ending their stream.
+            observer.send(event, None, None) != Delivery::Rejected && observer.is_subscribed()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_oldest_events_beyond_capacity() {
+        let mut journal = Journal::new(2);
+        for seq in 0..3 {
+            journal.record(seq, &"key", Change::Inserted(seq));
+        }
+
+        assert_eq!(
+            journal.since(0, 3),
+            Err(JournalError::Truncated { oldest: 1 })
+        );
+        let events = journal.since(2, 3).unwrap();
+        assert_eq!(
+            events,
+            vec![MapEvent {
+                seq: 2,
+                key: "key",
+                change: Change::Inserted(2)
+            }]
+        );
+        assert_eq!(journal.since(3, 3), Ok(vec![]));
+    }
+
+    #[test]
+    fn finds_events_across_gaps_in_their_numbers() {
+        let mut journal = Journal::new(8);
+        for seq in [0, 1, 10, 11] {
+            journal.record(seq, &"key", Change::Inserted(seq));
+        }
+        let seqs = |since| {
+            journal
+                .since(since, 12)
+                .map(|events| events.iter().map(|event| event.seq).collect::<Vec<_>>())
+        };
+
+        assert_eq!(seqs(1), Ok(vec![1, 10, 11]));
+        assert_eq!(seqs(5), Ok(vec![10, 11]));
+        assert_eq!(seqs(11), Ok(vec![11]));
+    }
+}
@@ -0,0 +1,108 @@
+use alloc::boxed::Box;
+use core::hash::Hash;
+
+use crate::channel::{self, Sender};
+use crate::observer::Delivery;
+use crate::project::Projection;
+use crate::{ObserveError, Observer, ObserverMap, Receiver, ThreadSafeObserverMap};
+
+// Observes one of the keys of a multi-key subscription, tagging its values with the key.
+struct Tagged<K, V> {
+    key: K,
+    tx: Sender<(K, V)>,
+}
+
+impl<K, V> Projection<V> for Tagged<K, V>
+where
+    K: Clone + Send + Sync,
+    V: Clone + Send,
+{
+    fn send(&self, value: &V) -> Delivery {
+        match self.tx.send((self.key.clone(), value.clone())) {
+            Ok(()) => Delivery::Delivered,
+            Err(_) => Delivery::Disconnected,
+        }
+    }
+
+    fn receivers(&self) -> usize {
+        usize::from(!self.tx.is_disconnected())
+    }
+
+    fn pending(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone + Send + Sync + 'static,
+    V: Clone + Send + 'static,
+{
+    /// Observes every update to any of `keys` through one receiver, which receives each
+    /// value tagged with its key, so a consumer watching many keys needn't hold a channel per
+    /// key. Updates queue up without limit if the receiver falls behind.
+    pub fn observe_keys(
+        &mut self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Receiver<(K, V)>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        // Counted as dropped if a key can't be observed, unregistering the keys before it.
+        let rx = rx.unregister_on_drop(&self.dropped);
+        for key in keys {
+            let tagged = Tagged {
+                key: key.clone(),
+                tx: tx.clone(),
+            };
+            self.add_observer(key, Observer::Projected(Box::new(tagged)))?;
+        }
+        Ok(rx)
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone + Send + Sync + 'static,
+    V: Clone + Send + 'static,
+{
+    /// See [`ObserverMap::observe_keys`]. The keys are observed under one lock.
+    pub fn observe_keys(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Receiver<(K, V)>, ObserveError> {
+        self.write().observe_keys(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn tags_updates_to_any_of_the_keys() {
+        let mut map = ObserverMap::new();
+        let instruments: Vec<_> = (0..500).map(|i| format!("inst-{i}")).collect();
+        let rx = map.observe_keys(instruments.iter().cloned()).unwrap();
+        map.insert("inst-7".to_string(), 1.5).unwrap();
+        map.insert("other".to_string(), 2.0).unwrap();
+        map.insert("inst-499".to_string(), 3.0).unwrap();
+
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![("inst-7".to_string(), 1.5), ("inst-499".to_string(), 3.0)]
+        );
+        assert_eq!(map.total_observers(), 500);
+    }
+
+    #[test]
+    fn keys_are_unregistered_if_one_cannot_be_observed() {
+        let mut map = ObserverMap::with_observer_limit(1);
+        let _held = map.observe("b").unwrap();
+        assert!(matches!(
+            map.observe_keys(["a", "b"]),
+            Err(ObserveError::TooManyObservers)
+        ));
+        map.insert("c", 1).unwrap();
+        assert_eq!(map.observer_count("a"), 0);
+    }
+}
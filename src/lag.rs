@@ -0,0 +1,103 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::time::Duration;
+
+use crate::clock::SystemTime;
+
+use crate::clock::Clock;
+use crate::{ObserverMap, Registration, ThreadSafeObserverMap};
+
+/// How far one of a key's observers has fallen behind, returned by
+/// [`ObserverMap::observer_lag`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObserverLag {
+    /// The group the observer was registered in, if any, to tell consumers apart.
+    pub group: Option<String>,
+    pub priority: i32,
+    /// Values queued that the observer hasn't received yet, or `None` for callbacks, watch
+    /// channels and async receivers, whose queues can't be measured.
+    pub pending: Option<usize>,
+    /// Values the observer missed because its channel was full: discarded or rejected by its
+    /// [`Backpressure`](crate::Backpressure) policy, displaced by newer values or timed out.
+    pub dropped: u64,
+    /// When a value was last delivered to the observer, or `None` if none has been.
+    pub last_delivery: Option<SystemTime>,
+    /// How long ago a value was last delivered to the observer.
+    pub since_delivery: Option<Duration>,
+}
+
+impl<T> Registration<T> {
+    fn lag(&self, clock: &Clock) -> ObserverLag {
+        let since_delivery = self.delivered.map(|delivered| clock.now() - delivered);
+        ObserverLag {
+            group: self.group.clone(),
+            priority: self.priority,
+            pending: self.observer.pending(),
+            dropped: self.dropped,
+            last_delivery: since_delivery.map(|since| clock.system_now() - since),
+            since_delivery,
+        }
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+{
+    /// How far each of the key's live observers has fallen behind, in the order they're
+    /// notified, to find the consumers failing to keep up with a hot key.
+    pub fn observer_lag(&self, key: K) -> Vec<ObserverLag> {
+        let Some(observers) = self
+            .hashmap
+            .get(&key)
+            .and_then(|item| item.observers.as_ref())
+        else {
+            return Vec::new();
+        };
+        observers
+            .iter()
+            .filter(|registration| registration.observer.receivers() > 0)
+            .map(|registration| registration.lag(&self.clock))
+            .collect()
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+{
+    /// See [`ObserverMap::observer_lag`].
+    pub fn observer_lag(&self, key: K) -> Vec<ObserverLag> {
+        self.read().observer_lag(key)
+    }
+}
+
+#[cfg(all(test, os))]
+mod tests {
+    use super::*;
+    use crate::{Backpressure, ObservableMap};
+
+    #[test]
+    fn reports_pending_and_dropped_values_per_observer() {
+        let mut map = ObserverMap::new();
+        let fast = map.observe_unbounded("px").unwrap();
+        let slow = map
+            .observe_with_backpressure("px", Backpressure::DropNewest)
+            .unwrap();
+        for px in 0..5 {
+            map.insert("px", px).unwrap();
+            fast.recv().unwrap();
+        }
+
+        let lag = map.observer_lag("px");
+        assert_eq!(lag.len(), 2);
+        assert_eq!((lag[0].pending, lag[0].dropped), (Some(0), 0));
+        assert_eq!((lag[1].pending, lag[1].dropped), (Some(1), 4));
+        assert!(lag[1].last_delivery.is_some());
+        assert!(lag[0].since_delivery <= lag[1].since_delivery);
+
+        drop(slow);
+        assert_eq!(map.observer_lag("px").len(), 1);
+    }
+}
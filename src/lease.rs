@@ -0,0 +1,143 @@
+use crate::collections::HashSet;
+use crate::sync::lock::{Condvar, Mutex, MutexGuard, PoisonError};
+use core::hash::Hash;
+
+use crate::{InsertError, ObservableMap, ThreadSafeObserverMap};
+
+// The keys leased to a `KeyGuard`, shared between every handle to a map.
+pub(crate) struct Leases<K> {
+    held: Mutex<HashSet<K>>,
+    released: Condvar,
+}
+
+impl<K> Leases<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            held: Mutex::new(HashSet::default()),
+            released: Condvar::new(),
+        }
+    }
+}
+
+impl<K> Leases<K>
+where
+    K: Hash + Eq,
+{
+    // Blocks until `key` isn't leased, returning the lock on the leases so that it can't be
+    // leased again until the caller is done.
+    pub(crate) fn wait_until_free(&self, key: &K) -> MutexGuard<'_, HashSet<K>> {
+        let held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        self.released
+            .wait_while(held, |held| held.contains(key))
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Like `wait_until_free`, but blocks until none of `keys` is leased.
+    pub(crate) fn wait_until_all_free<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a K> + Clone,
+    ) -> MutexGuard<'_, HashSet<K>>
+    where
+        K: 'a,
+    {
+        let held = self.held.lock().unwrap_or_else(PoisonError::into_inner);
+        self.released
+            .wait_while(held, |held| keys.clone().any(|key| held.contains(key)))
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn release(&self, key: &K) {
+        self.held
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+        self.released.notify_all();
+    }
+}
+
+/// Exclusive rights to write a key of a [`ThreadSafeObserverMap`], returned by
+/// [`ThreadSafeObserverMap::lock_key`]. Until the guard is dropped, inserts for the key through
+/// any handle block, whilst reads and observers are unaffected. Write the key through the
+/// guard instead.
+pub struct KeyGuard<K, V>
+where
+    K: Hash + Eq,
+{
+    map: ThreadSafeObserverMap<K, V>,
+    key: K,
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    /// Leases `key` to the caller, blocking until any other guard for it is dropped.
+    pub fn lock_key(&self, key: K) -> KeyGuard<K, V> {
+        self.leases.wait_until_free(&key).insert(key.clone());
+        KeyGuard {
+            map: self.clone(),
+            key,
+        }
+    }
+}
+
+impl<K, V> KeyGuard<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> Option<V> {
+        self.map.read().get(self.key.clone())
+    }
+
+    pub fn insert(&self, value: V) -> Result<(), InsertError<V>> {
+        self.map.write().insert(self.key.clone(), value)
+    }
+}
+
+impl<K, V> Drop for KeyGuard<K, V>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        self.map.leases.release(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn other_writers_block_until_guard_is_dropped() {
+        let map = ThreadSafeObserverMap::new();
+        let guard = map.lock_key("key".to_string());
+        guard.insert(1).unwrap();
+
+        let (done, finished) = mpsc::channel();
+        let mut writer = map.clone();
+        thread::spawn(move || {
+            writer.insert("key".to_string(), 3).unwrap();
+            done.send(()).unwrap();
+        });
+        assert!(finished.recv_timeout(Duration::from_millis(50)).is_err());
+
+        let mut reader = map.clone();
+        assert_eq!(reader.get("key".to_string()), Some(1));
+        reader.insert("other".to_string(), 0).unwrap();
+        guard.insert(2).unwrap();
+        assert_eq!(guard.get(), Some(2));
+
+        drop(guard);
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(reader.get("key".to_string()), Some(3));
+    }
+}
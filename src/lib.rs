@@ -1,25 +1,865 @@
-use std::collections::HashMap;
-use std::hash::Hash;
-use std::sync::mpsc::{sync_channel, Receiver, RecvError, SendError, SyncSender};
-use std::sync::{Arc, RwLock};
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "tokio")]
+mod async_map;
+#[cfg(feature = "tokio")]
+mod async_receiver;
+mod audit;
+#[cfg(os)]
+mod batch;
+mod builder;
+mod bulk;
+pub mod channel;
+mod clock;
+mod collections;
+#[cfg(feature = "lz4")]
+mod compress;
+#[cfg(feature = "encryption")]
+mod crypt;
+mod delta;
+mod diff;
+mod enum_map;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod filter;
+#[cfg(feature = "heapless")]
+mod fixed;
+mod generation;
+mod handle;
+mod idle;
+#[cfg(feature = "im")]
+mod im_map;
+#[cfg(feature = "tracing")]
+mod instrument;
+mod iter;
+mod journal;
+mod keys;
+mod lag;
+mod lease;
+#[cfg(all(test, loom))]
+mod loom_tests;
+mod merge;
+mod metrics;
+#[cfg(feature = "mmap")]
+mod mmap_map;
+#[cfg(feature = "net")]
+pub mod net;
+mod observer;
+#[cfg(feature = "persist")]
+mod persist;
+mod project;
+mod quota;
+#[cfg(feature = "redis")]
+pub mod redis_bridge;
+mod registry;
+mod rendezvous;
+mod scope;
+mod seqlock;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "futures")]
+mod sink;
+#[cfg(feature = "sled")]
+mod sled_map;
+#[cfg(feature = "spsc")]
+mod spsc;
+#[cfg(feature = "sse")]
+pub mod sse;
+mod subscription;
+mod sync;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tombstone;
+mod trie_map;
+mod update;
+#[cfg(feature = "wal")]
+mod wal;
+mod zip;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::hash::Hash;
+use core::mem;
+use core::ops::Index;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+#[cfg(os)]
+use std::thread;
+
+#[cfg(os)]
+use channel::{RecvError, RecvTimeoutError};
+#[cfg(feature = "std")]
+use clock::{Instant, SystemTime};
+#[cfg(feature = "std")]
+use collections::HashMap;
+use sync::lock::{Mutex, PoisonError};
+
+#[cfg(feature = "tokio")]
+pub use async_map::{AsyncObservableMap, AsyncObserverMap};
+#[cfg(feature = "tokio")]
+pub use async_receiver::{AsyncReceiver, Recv};
+pub use audit::{Attributed, WriteInfo};
+pub use builder::ObserverMapBuilder;
+pub use channel::Receiver;
+#[cfg(feature = "mock-clock")]
+pub use clock::MockClock;
+#[cfg(not(feature = "std"))]
+pub use clock::{Instant, SystemTime};
+#[cfg(not(feature = "std"))]
+pub use collections::{FnvHasher, HashMap};
+#[cfg(feature = "lz4")]
+pub use compress::CompressedBytes;
+#[cfg(feature = "encryption")]
+pub use crypt::EncryptionKey;
+pub use delta::Diffable;
+pub use diff::DiffEntry;
+pub use enum_map::{EnumKey, EnumObserverMap};
+pub use error::{
+    ApplyError, InsertError, JournalError, ObserveError, Rejection, RemoveError, WaitError,
+};
+#[cfg(feature = "heapless")]
+pub use fixed::FixedObserverMap;
+pub use generation::Generational;
+pub use handle::{Reader, Writer};
+#[cfg(feature = "im")]
+pub use im_map::ImObserverMap;
+pub use iter::{IntoIter, Iter};
+pub use journal::{Change, MapEvent};
+pub use lag::ObserverLag;
+pub use lease::KeyGuard;
+pub use merge::{GCounter, LwwRegister, MergeableValue};
+pub use metrics::{KeyStats, MemoryUsage, Metrics, MetricsSnapshot};
+#[cfg(feature = "mmap")]
+pub use mmap_map::MmapObserverMap;
+pub use observer::{Backpressure, DeliveryReport};
+#[cfg(feature = "persist")]
+pub use persist::Format;
+pub use quota::{EvictionPolicy, SizeOf};
+pub use scope::ScopeGuard;
+pub use seqlock::{SeqlockObserverMap, SeqlockReader};
+#[cfg(feature = "futures")]
+pub use sink::{KeySink, MapSink};
+#[cfg(feature = "sled")]
+pub use sled_map::PersistentObserverMap;
+#[cfg(feature = "spsc")]
+pub use spsc::{SpscObserverMap, SpscReader};
+pub use subscription::Subscription;
+pub use tombstone::{Lookup, Tombstone};
+pub use trie_map::TrieObserverMap;
+pub use update::UpdateGuard;
+
+use clock::Clock;
+use filter::Filter;
+use idle::IdleExpiry;
+#[cfg(feature = "tracing")]
+use instrument::{DisplayKey, KeyFormatter};
+use journal::{EventSubscribers, Journal};
+use lease::Leases;
+use metrics::{ObserverGauge, UpdateRate};
+use observer::{Callback, Observer};
+use quota::MemoryQuota;
+use rendezvous::Rendezvous;
+use sync::{AtomicUsize, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(feature = "wal")]
+use wal::{Record, WriteAheadLog};
 
 pub trait ObservableMap<K, V> {
-    fn insert(&mut self, key: K, value: V) -> Result<(), SendError<V>>;
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>>;
+
+    /// Inserts a value, reporting how it was delivered to the key's observers. Unlike
+    /// [`insert`](ObservableMap::insert), observers rejecting the value are counted in the
+    /// report rather than failing the insert.
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>>;
+
+    /// Inserts a value, notifying only the observers subscribed to `group` with
+    /// [`observe_group`](ObservableMap::observe_group).
+    /// [`insert`](ObservableMap::insert) notifies every observer, whatever its group.
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>>;
+
     fn get(&self, key: K) -> Option<V>;
-    fn observe(&mut self, key: K) -> Receiver<V>;
-    fn wait(&mut self, key: K) -> Result<V, RecvError>;
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError>;
+    fn is_closed(&self) -> bool;
+
+    /// The number of live receivers observing `key`.
+    fn observer_count(&self, key: K) -> usize;
+
+    /// The number of live receivers observing any key.
+    fn total_observers(&self) -> usize;
+
+    /// The metrics collector attached to the map, if any.
+    fn metrics(&self) -> Option<Arc<Metrics>>;
+
+    #[cfg(os)]
+    fn wait(&mut self, key: K) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let rx = self.observe(key)?;
+        let result = rx.recv().map_err(|RecvError| {
+            if self.is_closed() {
+                WaitError::Closed
+            } else {
+                WaitError::Disconnected
+            }
+        });
+        record_wait(self.metrics(), started, &result);
+        result
+    }
+
+    /// Like [`wait`](ObservableMap::wait), but spins for up to `spin` before parking the
+    /// thread, for latency-sensitive consumers of rapidly updated keys. See
+    /// [`Receiver::recv_spinning`].
+    #[cfg(os)]
+    fn wait_spinning(&mut self, key: K, spin: Duration) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let rx = self.observe(key)?;
+        let result = rx.recv_spinning(spin).map_err(|RecvError| {
+            if self.is_closed() {
+                WaitError::Closed
+            } else {
+                WaitError::Disconnected
+            }
+        });
+        record_wait(self.metrics(), started, &result);
+        result
+    }
+
+    /// Waits for the next value of `key`, failing with [`WaitError::Timeout`] if none is
+    /// inserted within `timeout`.
+    #[cfg(os)]
+    fn wait_timeout(&mut self, key: K, timeout: Duration) -> Result<V, WaitError> {
+        wait_timeout_on(self, key, timeout, &Clock::default())
+    }
+
+    /// Like [`wait_timeout`](ObservableMap::wait_timeout), but fails once `deadline` has
+    /// passed, so waits for several keys can share one deadline without each computing the
+    /// time remaining.
+    #[cfg(os)]
+    fn wait_deadline(&mut self, key: K, deadline: Instant) -> Result<V, WaitError> {
+        self.wait_timeout(key, deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns the current value of `key` if it has one, and otherwise waits for the first.
+    /// Unlike calling [`get`](ObservableMap::get) then [`wait`](ObservableMap::wait), no
+    /// value can be inserted in between and missed.
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError>;
+
+    /// Observes every update to `key` until the receiver is dropped, applying `backpressure`
+    /// whenever the receiver falls behind.
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError>;
+
+    /// Observes every update to `key` until the receiver is dropped, buffering up to `capacity`
+    /// values before the inserting thread blocks.
+    ///
+    /// The insert holds the map's lock whilst blocked, stalling every other insert, and every
+    /// read of a [`ThreadSafeObserverMap`], so it waits at most the map's
+    /// [send timeout](ObserverMap::set_send_timeout), [`DEFAULT_SEND_TIMEOUT`] unless changed,
+    /// after which the receiver misses the value. Use
+    /// [`observe_with_backpressure`](ObservableMap::observe_with_backpressure) to drop values
+    /// without waiting instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError>;
+
+    /// Observes every update to `key` until the receiver is dropped, without ever blocking the
+    /// inserting thread. Values queue up without limit if the receiver falls behind, so a slow
+    /// consumer costs memory rather than producer latency.
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError>;
+
+    /// Like [`observe_unbounded`](ObservableMap::observe_unbounded), but the key's current
+    /// value, if it has one, is delivered first. No insert can fall between the two, so the
+    /// receiver always sees the latest value.
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError>;
+
+    /// Observes every update to `key`, delivering at most one value per `interval`.
+    /// Updates arriving within an interval are conflated so only the most recent is delivered.
+    #[cfg(os)]
+    fn observe_throttled(
+        &mut self,
+        key: K,
+        interval: Duration,
+    ) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static;
+
+    /// Observes every update to `key` until the receiver is dropped, like
+    /// [`observe_with_backpressure`](ObservableMap::observe_with_backpressure) with
+    /// [`Backpressure::Block`], so a receiver that falls behind blocks inserts as described
+    /// for [`observe_with_capacity`](ObservableMap::observe_with_capacity). Observers with a
+    /// higher `priority` are notified of each update before those with a lower one, and
+    /// observers of equal priority in the order they registered. Other observers have
+    /// priority 0.
+    fn observe_with_priority(&mut self, key: K, priority: i32)
+        -> Result<Receiver<V>, ObserveError>;
+
+    /// Observes every update to `key` until the receiver is dropped, like
+    /// [`observe_with_backpressure`](ObservableMap::observe_with_backpressure) with
+    /// [`Backpressure::Block`], as a member of `group`, so a receiver that falls behind blocks
+    /// inserts as described for [`observe_with_capacity`](ObservableMap::observe_with_capacity).
+    /// Members of a group are notified by
+    /// [`insert_for_group`](ObservableMap::insert_for_group) for that group as well as by
+    /// plain inserts.
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError>;
+
+    /// Registers `callback` to be run with every value subsequently inserted for `key`. The
+    /// callback runs on the inserting thread whilst the map is locked, so it should be quick
+    /// and must not access the map.
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static;
+
+    /// Like [`on_update`](ObservableMap::on_update), but notified in `priority` order as
+    /// described by [`observe_with_priority`](ObservableMap::observe_with_priority).
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static;
+
+    /// Observes `key` through a `tokio::sync::watch` channel, which starts with the current
+    /// value and conflates updates until the receiver next reads.
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError>;
+
+    /// Subscribes to `key` through a `tokio::sync::broadcast` channel shared by every
+    /// broadcast subscriber of the key. Receivers that fall more than `capacity` values
+    /// behind observe `RecvError::Lagged`. The channel is created by the first subscriber,
+    /// so `capacity` is ignored whilst other broadcast subscribers of the key remain.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError>;
+
+    /// Observes every update to `key` until the receiver is dropped, through a receiver that
+    /// can be awaited or polled as a `Stream` without blocking a thread.
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError>;
+
+    /// Like [`observe_async`](ObservableMap::observe_async), but at most `capacity` values
+    /// are queued for the receiver. An observer that falls so far behind that its queue is
+    /// full is disconnected, so its receiver ends once it has taken the values already queued,
+    /// rather than inserts blocking or memory growing without limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError>;
+
+    /// Spawns a task on the current Tokio runtime that runs `callback` with every value
+    /// subsequently inserted for `key`. Values are passed to the callback in insertion order
+    /// and each returned future is awaited before the next value is handled, so a slow callback
+    /// queues values rather than blocking inserts. The task exits once the map is closed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a Tokio runtime.
+    #[cfg(feature = "tokio")]
+    fn on_update_async<F, Fut>(&mut self, key: K, mut callback: F) -> Result<(), ObserveError>
+    where
+        V: Send + 'static,
+        F: FnMut(V) -> Fut + Send + 'static,
+        Fut: std::future::Future<Output = ()> + Send,
+    {
+        let mut rx = self.observe_async(key)?;
+        tokio::spawn(async move {
+            while let Ok(value) = rx.recv().await {
+                callback(value).await;
+            }
+        });
+        Ok(())
+    }
+
+    /// Waits for the next value of `key`, returning early with [`WaitError::Cancelled`] as
+    /// soon as `token` is cancelled. Needn't be called from within a Tokio runtime.
+    #[cfg(all(feature = "tokio", os))]
+    fn wait_with_cancel(
+        &mut self,
+        key: K,
+        token: &tokio_util::sync::CancellationToken,
+    ) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let mut rx = self.observe_async(key)?;
+        // Woken by whichever of an insert and the cancellation comes first.
+        let received = block_on(async {
+            tokio::select! {
+                biased;
+                () = token.cancelled() => None,
+                value = rx.recv() => Some(value),
+            }
+        });
+        let result = match received {
+            None => Err(WaitError::Cancelled),
+            Some(Ok(value)) => Ok(value),
+            Some(Err(RecvError)) if self.is_closed() => Err(WaitError::Closed),
+            Some(Err(RecvError)) => Err(WaitError::Disconnected),
+        };
+        record_wait(self.metrics(), started, &result);
+        result
+    }
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+#[cfg(os)]
+fn record_wait<V>(metrics: Option<Arc<Metrics>>, started: Instant, result: &Result<V, WaitError>) {
+    #[cfg(feature = "tracing")]
+    ::tracing::debug!(elapsed = ?started.elapsed(), error = ?result.as_ref().err(), "waited");
+    if let Some(metrics) = metrics {
+        metrics.record_wait(started.elapsed());
+    }
+}
+
+// Runs `future` to completion on the current thread, which is parked until the future is woken.
+#[cfg(all(feature = "tokio", os))]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    struct Unparker(thread::Thread);
+
+    impl std::task::Wake for Unparker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = std::task::Waker::from(Arc::new(Unparker(thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+// Waits for the next value of `key`, timing out after `timeout` has passed on `clock`.
+#[cfg(os)]
+fn wait_timeout_on<K, V>(
+    map: &mut (impl ObservableMap<K, V> + ?Sized),
+    key: K,
+    timeout: Duration,
+    clock: &Clock,
+) -> Result<V, WaitError> {
+    let started = Instant::now();
+    let rx = map.observe(key)?;
+    let result = clock
+        .recv_timeout(&rx, timeout)
+        .map_err(|error| match error {
+            RecvTimeoutError::Timeout => WaitError::Timeout,
+            RecvTimeoutError::Disconnected if map.is_closed() => WaitError::Closed,
+            RecvTimeoutError::Disconnected => WaitError::Disconnected,
+        });
+    record_wait(map.metrics(), started, &result);
+    result
+}
+
+// Completes a `get_or_wait`, waiting on the receiver if the key had no value.
+#[cfg(os)]
+fn wait_if_unset<K, V>(
+    map: &impl ObservableMap<K, V>,
+    current: Result<V, Receiver<V>>,
+    started: Instant,
+) -> Result<V, WaitError> {
+    let result = current.or_else(|rx| {
+        rx.recv().map_err(|RecvError| {
+            if map.is_closed() {
+                WaitError::Closed
+            } else {
+                WaitError::Disconnected
+            }
+        })
+    });
+    record_wait(map.metrics(), started, &result);
+    result
+}
+
+fn run_hook<K>(hook: &Option<Mutex<Callback<K>>>, key: &K) {
+    if let Some(hook) = hook {
+        hook.lock().unwrap_or_else(PoisonError::into_inner)(key);
+    }
+}
+
+fn run_after_notify<K, V>(
+    hooks: &[Mutex<AfterNotify<K, V>>],
+    key: &K,
+    value: Option<&V>,
+    report: &DeliveryReport,
+) {
+    if let Some(value) = value {
+        for hook in hooks {
+            hook.lock().unwrap_or_else(PoisonError::into_inner)(key, value, report);
+        }
+    }
+}
+
+// Like `ObserverMap::sequence_change`, but borrowing only the fields it needs, so that changes
+// can be recorded whilst the map's items are borrowed, for example as they're removed.
+fn sequence_change<K, V: Clone>(
+    seq: &mut u64,
+    journal: &mut Option<Journal<K, V>>,
+    subscribers: &mut Option<EventSubscribers<K, V>>,
+    key: &K,
+    change: impl Fn() -> Change<V>,
+) -> u64 {
+    let next = *seq;
+    if let Some(journal) = journal {
+        journal.record(next, key, change());
+    }
+    if let Some(subscribers) = subscribers {
+        subscribers.publish(next, key, &change());
+    }
+    *seq += 1;
+    next
 }
 
+type Resolver<V> = Box<dyn Fn(&V, V) -> V + Send + Sync>;
+type Interceptor<K, V> =
+    Box<dyn Fn(&K, &mut V) -> Result<(), Box<dyn core::error::Error + Send + Sync>> + Send + Sync>;
+type AfterNotify<K, V> = Box<dyn FnMut(&K, &V, &DeliveryReport) + Send>;
+
+// wasm32-unknown-unknown panics on reading the time, so no timestamps are taken on wasm32, or
+// without the `std` feature. Update rates and history aren't recorded there.
+const HAS_CLOCK: bool = cfg!(os);
+
+/// How long an insert waits for a full [`Backpressure::Block`] observer to make room before
+/// the observer misses the value, unless the map's
+/// [send timeout](ObserverMap::set_send_timeout) has been changed. Inserts wait indefinitely
+/// on `wasm32`, which has no clock, and without the `std` feature.
+pub const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub struct ObserverMap<K, V> {
     hashmap: HashMap<K, Item<V>>,
+    closed: bool,
+    // Whether keys are immutable once they have a value.
+    write_once: bool,
+    // Receivers dropped since their senders were last unregistered.
+    dropped: sync::Arc<AtomicUsize>,
+    observer_limit: Option<usize>,
+    slow_observers: Option<SlowObservers<K>>,
+    send_timeout: Option<Duration>,
+    metrics: Option<Arc<Metrics>>,
+    interceptors: Vec<Interceptor<K, V>>,
+    resolver: Option<Resolver<V>>,
+    key_resolvers: HashMap<K, Resolver<V>>,
+    rendezvous: HashMap<K, Rendezvous<V>>,
+    // How many recent values to keep for each key.
+    history_capacity: Option<usize>,
+    idle: Option<IdleExpiry>,
+    clock: Clock,
+    on_first_observer: Option<Mutex<Callback<K>>>,
+    on_last_observer: Option<Mutex<Callback<K>>>,
+    after_notify: Vec<Mutex<AfterNotify<K, V>>>,
+    // The sequence number of the next insert.
+    seq: u64,
+    // Shared with generational observers, which read it as they're notified.
+    generation: Arc<AtomicU64>,
+    journal: Option<Journal<K, V>>,
+    subscribers: Option<EventSubscribers<K, V>>,
+    quota: Option<MemoryQuota<K, V>>,
+    // When each removed key's value was removed, if tombstones are kept.
+    tombstones: Option<HashMap<K, Tombstone>>,
+    #[cfg(feature = "tracing")]
+    key_formatter: Option<KeyFormatter<K>>,
+    #[cfg(feature = "wal")]
+    wal: Option<WriteAheadLog<K, V>>,
+}
+
+// Evicts observers that fail to keep up with `strikes` consecutive updates.
+struct SlowObservers<K> {
+    strikes: u32,
+    on_evict: Mutex<Callback<K>>,
+}
+
+impl<K> SlowObservers<K> {
+    fn evicted(&self, key: &K, count: usize) {
+        let mut on_evict = self.on_evict.lock().unwrap_or_else(PoisonError::into_inner);
+        for _ in 0..count {
+            on_evict(key);
+        }
+    }
 }
 
 impl<K, V> ObserverMap<K, V> {
     pub fn new() -> Self {
         Self {
-            hashmap: HashMap::new(),
+            hashmap: HashMap::default(),
+            closed: false,
+            write_once: false,
+            dropped: sync::Arc::new(AtomicUsize::new(0)),
+            observer_limit: None,
+            slow_observers: None,
+            send_timeout: Limits::default().send_timeout,
+            metrics: None,
+            interceptors: Vec::new(),
+            resolver: None,
+            key_resolvers: HashMap::default(),
+            rendezvous: HashMap::default(),
+            history_capacity: None,
+            idle: None,
+            clock: Clock::default(),
+            on_first_observer: None,
+            on_last_observer: None,
+            after_notify: Vec::new(),
+            seq: 0,
+            generation: Arc::new(AtomicU64::new(0)),
+            journal: None,
+            subscribers: None,
+            quota: None,
+            tombstones: None,
+            #[cfg(feature = "tracing")]
+            key_formatter: None,
+            #[cfg(feature = "wal")]
+            wal: None,
+        }
+    }
+
+    /// Creates a map with room for at least `capacity` keys before it reallocates.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            hashmap: HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            ..Self::new()
+        }
+    }
+
+    /// The number of keys the map can hold before it reallocates. Keys that are observed but
+    /// have no value count towards it.
+    pub fn capacity(&self) -> usize {
+        self.hashmap.capacity()
+    }
+
+    /// Reserves room for at least `additional` more keys, for example before loading many
+    /// keys at once.
+    pub fn reserve(&mut self, additional: usize)
+    where
+        K: Hash + Eq,
+    {
+        self.hashmap.reserve(additional)
+    }
+
+    /// Releases excess capacity held by the map and by each key's observers and history,
+    /// for example after a spike in keys or observers has passed.
+    pub fn shrink_to_fit(&mut self)
+    where
+        K: Hash + Eq,
+    {
+        self.hashmap.shrink_to_fit();
+        for item in self.hashmap.values_mut() {
+            item.shrink_to_fit();
+        }
+    }
+
+    /// Creates a map allowing at most `limit` observers per key. Further attempts to observe
+    /// the key fail with [`ObserveError::TooManyObservers`] until an observer is dropped.
+    /// Each `broadcast` channel counts as one observer however many receivers share it.
+    pub fn with_observer_limit(limit: usize) -> Self {
+        Self {
+            observer_limit: Some(limit),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a map whose keys can only be set once. Inserts for a key that already has a
+    /// value fail with [`InsertError::AlreadySet`] without notifying its observers.
+    pub fn write_once() -> Self {
+        Self {
+            write_once: true,
+            ..Self::new()
+        }
+    }
+
+    /// Closes the map. Every registered observer and event subscriber is disconnected, so
+    /// blocked waits return promptly and event streams end, and subsequent inserts fail.
+    /// Values already in the map can still be read.
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.subscribers = None;
+        for (key, item) in &mut self.hashmap {
+            if item.observers.take().is_some() {
+                run_hook(&self.on_last_observer, key);
+            }
+        }
+    }
+
+    /// Runs `hook` with a key whenever it gains its first observer, for example to start
+    /// fetching its values from upstream only once something wants them.
+    ///
+    /// `hook` runs whilst the map is locked, so it must not access the map.
+    pub fn on_first_observer<F>(&mut self, hook: F)
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.on_first_observer = Some(Mutex::new(Box::new(hook)));
+    }
+
+    /// Runs `hook` with a key whenever its last observer goes away. Observers whose receivers
+    /// are dropped go away the next time the map is written, or when
+    /// [`purge_dead_observers`](ObserverMap::purge_dead_observers) is called.
+    ///
+    /// `hook` runs whilst the map is locked, so it must not access the map.
+    pub fn on_last_observer<F>(&mut self, hook: F)
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.on_last_observer = Some(Mutex::new(Box::new(hook)));
+    }
+
+    /// Runs `hook` after the observers of a key have been notified of each insert, with the
+    /// key, the value delivered and the [`DeliveryReport`], after any hooks added before it.
+    /// Hooks see every insert without registering as observers, for example to audit writes.
+    ///
+    /// `hook` runs whilst the map is locked, so it must not access the map.
+    pub fn after_notify<F>(&mut self, hook: F)
+    where
+        F: FnMut(&K, &V, &DeliveryReport) + Send + 'static,
+    {
+        self.after_notify.push(Mutex::new(Box::new(hook)));
+    }
+
+    /// Evicts observers that fail to keep up with `strikes` consecutive updates, running
+    /// `on_evict` with the key of each. An observer fails to keep up with an update whenever its
+    /// channel is full as the value is delivered, whatever its [`Backpressure`] policy. Evicted
+    /// observers' receivers disconnect once they have drained any values already queued.
+    ///
+    /// `on_evict` runs whilst the map is locked, so it must not access the map.
+    pub fn evict_slow_observers<F>(&mut self, strikes: u32, on_evict: F)
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.slow_observers = Some(SlowObservers {
+            strikes,
+            on_evict: Mutex::new(Box::new(on_evict)),
+        });
+    }
+
+    /// Records the map's activity in `metrics`. Only observers registered from now on are
+    /// counted as active.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Drives the map's timeouts, throttling, history timestamps and key stats from `clock`
+    /// instead of real time. Throttled observers registered earlier keep real time.
+    #[cfg(feature = "mock-clock")]
+    pub fn set_clock(&mut self, clock: MockClock) {
+        self.clock = Clock::mock(clock);
+    }
+
+    /// Formats keys with `formatter` in the map's spans and events. Keys are shown as `_`
+    /// until a formatter is set.
+    #[cfg(feature = "tracing")]
+    pub fn set_key_formatter<F>(&mut self, formatter: F)
+    where
+        F: Fn(&K) -> String + Send + Sync + 'static,
+    {
+        self.key_formatter = Some(Box::new(formatter));
+    }
+
+    #[cfg(feature = "tracing")]
+    fn display_key<'a>(&'a self, key: &'a K) -> DisplayKey<'a, K> {
+        DisplayKey {
+            key,
+            formatter: self.key_formatter.as_ref(),
         }
     }
+
+    /// Limits how long an insert waits for each [`Backpressure::Block`] observer with a full
+    /// channel, [`DEFAULT_SEND_TIMEOUT`] by default. Observers that don't make room within
+    /// `timeout` miss the value, so a stalled observer can't wedge inserts whilst the map is
+    /// locked. A `timeout` of [`Duration::MAX`] waits indefinitely, so that observers never
+    /// miss values but can stall the map.
+    pub fn set_send_timeout(&mut self, timeout: Duration) {
+        self.send_timeout = Some(timeout);
+    }
+
+    /// Keeps the `capacity` most recently inserted values of each key, readable with
+    /// [`last_n`](ObserverMap::last_n), [`history_since`](ObserverMap::history_since) and
+    /// [`get_at`](ObserverMap::get_at).
+    /// Values inserted before the history is enabled aren't recorded.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history_capacity = Some(capacity);
+    }
+
+    /// Runs `interceptor` on every value before it is stored or delivered to observers, after
+    /// any interceptors added before it. An interceptor may modify the value, for example to
+    /// normalise its units, or reject it with an error, failing the insert with
+    /// [`InsertError::Invalid`]. Any error can be returned, a message among them, as in
+    /// `Err("negative price".into())`.
+    pub fn add_interceptor<F>(&mut self, interceptor: F)
+    where
+        F: Fn(&K, &mut V) -> Result<(), Box<dyn core::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.interceptors.push(Box::new(interceptor));
+    }
+
+    /// Resolves every overwrite of a key's value with `resolver`, which is passed the current
+    /// value and the value being inserted and returns the value to store and deliver to
+    /// observers. It runs whilst the map is locked, so concurrent writers can't race.
+    pub fn set_resolver<F>(&mut self, resolver: F)
+    where
+        F: Fn(&V, V) -> V + Send + Sync + 'static,
+    {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    /// Like [`set_resolver`](ObserverMap::set_resolver), but only for `key`, overriding any
+    /// resolver set for the whole map.
+    pub fn set_key_resolver<F>(&mut self, key: K, resolver: F)
+    where
+        K: Hash + Eq,
+        F: Fn(&V, V) -> V + Send + Sync + 'static,
+    {
+        self.key_resolvers.insert(key, Box::new(resolver));
+    }
+
+    /// Removes observers whose receivers have been dropped, returning how many were removed.
+    /// Keys left with neither a value nor observers are removed too.
+    ///
+    /// Dropping a [`Receiver`] unregisters its sender automatically the next time the map is
+    /// written, so this is only needed to reclaim other kinds of observer promptly.
+    pub fn purge_dead_observers(&mut self) -> usize {
+        let mut purged = 0;
+        self.hashmap.retain(|key, item| {
+            let observed = item.observers.is_some();
+            purged += item.purge_dead_observers();
+            if observed && item.observers.is_none() {
+                run_hook(&self.on_last_observer, key);
+            }
+            !item.is_empty()
+        });
+        purged
+    }
 }
 
 impl<K, V> ObservableMap<K, V> for ObserverMap<K, V>
@@ -27,334 +867,3240 @@ where
     K: Hash + Eq + PartialEq,
     V: Clone,
 {
-    fn insert(&mut self, key: K, value: V) -> Result<(), SendError<V>> {
-        match self.hashmap.get_mut(&key) {
-            Some(item) => item.update(value),
-            None => {
-                self.hashmap.insert(key, Item::new(value));
-                Ok(())
-            }
-        }
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, None)
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, Some(group), None)
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        let (report, _) = self.insert_notifying(key, value, None, None)?;
+        Ok(report)
     }
 
     fn get(&self, key: K) -> Option<V> {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_get();
+        }
         match self.hashmap.get(&key) {
-            Some(item) => item.value.clone(),
+            Some(item) => self.read(item).cloned(),
             None => None,
         }
     }
 
-    fn observe(&mut self, key: K) -> Receiver<V> {
-        let (tx, rx) = sync_channel(1);
-        match self.hashmap.get_mut(&key) {
+    #[cfg(os)]
+    fn wait_timeout(&mut self, key: K, timeout: Duration) -> Result<V, WaitError> {
+        let clock = self.clock.clone();
+        wait_timeout_on(self, key, timeout, &clock)
+    }
+
+    #[cfg(os)]
+    fn wait_deadline(&mut self, key: K, deadline: Instant) -> Result<V, WaitError> {
+        let clock = self.clock.clone();
+        let timeout = deadline.saturating_duration_since(clock.now());
+        wait_timeout_on(self, key, timeout, &clock)
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let current = self.get_or_observe(key)?;
+        wait_if_unset(self, current, started)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        self.add_observer(key, Observer::once(tx))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.hashmap.get(&key).map_or(0, Item::observer_count)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.hashmap.values().map(Item::observer_count).sum()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.metrics.clone()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        self.add_observer(key, Observer::stream(tx, backpressure))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::bounded(capacity);
+        self.add_observer(key, Observer::stream(tx, Backpressure::Block))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.add_observer(key, Observer::stream(tx, Backpressure::Block))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        if let Some(value) = self
+            .hashmap
+            .get(&key)
+            .and_then(|item| self.read(item).cloned())
+        {
+            // Can't fail: the receiver is still held.
+            let _ = tx.send(value);
+        }
+        self.add_observer(key, Observer::stream(tx, Backpressure::Block))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(&mut self, key: K, interval: Duration) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        let rx = self.observe_unbounded(key)?;
+        Ok(throttle(rx, interval, self.clock.clone()))
+    }
+
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        let observer = Observer::stream(tx, Backpressure::Block);
+        self.register(
+            key,
+            Registration {
+                group: Some(group.to_string()),
+                ..Registration::new(observer)
+            },
+        )?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.on_update_with_priority(key, 0, callback)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        let observer = Observer::Callback(Mutex::new(Box::new(callback)));
+        self.register(
+            key,
+            Registration {
+                priority,
+                ..Registration::new(observer)
+            },
+        )
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        let observer = Observer::stream(tx, Backpressure::Block);
+        self.register(
+            key,
+            Registration {
+                priority,
+                ..Registration::new(observer)
+            },
+        )?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        let current = self
+            .hashmap
+            .get(&key)
+            .and_then(|item| self.read(item).cloned());
+        let (tx, rx) = tokio::sync::watch::channel(current);
+        self.add_observer(key, Observer::Watch(tx))?;
+        Ok(rx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        if let Some(tx) = self.hashmap.get(&key).and_then(Item::broadcaster) {
+            return Ok(tx.subscribe());
+        }
+        let (tx, rx) = tokio::sync::broadcast::channel(capacity);
+        self.add_observer(key, Observer::Broadcast(tx))?;
+        Ok(rx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.add_observer(key, Observer::Async(tx))?;
+        Ok(AsyncReceiver::new(rx))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        self.add_observer(key, Observer::async_bounded(tx))?;
+        Ok(AsyncReceiver::bounded(rx))
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    // Unregisters the senders of receivers dropped since the last call. Inserts call this after
+    // notifying, so a dead observer of the written key is still counted in the delivery report.
+    fn unregister_dropped(&mut self) {
+        if self.dropped.swap(0, Ordering::Acquire) > 0 {
+            self.purge_dead_observers();
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            strikes: self.slow_observers.as_ref().map(|slow| slow.strikes),
+            send_timeout: self.send_timeout,
+        }
+    }
+
+    // Inserts a value, failing with `Full` if any observer rejects it.
+    fn insert_checked(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<(), InsertError<V>> {
+        match self.insert_notifying(key, value, group, writer)? {
+            (_, Some(rejected)) => Err(InsertError::Full(rejected)),
+            (_, None) => Ok(()),
+        }
+    }
+
+    // Stores a value and notifies the key's observers, in `group` if given, reporting how it
+    // was delivered. If any observer rejected it, a copy of the stored value is returned too.
+    fn insert_notifying(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<(DeliveryReport, Option<V>), InsertError<V>> {
+        if self.closed {
+            return Err(InsertError::Closed(value));
+        }
+        if self.write_once
+            && self
+                .hashmap
+                .get(&key)
+                .is_some_and(|item| self.read(item).is_some())
+        {
+            return Err(InsertError::AlreadySet(value));
+        }
+        let value = self.intercept(&key, value)?;
+        let value = self.resolve(&key, value);
+        #[cfg(feature = "wal")]
+        let value = self.log(&key, value)?;
+        let seq = self.sequence(&key, &value);
+        self.charge_quota(&key, &value);
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.remove(&key);
+        }
+        #[cfg(feature = "tracing")]
+        let _span =
+            ::tracing::debug_span!("insert", key = %self.display_key(&key), group).entered();
+        let limits = self.limits();
+        let delivered = match self.hashmap.get_mut(&key) {
             Some(item) => {
-                item.add_observer(tx);
+                let observed = item.observers.is_some();
+                let report = item.update(value, group, writer, limits, &self.clock);
+                // Only cloned when it must be returned by `insert` in its error.
+                let rejected = (report.rejected > 0).then(|| item.value.clone()).flatten();
+                item.remember(seq, self.history_capacity, &self.clock);
+                item.written(self.idle.as_ref(), &self.clock);
+                if observed && item.observers.is_none() {
+                    run_hook(&self.on_last_observer, &key);
+                }
+                run_after_notify(&self.after_notify, &key, item.value.as_ref(), &report);
+                self.delivered(&key, &report);
+                (report, rejected)
             }
             None => {
-                self.hashmap.insert(key, Item::from_observer(tx));
+                let mut item = Item::new(value, writer, &self.clock);
+                item.remember(seq, self.history_capacity, &self.clock);
+                item.written(self.idle.as_ref(), &self.clock);
+                let report = DeliveryReport::default();
+                run_after_notify(&self.after_notify, &key, item.value.as_ref(), &report);
+                self.delivered(&key, &report);
+                self.hashmap.insert(key, item);
+                (report, None)
             }
+        };
+        self.unregister_dropped();
+        self.expire_idle_if_due();
+        Ok(delivered)
+    }
+
+    // Appends the insert to the write-ahead log, if there is one, before it is applied.
+    #[cfg(feature = "wal")]
+    fn log(&mut self, key: &K, value: V) -> Result<V, InsertError<V>> {
+        match &mut self.wal {
+            Some(wal) => match wal.append(Record::Insert(key, &value)) {
+                Ok(()) => Ok(value),
+                Err(error) => Err(InsertError::Persist(value, error.kind())),
+            },
+            None => Ok(value),
         }
-        rx
     }
 
-    fn wait(&mut self, key: K) -> Result<V, RecvError> {
-        self.observe(key).recv()
-    }
-}
+    // Appends a removal to the write-ahead log, if there is one, before it is applied.
+    #[cfg(feature = "wal")]
+    pub(crate) fn log_removal(&mut self, record: Record<&K, &V>) -> Result<(), RemoveError> {
+        match &mut self.wal {
+            Some(wal) => wal
+                .append(record)
+                .map_err(|error| RemoveError::Persist(error.kind())),
+            None => Ok(()),
+        }
+    }
+
+    fn intercept(&self, key: &K, mut value: V) -> Result<V, InsertError<V>> {
+        for interceptor in &self.interceptors {
+            if let Err(error) = interceptor(key, &mut value) {
+                return Err(InsertError::Invalid(value, Rejection::new(error)));
+            }
+        }
+        Ok(value)
+    }
+
+    // Applies the key's resolver, or else the map's, if the key already has a value.
+    fn resolve(&self, key: &K, value: V) -> V {
+        let current = self.hashmap.get(key).and_then(|item| self.read(item));
+        let resolver = self.key_resolvers.get(key).or(self.resolver.as_ref());
+        match (current, resolver) {
+            (Some(current), Some(resolver)) => resolver(current, value),
+            _ => value,
+        }
+    }
+
+    // Numbers the insert, recording it in the journal and publishing it to event subscribers.
+    fn sequence(&mut self, key: &K, value: &V) -> u64 {
+        self.sequence_change(key, || Change::Inserted(value.clone()))
+    }
+
+    // Numbers a change, recording it in the journal and publishing it to event subscribers.
+    // `change` is only called if either needs it.
+    fn sequence_change(&mut self, key: &K, change: impl Fn() -> Change<V>) -> u64 {
+        sequence_change(
+            &mut self.seq,
+            &mut self.journal,
+            &mut self.subscribers,
+            key,
+            change,
+        )
+    }
+
+    /// The key's current value along with its version, the number of values inserted for it.
+    /// Pass the version to [`insert_if_version`](ObserverMap::insert_if_version) to update
+    /// the value only if nobody else has in the meantime.
+    pub fn get_versioned(&self, key: K) -> Option<(V, u64)> {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_get();
+        }
+        let item = self.hashmap.get(&key)?;
+        Some((self.read(item)?.clone(), item.writes))
+    }
+
+    /// Inserts a value only if the key is still at `expected_version`, returning its new
+    /// version. Fails with [`InsertError::VersionConflict`] if it has been written since. A key
+    /// that has never been written is at version 0. Once the value is written the new version
+    /// is returned, even if a [`Backpressure::Error`] observer had no room for it, so that a
+    /// caller retrying on failure doesn't see its own write as a conflict.
+    pub fn insert_if_version(
+        &mut self,
+        key: K,
+        value: V,
+        expected_version: u64,
+    ) -> Result<u64, InsertError<V>> {
+        if self.closed {
+            return Err(InsertError::Closed(value));
+        }
+        let version = self.hashmap.get(&key).map_or(0, |item| item.writes);
+        if version != expected_version {
+            return Err(InsertError::VersionConflict(value, version));
+        }
+        self.insert_reporting(key, value)?;
+        Ok(version + 1)
+    }
+
+    /// Up to `n` of the key's most recently inserted values, oldest first. The last is the
+    /// current value. Empty unless the history is enabled with
+    /// [`enable_history`](ObserverMap::enable_history).
+    pub fn last_n(&self, key: K, n: usize) -> Vec<V> {
+        let Some(item) = self.hashmap.get(&key) else {
+            return Vec::new();
+        };
+        let Some(history) = item.history() else {
+            return Vec::new();
+        };
+        let skip = history.len().saturating_sub(n);
+        history
+            .iter()
+            .skip(skip)
+            .map(|past| past.value.clone())
+            .collect()
+    }
+
+    /// The key's retained values inserted with sequence number `seq` onwards, oldest first,
+    /// along with their sequence numbers.
+    pub fn history_since(&self, key: K, seq: u64) -> Vec<(u64, V)> {
+        let Some(item) = self.hashmap.get(&key) else {
+            return Vec::new();
+        };
+        item.history()
+            .into_iter()
+            .flatten()
+            .filter(|past| past.seq >= seq)
+            .map(|past| (past.seq, past.value.clone()))
+            .collect()
+    }
+
+    /// The value the key had at `time`, if it is within the retained history. `None` if the
+    /// key had no value then, or if the values around `time` have been evicted.
+    pub fn get_at(&self, key: K, time: SystemTime) -> Option<V> {
+        let history = self.hashmap.get(&key)?.history()?;
+        let after = history.partition_point(|past| past.at <= time);
+        let past = history.get(after.checked_sub(1)?)?;
+        Some(past.value.clone())
+    }
+
+    /// How often the key has been updated, or `None` if it never has. A key whose upstream has
+    /// died shows a growing [`idle`](KeyStats::idle) time and a decaying rate.
+    pub fn key_stats(&self, key: K) -> Option<KeyStats> {
+        let item = self.hashmap.get(&key)?;
+        Some(item.rate.as_ref()?.stats(item.writes, &self.clock))
+    }
+
+    /// Estimates the map's memory use, counting only the inline size of each value. See
+    /// [`approx_memory_usage_with`](ObserverMap::approx_memory_usage_with) for values that own
+    /// heap memory.
+    pub fn approx_memory_usage(&self) -> MemoryUsage {
+        self.approx_memory_usage_with(|_| 0)
+    }
+
+    /// Estimates the map's memory use, adding `heap_size` of every current and retained value
+    /// to its inline size.
+    pub fn approx_memory_usage_with<F>(&self, heap_size: F) -> MemoryUsage
+    where
+        F: Fn(&V) -> usize,
+    {
+        let table = self.hashmap.capacity() * mem::size_of::<(K, Item<V>)>();
+        MemoryUsage {
+            entries: self.hashmap.len(),
+            observers: self.total_observers(),
+            bytes: self
+                .hashmap
+                .values()
+                .map(|item| item.heap_size(&heap_size))
+                .fold(table, usize::saturating_add),
+        }
+    }
+
+    /// The sequence number that will be given to the next insert.
+    pub fn next_seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Applies a change received from another map's [`events`](ObserverMap::events), notifying
+    /// this map's observers as if it had been inserted directly. The change keeps its sequence
+    /// number, so this map's own events and journal can feed further replicas. Changes numbered
+    /// below [`next_seq`](ObserverMap::next_seq) have already been applied and are ignored, so
+    /// a replica can resume from an overlapping stream.
+    pub fn apply_event(&mut self, event: MapEvent<K, V>) -> Result<(), ApplyError<V>> {
+        if event.seq < self.seq {
+            return Ok(());
+        }
+        self.seq = event.seq;
+        match event.change {
+            Change::Inserted(value) => Ok(self.insert(event.key, value)?),
+            Change::Evicted => {
+                self.evict(&event.key);
+                Ok(())
+            }
+            Change::Removed => {
+                self.remove(event.key)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The journalled changes numbered `seq` onwards, oldest first, so a component that has
+    /// applied every change before `seq` can catch up. Fails if the map isn't keeping a
+    /// journal, or if some of those changes have already been evicted from it.
+    pub fn journal_since(&self, seq: u64) -> Result<Vec<MapEvent<K, V>>, JournalError> {
+        match &self.journal {
+            Some(journal) => journal.since(seq, self.seq),
+            None => Err(JournalError::Disabled),
+        }
+    }
+
+    // Records the outcome of notifying the observers of `key` of an insert.
+    fn delivered(&self, key: &K, report: &DeliveryReport) {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(
+            notified = report.notified,
+            disconnected = report.disconnected,
+            dropped = report.dropped,
+            rejected = report.rejected,
+            timed_out = report.timed_out,
+            evicted = report.evicted,
+            "notified observers"
+        );
+        if let Some(slow) = &self.slow_observers {
+            slow.evicted(key, report.evicted);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_insert(report);
+        }
+    }
+
+    // The key's current value, or else a receiver for its next one.
+    #[cfg(os)]
+    fn get_or_observe(&mut self, key: K) -> Result<Result<V, Receiver<V>>, ObserveError> {
+        match self
+            .hashmap
+            .get(&key)
+            .and_then(|item| self.read(item).cloned())
+        {
+            Some(value) => Ok(Ok(value)),
+            None => self.observe(key).map(Err),
+        }
+    }
+
+    fn add_observer(&mut self, key: K, observer: Observer<V>) -> Result<(), ObserveError> {
+        self.register(key, Registration::new(observer))
+    }
+
+    fn register(&mut self, key: K, mut registration: Registration<V>) -> Result<(), ObserveError> {
+        if self.closed {
+            return Err(ObserveError::Closed);
+        }
+        registration.gauge = self.metrics.clone().map(ObserverGauge::new);
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!(
+            key = %self.display_key(&key),
+            priority = registration.priority,
+            group = registration.group.as_deref(),
+            "observer registered"
+        );
+        self.unregister_dropped();
+        match self.hashmap.get_mut(&key) {
+            Some(item) => {
+                if let Some(limit) = self.observer_limit {
+                    item.purge_dead_observers();
+                    if item.observers.as_ref().map_or(0, Vec::len) >= limit {
+                        return Err(ObserveError::TooManyObservers);
+                    }
+                }
+                if item.observers.is_none() {
+                    run_hook(&self.on_first_observer, &key);
+                }
+                item.register(registration);
+            }
+            None => {
+                run_hook(&self.on_first_observer, &key);
+                self.hashmap
+                    .insert(key, Item::from_registration(registration));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// A copy of every key's current value. Observers are unaffected, and keys that are
+    /// observed but have no value yet are omitted.
+    pub fn snapshot(&self) -> HashMap<K, V> {
+        let mut snapshot =
+            HashMap::with_capacity_and_hasher(self.hashmap.len(), Default::default());
+        self.snapshot_into(&mut snapshot);
+        snapshot
+    }
+
+    /// Like [`snapshot`](ObserverMap::snapshot), but extends `into` with the key-value pairs.
+    pub fn snapshot_into(&self, into: &mut impl Extend<(K, V)>) {
+        into.extend(self.hashmap.iter().filter_map(|(key, item)| {
+            let value = self.read(item)?;
+            Some((key.clone(), value.clone()))
+        }));
+    }
+
+    /// Subscribes to every subsequent change to the map, in order, for example to maintain a
+    /// replica with [`apply_event`](ObserverMap::apply_event).
+    pub fn events(&mut self) -> Receiver<MapEvent<K, V>> {
+        let (tx, rx) = channel::unbounded();
+        self.subscribe(Observer::stream(tx, Backpressure::Block));
+        rx
+    }
+
+    /// Like [`events`](ObserverMap::events), but holds at most `capacity` changes the receiver
+    /// hasn't received. A receiver that falls further behind is unsubscribed: it receives the
+    /// changes already held, then reports that it is disconnected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn events_with_capacity(&mut self, capacity: usize) -> Receiver<MapEvent<K, V>> {
+        let (tx, rx) = channel::bounded(capacity);
+        self.subscribe(Observer::stream(tx, Backpressure::Error));
+        rx
+    }
+
+    /// Like [`events`](ObserverMap::events), but received asynchronously.
+    #[cfg(feature = "tokio")]
+    pub fn events_async(&mut self) -> AsyncReceiver<MapEvent<K, V>> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.subscribe(Observer::Async(tx));
+        AsyncReceiver::new(rx)
+    }
+
+    /// Like [`events_with_capacity`](ObserverMap::events_with_capacity), but received
+    /// asynchronously.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    #[cfg(feature = "tokio")]
+    pub fn events_async_with_capacity(&mut self, capacity: usize) -> AsyncReceiver<MapEvent<K, V>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        self.subscribe(Observer::async_bounded(tx));
+        AsyncReceiver::bounded(rx)
+    }
+
+    fn subscribe(&mut self, observer: Observer<MapEvent<K, V>>) {
+        // A closed map makes no more changes, so its subscribers are disconnected at once.
+        if self.closed {
+            return;
+        }
+        self.subscribers
+            .get_or_insert_with(EventSubscribers::new)
+            .subscribe(observer)
+    }
+
+    /// Keeps a journal of the most recent `capacity` inserts, readable with
+    /// [`journal_since`](ObserverMap::journal_since). Replaces any existing journal.
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone + MergeableValue,
+{
+    /// Merges `value` into the key's current value, if it has one, and inserts the result,
+    /// notifying observers of the merged value. Succeeds once the merged value is written,
+    /// even if a [`Backpressure::Error`] observer had no room for it, so that a caller
+    /// retrying on failure doesn't merge the value twice.
+    pub fn insert_merge(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        let merged = match self.hashmap.get(&key).and_then(|item| self.read(item)) {
+            Some(current) => {
+                let mut merged = current.clone();
+                merged.merge(value);
+                merged
+            }
+            None => value,
+        };
+        self.insert_reporting(key, merged)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "wal")]
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + serde::Serialize + serde::de::DeserializeOwned,
+    V: Clone + serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Opens a map backed by the write-ahead log at `path`, creating the log if it doesn't
+    /// exist. The map starts with the values recorded in the log, and every subsequent insert
+    /// is appended and synced to the log before it is applied, failing with
+    /// [`InsertError::Persist`] if it can't be. [Removals](ObserverMap::remove), clearing the
+    /// map and [idle expiry](ObserverMap::expire_after_idle) are logged the same way, so
+    /// removed values aren't restored when the log is replayed. Values evicted by a
+    /// [memory quota](ObserverMap::set_memory_quota) aren't logged, since setting the quota
+    /// on the reopened map evicts them again.
+    pub fn open_with_wal(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::from_wal(wal::open(path.as_ref())?))
+    }
+
+    /// Like [`open_with_wal`](ObserverMap::open_with_wal), but every record is encrypted with
+    /// `key` so that values aren't written to disk in plaintext.
+    #[cfg(feature = "encryption")]
+    pub fn open_with_encrypted_wal(
+        path: impl AsRef<std::path::Path>,
+        key: EncryptionKey,
+    ) -> std::io::Result<Self> {
+        Ok(Self::from_wal(wal::open_encrypted(path.as_ref(), key)?))
+    }
+
+    fn from_wal((records, wal): wal::Replay<K, V>) -> Self {
+        let mut map = Self::new();
+        // Can't fail: the map is open, has no observers and isn't logging the replay.
+        for record in records {
+            match record {
+                Record::Insert(key, value) => {
+                    let _ = map.insert(key, value);
+                }
+                Record::Remove(key) => {
+                    let _ = map.remove(key);
+                }
+                Record::Clear => {
+                    let _ = map.clear();
+                }
+            }
+        }
+        map.wal = Some(wal);
+        map
+    }
+}
+
+impl<K, V> Default for ObserverMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a value by reference, as `map["key"]`.
+///
+/// # Panics
+///
+/// Panics if the key has no value.
+impl<K, Q, V> Index<&Q> for ObserverMap<K, V>
+where
+    K: Hash + Eq + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.hashmap
+            .get(key)
+            .and_then(|item| self.read(item))
+            .expect("no value for key")
+    }
+}
+
+#[derive(Clone)]
+pub struct ThreadSafeObserverMap<K, V> {
+    inner: sync::Arc<RwLock<ObserverMap<K, V>>>,
+    leases: Arc<Leases<K>>,
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V> {
+    pub fn new() -> Self {
+        Self::from(ObserverMap::new())
+    }
+
+    /// See [`ObserverMap::with_capacity`].
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from(ObserverMap::with_capacity(capacity))
+    }
+
+    /// See [`ObserverMap::capacity`].
+    pub fn capacity(&self) -> usize {
+        self.read().capacity()
+    }
+
+    /// See [`ObserverMap::reserve`].
+    pub fn reserve(&self, additional: usize)
+    where
+        K: Hash + Eq,
+    {
+        self.write().reserve(additional)
+    }
+
+    /// See [`ObserverMap::shrink_to_fit`].
+    pub fn shrink_to_fit(&self)
+    where
+        K: Hash + Eq,
+    {
+        self.write().shrink_to_fit()
+    }
+
+    /// See [`ObserverMap::with_observer_limit`].
+    pub fn with_observer_limit(limit: usize) -> Self {
+        Self::from(ObserverMap::with_observer_limit(limit))
+    }
+
+    /// See [`ObserverMap::write_once`].
+    pub fn write_once() -> Self {
+        Self::from(ObserverMap::write_once())
+    }
+
+    /// Closes the map for every handle. See [`ObserverMap::close`].
+    pub fn close(&self) {
+        self.write().close()
+    }
+
+    /// See [`ObserverMap::evict_slow_observers`].
+    pub fn evict_slow_observers<F>(&self, strikes: u32, on_evict: F)
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.write().evict_slow_observers(strikes, on_evict)
+    }
+
+    /// See [`ObserverMap::set_metrics`].
+    pub fn set_metrics(&self, metrics: Arc<Metrics>) {
+        self.write().set_metrics(metrics)
+    }
+
+    /// See [`ObserverMap::set_clock`].
+    #[cfg(feature = "mock-clock")]
+    pub fn set_clock(&self, clock: MockClock) {
+        self.write().set_clock(clock)
+    }
+
+    /// See [`ObserverMap::set_key_formatter`].
+    #[cfg(feature = "tracing")]
+    pub fn set_key_formatter<F>(&self, formatter: F)
+    where
+        F: Fn(&K) -> String + Send + Sync + 'static,
+    {
+        self.write().set_key_formatter(formatter)
+    }
+
+    /// See [`ObserverMap::on_first_observer`].
+    pub fn on_first_observer<F>(&self, hook: F)
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.write().on_first_observer(hook)
+    }
+
+    /// See [`ObserverMap::on_last_observer`].
+    pub fn on_last_observer<F>(&self, hook: F)
+    where
+        F: FnMut(&K) + Send + 'static,
+    {
+        self.write().on_last_observer(hook)
+    }
+
+    /// See [`ObserverMap::after_notify`].
+    pub fn after_notify<F>(&self, hook: F)
+    where
+        F: FnMut(&K, &V, &DeliveryReport) + Send + 'static,
+    {
+        self.write().after_notify(hook)
+    }
+
+    /// See [`ObserverMap::set_send_timeout`].
+    pub fn set_send_timeout(&self, timeout: Duration) {
+        self.write().set_send_timeout(timeout)
+    }
+
+    /// See [`ObserverMap::enable_history`].
+    pub fn enable_history(&self, capacity: usize) {
+        self.write().enable_history(capacity)
+    }
+
+    /// See [`ObserverMap::add_interceptor`].
+    pub fn add_interceptor<F>(&self, interceptor: F)
+    where
+        F: Fn(&K, &mut V) -> Result<(), Box<dyn core::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.write().add_interceptor(interceptor)
+    }
+
+    /// See [`ObserverMap::set_resolver`].
+    pub fn set_resolver<F>(&self, resolver: F)
+    where
+        F: Fn(&V, V) -> V + Send + Sync + 'static,
+    {
+        self.write().set_resolver(resolver)
+    }
+
+    /// See [`ObserverMap::set_key_resolver`].
+    pub fn set_key_resolver<F>(&self, key: K, resolver: F)
+    where
+        K: Hash + Eq,
+        F: Fn(&V, V) -> V + Send + Sync + 'static,
+    {
+        self.write().set_key_resolver(key, resolver)
+    }
+
+    /// See [`ObserverMap::purge_dead_observers`].
+    pub fn purge_dead_observers(&self) -> usize {
+        self.write().purge_dead_observers()
+    }
+
+    // The map is left consistent even if a caller panics whilst holding the lock, so recover
+    // from poisoning rather than propagating one caller's panic to every other handle.
+    fn read(&self) -> RwLockReadGuard<'_, ObserverMap<K, V>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, ObserverMap<K, V>> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Locks the map to write `key`, once no `KeyGuard` holds it.
+    fn write_unleased(&self, key: &K) -> RwLockWriteGuard<'_, ObserverMap<K, V>>
+    where
+        K: Hash + Eq,
+    {
+        let _leases = self.leases.wait_until_free(key);
+        self.write()
+    }
+}
+
+impl<K, V> From<ObserverMap<K, V>> for ThreadSafeObserverMap<K, V> {
+    fn from(map: ObserverMap<K, V>) -> Self {
+        Self {
+            inner: sync::Arc::new(RwLock::new(map)),
+            leases: Arc::new(Leases::new()),
+        }
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// A consistent copy of every key's current value, taken under a single read lock so no
+    /// concurrent insert is partially reflected. See [`ObserverMap::snapshot`].
+    pub fn snapshot(&self) -> HashMap<K, V> {
+        self.read().snapshot()
+    }
+
+    /// See [`ThreadSafeObserverMap::snapshot`] and [`ObserverMap::snapshot_into`].
+    pub fn snapshot_into(&self, into: &mut impl Extend<(K, V)>) {
+        self.read().snapshot_into(into)
+    }
+
+    /// The keys that have values, copied under a single read lock, so they can be iterated
+    /// without holding the lock while other threads insert.
+    pub fn keys_snapshot(&self) -> Vec<K> {
+        let map = self.read();
+        map.hashmap
+            .iter()
+            .filter(|(_, item)| map.read(item).is_some())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Iterates over a [`snapshot`](ThreadSafeObserverMap::snapshot), so the map isn't locked
+    /// while the caller's code runs, and no insert is partially reflected.
+    pub fn iter_snapshot(&self) -> crate::collections::hash_map::IntoIter<K, V> {
+        self.snapshot().into_iter()
+    }
+
+    /// See [`ObserverMap::events`].
+    pub fn events(&self) -> Receiver<MapEvent<K, V>> {
+        self.write().events()
+    }
+
+    /// See [`ObserverMap::events_with_capacity`].
+    pub fn events_with_capacity(&self, capacity: usize) -> Receiver<MapEvent<K, V>> {
+        self.write().events_with_capacity(capacity)
+    }
+
+    /// See [`ObserverMap::events_async`].
+    #[cfg(feature = "tokio")]
+    pub fn events_async(&self) -> AsyncReceiver<MapEvent<K, V>> {
+        self.write().events_async()
+    }
+
+    /// See [`ObserverMap::events_async_with_capacity`].
+    #[cfg(feature = "tokio")]
+    pub fn events_async_with_capacity(&self, capacity: usize) -> AsyncReceiver<MapEvent<K, V>> {
+        self.write().events_async_with_capacity(capacity)
+    }
+
+    /// See [`ObserverMap::enable_journal`].
+    pub fn enable_journal(&self, capacity: usize) {
+        self.write().enable_journal(capacity)
+    }
+
+    /// See [`ObserverMap::get_versioned`].
+    pub fn get_versioned(&self, key: K) -> Option<(V, u64)> {
+        self.read().get_versioned(key)
+    }
+
+    /// See [`ObserverMap::insert_if_version`].
+    pub fn insert_if_version(
+        &self,
+        key: K,
+        value: V,
+        expected_version: u64,
+    ) -> Result<u64, InsertError<V>> {
+        self.write_unleased(&key)
+            .insert_if_version(key, value, expected_version)
+    }
+
+    /// See [`ObserverMap::last_n`].
+    pub fn last_n(&self, key: K, n: usize) -> Vec<V> {
+        self.read().last_n(key, n)
+    }
+
+    /// See [`ObserverMap::history_since`].
+    pub fn history_since(&self, key: K, seq: u64) -> Vec<(u64, V)> {
+        self.read().history_since(key, seq)
+    }
+
+    /// See [`ObserverMap::get_at`].
+    pub fn get_at(&self, key: K, time: SystemTime) -> Option<V> {
+        self.read().get_at(key, time)
+    }
+
+    /// See [`ObserverMap::key_stats`].
+    pub fn key_stats(&self, key: K) -> Option<KeyStats> {
+        self.read().key_stats(key)
+    }
+
+    /// See [`ObserverMap::approx_memory_usage`].
+    pub fn approx_memory_usage(&self) -> MemoryUsage {
+        self.read().approx_memory_usage()
+    }
+
+    /// See [`ObserverMap::approx_memory_usage_with`].
+    pub fn approx_memory_usage_with<F>(&self, heap_size: F) -> MemoryUsage
+    where
+        F: Fn(&V) -> usize,
+    {
+        self.read().approx_memory_usage_with(heap_size)
+    }
+
+    /// See [`ObserverMap::next_seq`].
+    pub fn next_seq(&self) -> u64 {
+        self.read().next_seq()
+    }
+
+    /// See [`ObserverMap::apply_event`].
+    pub fn apply_event(&self, event: MapEvent<K, V>) -> Result<(), ApplyError<V>> {
+        self.write_unleased(&event.key).apply_event(event)
+    }
+
+    /// See [`ObserverMap::journal_since`].
+    pub fn journal_since(&self, seq: u64) -> Result<Vec<MapEvent<K, V>>, JournalError> {
+        self.read().journal_since(seq)
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone + MergeableValue,
+{
+    /// See [`ObserverMap::insert_merge`]. The merge happens under the map's lock, so
+    /// concurrent merges are never lost.
+    pub fn insert_merge(&self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.write_unleased(&key).insert_merge(key, value)
+    }
+}
+
+#[cfg(feature = "wal")]
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + serde::Serialize + serde::de::DeserializeOwned,
+    V: Clone + serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// See [`ObserverMap::open_with_wal`].
+    pub fn open_with_wal(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::from(ObserverMap::open_with_wal(path)?))
+    }
+
+    /// See [`ObserverMap::open_with_encrypted_wal`].
+    #[cfg(feature = "encryption")]
+    pub fn open_with_encrypted_wal(
+        path: impl AsRef<std::path::Path>,
+        key: EncryptionKey,
+    ) -> std::io::Result<Self> {
+        Ok(Self::from(ObserverMap::open_with_encrypted_wal(path, key)?))
+    }
+}
+
+impl<K, V> ObservableMap<K, V> for ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.write_unleased(&key).insert(key, value)
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        self.write_unleased(&key).insert_reporting(key, value)
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>> {
+        self.write_unleased(&key)
+            .insert_for_group(key, value, group)
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        self.read().get(key)
+    }
+
+    #[cfg(os)]
+    fn wait_timeout(&mut self, key: K, timeout: Duration) -> Result<V, WaitError> {
+        let clock = self.read().clock.clone();
+        wait_timeout_on(self, key, timeout, &clock)
+    }
+
+    #[cfg(os)]
+    fn wait_deadline(&mut self, key: K, deadline: Instant) -> Result<V, WaitError> {
+        let clock = self.read().clock.clone();
+        let timeout = deadline.saturating_duration_since(clock.now());
+        wait_timeout_on(self, key, timeout, &clock)
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let current = self.write().get_or_observe(key)?;
+        wait_if_unset(self, current, started)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.read().is_closed()
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.read().observer_count(key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.read().total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.read().metrics()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_with_backpressure(key, backpressure)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_with_capacity(key, capacity)
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_unbounded(key)
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_with_initial(key)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(&mut self, key: K, interval: Duration) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        self.write().observe_throttled(key, interval)
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.write().on_update(key, callback)
+    }
+
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_group(key, group)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.write()
+            .on_update_with_priority(key, priority, callback)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.write().observe_with_priority(key, priority)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        self.write().watch(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        self.write().broadcast(key, capacity)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.write().observe_async(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.write().observe_async_with_capacity(key, capacity)
+    }
+}
+
+impl<K, V> Default for ThreadSafeObserverMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Forwards values from `source`, conflating updates so that at most one value is sent per
+// `interval`. The forwarding thread exits once either end of the pipeline is dropped.
+#[cfg(os)]
+fn throttle<T>(source: Receiver<T>, interval: Duration, clock: Clock) -> Receiver<T>
+where
+    T: Send + 'static,
+{
+    let (tx, rx) = channel::bounded(1);
+    thread::spawn(move || {
+        let mut next_send = clock.now();
+        while let Ok(mut value) = source.recv() {
+            while let Some(remaining) = next_send.checked_duration_since(clock.now()) {
+                match clock.recv_timeout(&source, remaining) {
+                    Ok(next) => value = next,
+                    Err(_) => break,
+                }
+            }
+            while let Ok(next) = source.try_recv() {
+                value = next;
+            }
+            if tx.send(value).is_err() {
+                return;
+            }
+            next_send = clock.now() + interval;
+        }
+    });
+    rx
+}
+
+struct Item<T> {
+    value: Option<T>,
+    // Ordered by descending priority, then by registration.
+    observers: Option<Vec<Registration<T>>>,
+    writes: u64,
+    // `None` until the key is first written.
+    rate: Option<UpdateRate>,
+    // Allocated only once the key needs it.
+    extras: Option<Box<Extras<T>>>,
+}
+
+// State most keys never use, kept out of line to keep maps of many keys small.
+struct Extras<T> {
+    // The writer of the current value, if known.
+    writer: Option<Arc<str>>,
+    // Recently inserted values, oldest first.
+    history: VecDeque<Past<T>>,
+    // When the item was last read or written, if the map expires idle values.
+    accessed: AtomicU64,
+}
+
+impl<T> Item<T>
+where
+    T: Clone,
+{
+    fn new(value: T, writer: Option<&str>, clock: &Clock) -> Self {
+        Self {
+            value: Some(value),
+            observers: None,
+            writes: 1,
+            rate: HAS_CLOCK.then(|| UpdateRate::new(clock)),
+            extras: writer.map(|writer| {
+                Box::new(Extras {
+                    writer: Some(Arc::from(writer)),
+                    history: VecDeque::new(),
+                    accessed: AtomicU64::new(0),
+                })
+            }),
+        }
+    }
+
+    fn from_registration(registration: Registration<T>) -> Self {
+        Self {
+            value: None,
+            observers: Some(vec![registration]),
+            writes: 0,
+            rate: None,
+            extras: None,
+        }
+    }
+
+    fn update(
+        &mut self,
+        value: T,
+        group: Option<&str>,
+        writer: Option<&str>,
+        limits: Limits,
+        clock: &Clock,
+    ) -> DeliveryReport {
+        self.set_writer(writer);
+        self.writes += 1;
+        match &mut self.rate {
+            Some(rate) => rate.record(clock),
+            None if HAS_CLOCK => self.rate = Some(UpdateRate::new(clock)),
+            None => {}
+        }
+        let report = self.notify(&value, group, limits, clock);
+        self.value = Some(value);
+        report
+    }
+
+    // Records the current value, inserted with sequence number `seq`, in the history.
+    fn remember(&mut self, seq: u64, capacity: Option<usize>, clock: &Clock) {
+        let (Some(capacity), Some(value)) = (capacity, &self.value) else {
+            return;
+        };
+        if capacity == 0 || !HAS_CLOCK {
+            return;
+        }
+        let past = Past {
+            seq,
+            at: clock.system_now(),
+            value: value.clone(),
+        };
+        let history = &mut self.extras_mut().history;
+        if history.len() == capacity {
+            history.pop_front();
+        }
+        history.push_back(past);
+    }
+
+    fn register(&mut self, registration: Registration<T>) {
+        let observers = self.observers.get_or_insert_with(Vec::new);
+        let index =
+            observers.partition_point(|existing| existing.priority >= registration.priority);
+        observers.insert(index, registration);
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcaster(&self) -> Option<&tokio::sync::broadcast::Sender<T>> {
+        self.observers
+            .iter()
+            .flatten()
+            .find_map(|registration| match &registration.observer {
+                Observer::Broadcast(tx) => Some(tx),
+                _ => None,
+            })
+    }
+
+    // Delivers the value to each live observer whose filter accepts it, limited to the members
+    // of `group` if one is given, carrying on if some deliveries fail. Observers that are
+    // disconnected, only wanted a single value, or have failed to keep up with too many
+    // consecutive updates are pruned.
+    fn notify(
+        &mut self,
+        value: &T,
+        group: Option<&str>,
+        limits: Limits,
+        clock: &Clock,
+    ) -> DeliveryReport {
+        let mut report = DeliveryReport::default();
+        let writer = self
+            .extras
+            .as_ref()
+            .and_then(|extras| extras.writer.as_ref());
+        let previous = self.value.as_ref();
+        if let Some(observers) = &mut self.observers {
+            let now = HAS_CLOCK.then(|| clock.now());
+            observers.retain_mut(|registration| {
+                if !registration.is_in(group) || !registration.accepts(previous, value) {
+                    return registration.observer.receivers() > 0;
+                }
+                let delivery =
+                    registration
+                        .observer
+                        .notify(previous, value, writer, limits.send_timeout);
+                delivery.record(&mut report);
+                if delivery.lost_value() {
+                    registration.dropped += 1;
+                }
+                if delivery.is_delivered() {
+                    registration.delivered = now;
+                }
+                registration.strikes = if delivery.is_slow() {
+                    registration.strikes + 1
+                } else {
+                    0
+                };
+                if limits
+                    .strikes
+                    .is_some_and(|strikes| registration.strikes >= strikes)
+                {
+                    report.evicted += 1;
+                    return false;
+                }
+                registration.observer.is_subscribed()
+            });
+            if observers.is_empty() {
+                self.observers = None;
+            }
+        }
+        report
+    }
+}
+
+impl<T> Item<T> {
+    // An item with neither a value nor observers.
+    fn empty() -> Self {
+        Self {
+            value: None,
+            observers: None,
+            writes: 0,
+            rate: None,
+            extras: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.observers.is_none()
+    }
+
+    fn shrink_to_fit(&mut self) {
+        if let Some(observers) = &mut self.observers {
+            observers.shrink_to_fit();
+        }
+        if let Some(extras) = &mut self.extras {
+            extras.history.shrink_to_fit();
+        }
+    }
+
+    fn writer(&self) -> Option<&Arc<str>> {
+        self.extras.as_ref()?.writer.as_ref()
+    }
+
+    fn set_writer(&mut self, writer: Option<&str>) {
+        match (writer, &mut self.extras) {
+            (Some(writer), _) => self.extras_mut().writer = Some(Arc::from(writer)),
+            (None, Some(extras)) => extras.writer = None,
+            (None, None) => {}
+        }
+    }
+
+    fn history(&self) -> Option<&VecDeque<Past<T>>> {
+        self.extras.as_ref().map(|extras| &extras.history)
+    }
+
+    fn extras_mut(&mut self) -> &mut Extras<T> {
+        self.extras.get_or_insert_with(|| {
+            Box::new(Extras {
+                writer: None,
+                history: VecDeque::new(),
+                accessed: AtomicU64::new(0),
+            })
+        })
+    }
+
+    // The memory the item owns outside its inline size, with `heap_size` of each value.
+    fn heap_size(&self, heap_size: &impl Fn(&T) -> usize) -> usize {
+        let observers = self.observers.as_ref().map_or(0, |observers| {
+            observers.capacity() * mem::size_of::<Registration<T>>()
+        });
+        let extras = self.extras.as_ref().map_or(0, |extras| {
+            let history = extras.history.capacity() * mem::size_of::<Past<T>>();
+            let writer = extras.writer.as_ref().map_or(0, |writer| writer.len());
+            let past = extras.history.iter().map(|past| heap_size(&past.value));
+            mem::size_of::<Extras<T>>() + history + writer + past.sum::<usize>()
+        });
+        self.value.as_ref().map_or(0, heap_size) + observers + extras
+    }
+
+    fn observer_count(&self) -> usize {
+        self.observers
+            .iter()
+            .flatten()
+            .map(|registration| registration.observer.receivers())
+            .sum()
+    }
+
+    fn purge_dead_observers(&mut self) -> usize {
+        let Some(observers) = &mut self.observers else {
+            return 0;
+        };
+        let before = observers.len();
+        observers.retain(|registration| registration.observer.receivers() > 0);
+        let purged = before - observers.len();
+        if observers.is_empty() {
+            self.observers = None;
+        }
+        purged
+    }
+}
+
+// A value that was inserted at `at` with sequence number `seq`.
+struct Past<T> {
+    seq: u64,
+    at: SystemTime,
+    value: T,
+}
+
+// Per-map limits applied whilst notifying observers.
+#[derive(Clone, Copy)]
+struct Limits {
+    strikes: Option<u32>,
+    send_timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            strikes: None,
+            send_timeout: HAS_CLOCK.then_some(DEFAULT_SEND_TIMEOUT),
+        }
+    }
+}
+
+struct Registration<T> {
+    observer: Observer<T>,
+    priority: i32,
+    group: Option<String>,
+    // Consecutive updates the observer has failed to keep up with.
+    strikes: u32,
+    gauge: Option<ObserverGauge>,
+    // Dangles once the `ScopeGuard` the observer belongs to is dropped.
+    scope: Option<alloc::sync::Weak<()>>,
+    filter: Option<Filter<T>>,
+    // Values the observer has missed because its channel was full.
+    dropped: u64,
+    delivered: Option<Instant>,
+}
+
+impl<T> Registration<T> {
+    fn new(observer: Observer<T>) -> Self {
+        Self {
+            observer,
+            priority: 0,
+            group: None,
+            strikes: 0,
+            gauge: None,
+            scope: None,
+            filter: None,
+            dropped: 0,
+            delivered: None,
+        }
+    }
+
+    fn is_in(&self, group: Option<&str>) -> bool {
+        group.is_none_or(|group| self.group.as_deref() == Some(group))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    use crate::channel::RecvError;
+
+    use num::bigint::ToBigUint;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn insert_and_get() {
+        let mut map = ObserverMap::new();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(map.get("key".to_string()).unwrap(), 1);
+
+        map.insert("key".to_string(), 2).unwrap();
+        assert_eq!(map.get("key".to_string()).unwrap(), 2);
+
+        map.insert("another_key".to_string(), 3).unwrap();
+        assert_eq!(map.get("another_key".to_string()).unwrap(), 3);
+
+        assert!(map.get("not_a_key".to_string()).is_none());
+    }
+
+    #[test]
+    fn value_is_arbitrary_structs_that_are_copy() {
+        #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+        struct Tmp();
+
+        let mut map = ObserverMap::new();
+        map.insert("is_copy".to_string(), Tmp()).unwrap();
+        assert_eq!(map.get("is_copy".to_string()).unwrap(), Tmp());
+    }
+
+    #[test]
+    fn value_is_arbitrary_precision_decimal_type() {
+        let mut map = ObserverMap::new();
+        map.insert("pi".to_string(), dec!(3.1415926535897932384))
+            .unwrap();
+        assert_eq!(
+            map.get("pi".to_string()).unwrap(),
+            dec!(3.1415926535897932384)
+        );
+    }
+
+    #[test]
+    fn value_is_num_biguint_type() {
+        let mut map = ObserverMap::new();
+        map.insert("key".to_string(), 123.to_biguint()).unwrap();
+        assert_eq!(map.get("key".to_string()).unwrap(), 123.to_biguint(),);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn thread_safe_wait_for_next_value() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        map.insert("key".to_string(), 1u64).unwrap();
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                map.insert("key".to_string(), 2).unwrap();
+            })
+        };
+
+        assert_eq!(map.wait("key".to_string()).unwrap(), 2);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn wait_for_value_of_new_key() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                map.insert("key".to_string(), 2u64).unwrap();
+            })
+        };
+
+        assert_eq!(map.wait("key".to_string()).unwrap(), 2);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn multiple_observers() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        let mut handles = vec![];
+
+        for _ in 1..=4 {
+            let mut map = map.clone();
+            let handle = thread::spawn(move || {
+                assert_eq!(map.wait("key".to_string()).unwrap(), 1);
+            });
+            handles.push(handle);
+        }
+
+        testing::assert_eventually_observed(&map, "key".to_string(), 4, Duration::from_secs(5));
+        map.insert("key".to_string(), 1u8).unwrap();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn wait_for_next_value_multiple_times() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        for v in 1u64..=4 {
+            {
+                let mut map = map.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(100));
+                    map.insert("key".to_string(), v).unwrap();
+                })
+            };
+
+            assert_eq!(map.wait("key".to_string()).unwrap(), v);
+        }
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn next_value_is_the_same_as_current_value() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                map.insert("key".to_string(), 1).unwrap();
+            })
+        };
+
+        assert_eq!(map.wait("key".to_string()).unwrap(), 1);
+    }
+
+    #[cfg(os)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_value_multiple_times_whilst_waiting_for_next_value() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        map.insert("key".to_string(), 1u64).unwrap();
+
+        {
+            let mut map = map.clone();
+            tokio::spawn(async move {
+                assert_eq!(map.wait("key".to_string()).unwrap(), 2);
+            });
+        }
+
+        let mut handles = vec![];
+
+        {
+            for _ in 0..100_000 {
+                let map = map.clone();
+                let handle = tokio::spawn(async move {
+                    assert_eq!(map.get("key".to_string()).unwrap(), 1);
+                });
+                handles.push(handle);
+            }
+        }
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        map.insert("key".to_string(), 2).unwrap();
+    }
+
+    #[test]
+    fn thread_unsafe_channel_closed() {
+        let mut map: ObserverMap<String, u32> = ObserverMap::new();
+
+        let rx = map.observe("key".to_string()).unwrap();
+
+        // Close the channel
+        map.hashmap.get_mut("key").unwrap().observers = None;
+
+        assert_eq!(rx.recv().unwrap_err(), RecvError);
+    }
+
+    #[test]
+    fn thread_safe_channel_closed() {
+        let mut map: ThreadSafeObserverMap<String, u64> = ThreadSafeObserverMap::new();
+
+        let rx = map.observe("key".to_string()).unwrap();
+
+        // Close the channel
+        map.inner
+            .write()
+            .unwrap()
+            .hashmap
+            .get_mut("key")
+            .unwrap()
+            .observers = None;
+
+        assert_eq!(rx.recv().unwrap_err(), RecvError);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn throttled_observer_receives_most_recent_value_per_interval() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        let rx = map
+            .observe_throttled("key".to_string(), Duration::from_millis(200))
+            .unwrap();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+
+        for v in 2..=10 {
+            map.insert("key".to_string(), v).unwrap();
+        }
+
+        assert_eq!(rx.recv().unwrap(), 10);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn throttled_observer_is_removed_when_receiver_is_dropped() {
+        let mut map = ObserverMap::new();
+
+        let rx = map
+            .observe_throttled("key".to_string(), Duration::from_millis(10))
+            .unwrap();
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+        drop(rx);
+
+        map.insert("key".to_string(), 2).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        map.insert("key".to_string(), 3).unwrap();
+
+        assert!(map.hashmap.get("key").unwrap().observers.is_none());
+    }
+
+    #[test]
+    fn backpressure_drop_newest_keeps_queued_value() {
+        let mut map = ObserverMap::new();
+
+        let rx = map
+            .observe_with_backpressure("key".to_string(), Backpressure::DropNewest)
+            .unwrap();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn backpressure_drop_oldest_keeps_latest_value() {
+        let mut map = ObserverMap::new();
+
+        let rx = map
+            .observe_with_backpressure("key".to_string(), Backpressure::DropOldest)
+            .unwrap();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn backpressure_error_fails_insert_when_observer_is_full() {
+        let mut map = ObserverMap::new();
+
+        let rx = map
+            .observe_with_backpressure("key".to_string(), Backpressure::Error)
+            .unwrap();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(map.insert("key".to_string(), 2), Err(InsertError::Full(2)));
+
+        assert_eq!(rx.recv().unwrap(), 1);
+        map.insert("key".to_string(), 3).unwrap();
+        assert_eq!(rx.recv().unwrap(), 3);
+    }
+
+    #[test]
+    fn observer_with_capacity_buffers_burst_of_updates() {
+        let mut map = ObserverMap::new();
+
+        let rx = map.observe_with_capacity("key".to_string(), 4).unwrap();
+
+        for v in 1u32..=4 {
+            map.insert("key".to_string(), v).unwrap();
+        }
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity must be non-zero")]
+    fn observer_with_zero_capacity_panics() {
+        let mut map: ObserverMap<String, u32> = ObserverMap::new();
+        map.observe_with_capacity("key".to_string(), 0).unwrap();
+    }
+
+    #[test]
+    fn unbounded_observer_never_blocks_producer() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        let rx = map.observe_unbounded("key".to_string()).unwrap();
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                for v in 0u32..1_000 {
+                    map.insert("key".to_string(), v).unwrap();
+                }
+            })
+            .join()
+            .unwrap();
+        }
+
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            (0..1_000).collect::<Vec<_>>()
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn watch_starts_with_current_value_and_sees_updates() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        let mut rx = map.watch("key".to_string()).unwrap();
+        assert_eq!(*rx.borrow_and_update(), Some(1));
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                map.insert("key".to_string(), 2).unwrap();
+            })
+        };
+
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow(), Some(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn watch_is_removed_when_receiver_is_dropped() {
+        let mut map = ObserverMap::new();
+
+        let rx = map.watch("key".to_string()).unwrap();
+        drop(rx);
+        map.insert("key".to_string(), 1u32).unwrap();
+
+        assert!(map.hashmap.get("key").unwrap().observers.is_none());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn broadcast_subscribers_share_one_observer() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        let mut first = map.broadcast("key".to_string(), 4).unwrap();
+        let mut second = map.broadcast("key".to_string(), 4).unwrap();
+        assert_eq!(
+            map.inner
+                .read()
+                .unwrap()
+                .hashmap
+                .get("key")
+                .unwrap()
+                .observers
+                .as_ref()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(map.observer_count("key".to_string()), 2);
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+
+        assert_eq!(first.recv().await.unwrap(), 1);
+        assert_eq!(first.recv().await.unwrap(), 2);
+        assert_eq!(second.recv().await.unwrap(), 1);
+        assert_eq!(second.recv().await.unwrap(), 2);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn broadcast_subscriber_detects_lag() {
+        let mut map = ObserverMap::new();
+
+        let mut rx = map.broadcast("key".to_string(), 2).unwrap();
+
+        for v in 1u32..=4 {
+            map.insert("key".to_string(), v).unwrap();
+        }
+
+        assert_eq!(
+            rx.recv().await,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(2))
+        );
+        assert_eq!(rx.recv().await.unwrap(), 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_receiver_is_cancel_safe_in_select() {
+        let mut map = ThreadSafeObserverMap::new();
+
+        let mut rx = map.observe_async("key".to_string()).unwrap();
+
+        tokio::select! {
+            _ = rx.recv() => panic!("no value has been inserted"),
+            _ = tokio::time::sleep(Duration::from_millis(50)) => {}
+        }
+
+        {
+            let mut map = map.clone();
+            tokio::spawn(async move {
+                map.insert("key".to_string(), 1u32).unwrap();
+                map.insert("key".to_string(), 2).unwrap();
+            });
+        }
+
+        assert_eq!(rx.recv().await, Ok(1));
+        assert_eq!(rx.recv().await, Ok(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_receiver_is_a_stream() {
+        use futures::StreamExt;
+
+        let mut map = ObserverMap::new();
+
+        let rx = map.observe_async("key".to_string()).unwrap();
+        for v in 1u32..=3 {
+            map.insert("key".to_string(), v).unwrap();
+        }
+        drop(map);
+
+        assert_eq!(rx.collect::<Vec<_>>().await, vec![1, 2, 3]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn wait_with_cancel_returns_when_token_is_cancelled() {
+        let mut map: ThreadSafeObserverMap<String, u32> = ThreadSafeObserverMap::new();
+        let token = tokio_util::sync::CancellationToken::new();
+
+        {
+            let token = token.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                token.cancel();
+            })
+        };
+
+        assert_eq!(
+            map.wait_with_cancel("key".to_string(), &token),
+            Err(WaitError::Cancelled)
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn wait_with_cancel_returns_value_inserted_before_cancellation() {
+        let mut map = ThreadSafeObserverMap::new();
+        let token = tokio_util::sync::CancellationToken::new();
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                map.insert("key".to_string(), 1u32).unwrap();
+            })
+        };
+
+        assert_eq!(map.wait_with_cancel("key".to_string(), &token), Ok(1));
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn close_wakes_all_waiters() {
+        let map: ThreadSafeObserverMap<String, u32> = ThreadSafeObserverMap::new();
+
+        let mut handles = vec![];
+
+        for _ in 1..=4 {
+            let mut map = map.clone();
+            let handle = thread::spawn(move || map.wait("key".to_string()));
+            handles.push(handle);
+        }
+
+        testing::assert_eventually_observed(&map, "key".to_string(), 4, Duration::from_secs(5));
+        map.close();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Err(WaitError::Closed));
+        }
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn closed_map_rejects_inserts_and_observers() {
+        let mut map = ObserverMap::new();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        map.close();
+
+        assert!(map.is_closed());
+        assert_eq!(
+            map.insert("key".to_string(), 2),
+            Err(InsertError::Closed(2))
+        );
+        assert_eq!(map.get("key".to_string()), Some(1));
+        assert_eq!(map.wait("key".to_string()), Err(WaitError::Closed));
+        assert_eq!(
+            map.observe("key".to_string()).unwrap_err(),
+            ObserveError::Closed
+        );
+    }
+
+    // Neither loom's locks nor spin locks are poisoned by panics.
+    #[cfg(all(not(loom), feature = "std"))]
+    #[test]
+    fn thread_safe_map_recovers_from_poisoned_lock() {
+        #[derive(PartialEq, Eq, Debug)]
+        struct PanicsOnClone(u32);
+
+        impl Clone for PanicsOnClone {
+            fn clone(&self) -> Self {
+                panic!("clone of {}", self.0);
+            }
+        }
+
+        let mut map = ThreadSafeObserverMap::new();
+        let _rx = map.observe("key".to_string()).unwrap();
+
+        {
+            let mut map = map.clone();
+            thread::spawn(move || map.insert("key".to_string(), PanicsOnClone(1)))
+                .join()
+                .unwrap_err();
+        }
+        assert!(map.inner.is_poisoned());
+
+        assert!(map.get("another_key".to_string()).is_none());
+        map.insert("another_key".to_string(), PanicsOnClone(2))
+            .unwrap();
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn wait_timeout_times_out_without_insert() {
+        let mut map: ThreadSafeObserverMap<String, u32> = ThreadSafeObserverMap::new();
+
+        assert_eq!(
+            map.wait_timeout("key".to_string(), Duration::from_millis(50)),
+            Err(WaitError::Timeout)
+        );
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn wait_timeout_without_a_representable_deadline_waits_forever() {
+        let mut map: ThreadSafeObserverMap<String, u32> = ThreadSafeObserverMap::new();
+        let mut writer = map.clone();
+        let inserter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            writer.insert("key".to_string(), 1).unwrap();
+        });
+
+        assert_eq!(map.wait_timeout("key".to_string(), Duration::MAX), Ok(1));
+        inserter.join().unwrap();
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn wait_spinning_receives_values_sent_during_and_after_the_spin() {
+        let mut map: ThreadSafeObserverMap<String, u32> = ThreadSafeObserverMap::new();
+
+        for delay in [Duration::from_millis(1), Duration::from_millis(50)] {
+            let mut writer = map.clone();
+            let inserter = thread::spawn(move || {
+                thread::sleep(delay);
+                writer.insert("key".to_string(), 1).unwrap();
+            });
+            let spin = Duration::from_millis(20);
+            assert_eq!(map.wait_spinning("key".to_string(), spin), Ok(1));
+            inserter.join().unwrap();
+        }
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn waits_share_one_deadline() {
+        let mut map: ThreadSafeObserverMap<String, u32> = ThreadSafeObserverMap::new();
+        let deadline = Instant::now() + Duration::from_millis(50);
+
+        let mut writer = map.clone();
+        let inserter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            writer.insert("a".to_string(), 1).unwrap();
+        });
+        assert_eq!(map.wait_deadline("a".to_string(), deadline), Ok(1));
+        inserter.join().unwrap();
+        assert_eq!(
+            map.wait_deadline("b".to_string(), deadline),
+            Err(WaitError::Timeout)
+        );
+        assert!(Instant::now() >= deadline);
+        assert_eq!(
+            map.wait_deadline("b".to_string(), deadline),
+            Err(WaitError::Timeout)
+        );
+    }
+
+    #[test]
+    fn insert_skips_disconnected_observers() {
+        let mut map = ObserverMap::new();
+
+        let first = map.observe("key".to_string()).unwrap();
+        drop(map.observe("key".to_string()).unwrap());
+        let third = map.observe("key".to_string()).unwrap();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+
+        assert_eq!(first.recv().unwrap(), 1);
+        assert_eq!(third.recv().unwrap(), 1);
+        assert!(map.hashmap.get("key").unwrap().observers.is_none());
+    }
+
+    #[test]
+    fn rejected_insert_is_still_delivered_to_other_observers() {
+        let mut map = ObserverMap::new();
+
+        let full = map
+            .observe_with_backpressure("key".to_string(), Backpressure::Error)
+            .unwrap();
+        map.insert("key".to_string(), 1u32).unwrap();
+        let rx = map.observe("key".to_string()).unwrap();
+
+        let error = map.insert("key".to_string(), 2).unwrap_err();
+        assert_eq!(error, InsertError::Full(2));
+        assert_eq!(error.into_value(), 2);
+
+        assert_eq!(rx.recv().unwrap(), 2);
+        assert_eq!(full.recv().unwrap(), 1);
+    }
+
+    #[test]
+    fn insert_reporting_summarises_delivery() {
+        let mut map = ObserverMap::new();
+
+        assert_eq!(
+            map.insert_reporting("key".to_string(), 1u32).unwrap(),
+            DeliveryReport::default()
+        );
+
+        let rx = map.observe("key".to_string()).unwrap();
+        let dead = map.observe("key".to_string()).unwrap();
+        let full = map
+            .observe_with_backpressure("key".to_string(), Backpressure::DropNewest)
+            .unwrap();
+        let rejecting = map
+            .observe_with_backpressure("key".to_string(), Backpressure::Error)
+            .unwrap();
+        drop(dead);
+
+        let report = map.insert_reporting("key".to_string(), 2).unwrap();
+        assert_eq!(
+            report,
+            DeliveryReport {
+                notified: 3,
+                disconnected: 1,
+                dropped: 0,
+                rejected: 0,
+                timed_out: 0,
+                evicted: 0,
+            }
+        );
+
+        let report = map.insert_reporting("key".to_string(), 3).unwrap();
+        assert_eq!(
+            report,
+            DeliveryReport {
+                notified: 0,
+                disconnected: 0,
+                dropped: 1,
+                rejected: 1,
+                timed_out: 0,
+                evicted: 0,
+            }
+        );
+        assert_eq!(rx.recv().unwrap(), 2);
+        assert_eq!(full.recv().unwrap(), 2);
+        assert_eq!(rejecting.recv().unwrap(), 2);
+    }
+
+    #[test]
+    fn counts_live_observers() {
+        let mut map = ThreadSafeObserverMap::new();
+        assert_eq!(map.observer_count("key".to_string()), 0);
+
+        let rx = map.observe("key".to_string()).unwrap();
+        let stream = map.observe_unbounded("key".to_string()).unwrap();
+        let _other = map.observe("other".to_string()).unwrap();
+        assert_eq!(map.observer_count("key".to_string()), 2);
+        assert_eq!(map.total_observers(), 3);
+
+        drop(stream);
+        assert_eq!(map.observer_count("key".to_string()), 1);
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+        assert_eq!(map.observer_count("key".to_string()), 0);
+        assert_eq!(map.total_observers(), 1);
+    }
+
+    #[test]
+    fn purges_dead_observers_without_inserting() {
+        let mut map = ObserverMap::new();
+
+        let rx = map.observe("key".to_string()).unwrap();
+        let stream = map.observe_unbounded("key".to_string()).unwrap();
+        let other = map.observe("other".to_string()).unwrap();
+        drop(stream);
+        drop(other);
+
+        assert_eq!(map.purge_dead_observers(), 2);
+        assert_eq!(map.purge_dead_observers(), 0);
+        assert_eq!(
+            map.hashmap
+                .get("key")
+                .unwrap()
+                .observers
+                .as_ref()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(!map.hashmap.contains_key("other"));
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+    }
+
+    #[test]
+    fn dropped_receiver_is_unregistered_on_next_write() {
+        let mut map = ObserverMap::new();
+
+        let rx = map.observe_unbounded("other".to_string()).unwrap();
+        assert!(map.hashmap.get("other").unwrap().observers.is_some());
+
+        drop(rx);
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert!(!map.hashmap.contains_key("other"));
+    }
+
+    #[test]
+    fn keys_without_values_are_removed_with_their_last_observer() {
+        let mut map = ObserverMap::new();
+        map.insert("set".to_string(), 1u32).unwrap();
+        let set = map.observe_unbounded("set".to_string()).unwrap();
+        let unset = map.observe_unbounded("unset".to_string()).unwrap();
+        let kept = map.observe_unbounded("kept".to_string()).unwrap();
+
+        drop(set);
+        drop(unset);
+        assert_eq!(map.purge_dead_observers(), 2);
+        let mut keys: Vec<_> = map.hashmap.keys().cloned().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["kept".to_string(), "set".to_string()]);
+        assert_eq!(map.approx_memory_usage().entries, 2);
+
+        drop(kept);
+        map.insert("set".to_string(), 2).unwrap();
+        assert_eq!(map.hashmap.len(), 1);
+    }
+
+    #[test]
+    fn on_update_runs_callback_for_every_insert() {
+        let mut map = ThreadSafeObserverMap::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        map.on_update("key".to_string(), move |value: &u32| {
+            tx.send(*value).unwrap()
+        })
+        .unwrap();
+        map.insert("key".to_string(), 1).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(map.observer_count("key".to_string()), 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn on_update_async_awaits_callbacks_in_order() {
+        let mut map = ThreadSafeObserverMap::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        map.on_update_async("key".to_string(), move |value: u32| {
+            let tx = tx.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(u64::from(10 - value))).await;
+                tx.send(value).unwrap();
+            }
+        })
+        .unwrap();
+        for value in 1..=3 {
+            map.insert("key".to_string(), value).unwrap();
+        }
+
+        for value in 1..=3 {
+            assert_eq!(rx.recv().await.unwrap(), value);
+        }
+        map.close();
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn notifies_observers_in_priority_order() {
+        let mut map = ObserverMap::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for (name, priority) in [("metrics", -1), ("audit", 0), ("risk", 10), ("log", 0)] {
+            let order = order.clone();
+            map.on_update_with_priority("key".to_string(), priority, move |_: &u32| {
+                order.lock().unwrap().push(name)
+            })
+            .unwrap();
+        }
+        map.insert("key".to_string(), 1).unwrap();
+
+        assert_eq!(*order.lock().unwrap(), ["risk", "audit", "log", "metrics"]);
+    }
+
+    #[test]
+    fn insert_for_group_notifies_only_group_members() {
+        let mut map = ObserverMap::new();
+
+        let snapshots = map.observe_group("key".to_string(), "snapshots").unwrap();
+        let deltas = map.observe_group("key".to_string(), "deltas").unwrap();
+        let rx = map.observe("key".to_string()).unwrap();
+
+        map.insert_for_group("key".to_string(), 1u32, "deltas")
+            .unwrap();
+        assert_eq!(deltas.try_recv().unwrap(), 1);
+        assert!(snapshots.try_recv().is_err());
+        assert!(rx.try_recv().is_err());
+        assert_eq!(map.get("key".to_string()), Some(1));
+
+        map.insert("key".to_string(), 2).unwrap();
+        assert_eq!(snapshots.try_recv().unwrap(), 2);
+        assert_eq!(deltas.try_recv().unwrap(), 2);
+        assert_eq!(rx.try_recv().unwrap(), 2);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn observe_fails_once_observer_limit_is_reached() {
+        let mut map = ThreadSafeObserverMap::<String, u32>::with_observer_limit(2);
+
+        let _first = map.observe("key".to_string()).unwrap();
+        let second = map.observe_unbounded("key".to_string()).unwrap();
+        assert_eq!(
+            map.observe("key".to_string()).unwrap_err(),
+            ObserveError::TooManyObservers
+        );
+        assert_eq!(
+            map.wait_timeout("key".to_string(), Duration::from_millis(1)),
+            Err(WaitError::TooManyObservers)
+        );
+        let _other = map.observe("other".to_string()).unwrap();
+
+        drop(second);
+        map.observe("key".to_string()).unwrap();
+    }
+
+    #[test]
+    fn evicts_observers_that_repeatedly_fail_to_keep_up() {
+        let mut map = ObserverMap::new();
+        let (tx, evictions) = std::sync::mpsc::channel();
+        map.evict_slow_observers(2, move |key: &String| tx.send(key.clone()).unwrap());
+
+        let slow = map
+            .observe_with_backpressure("key".to_string(), Backpressure::DropOldest)
+            .unwrap();
+        let fast = map
+            .observe_with_backpressure("key".to_string(), Backpressure::DropOldest)
+            .unwrap();
+
+        map.insert("key".to_string(), 1u32).unwrap();
+        assert_eq!(fast.recv().unwrap(), 1);
+        map.insert("key".to_string(), 2).unwrap();
+        assert_eq!(fast.recv().unwrap(), 2);
+        assert!(evictions.try_recv().is_err());
+
+        let report = map.insert_reporting("key".to_string(), 3).unwrap();
+        assert_eq!(report.evicted, 1);
+        assert_eq!(evictions.try_recv().unwrap(), "key");
+        assert_eq!(map.observer_count("key".to_string()), 1);
+
+        assert_eq!(slow.recv().unwrap(), 3);
+        assert_eq!(slow.recv(), Err(RecvError));
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn send_timeout_stops_a_full_observer_wedging_inserts() {
+        let mut map = ThreadSafeObserverMap::new();
+        map.set_send_timeout(Duration::from_millis(10));
+
+        let stalled = map
+            .observe_with_backpressure("key".to_string(), Backpressure::Block)
+            .unwrap();
+        map.insert("key".to_string(), 1u32).unwrap();
+
+        let report = map.insert_reporting("key".to_string(), 2).unwrap();
+        assert_eq!(report.timed_out, 1);
+        assert_eq!(map.get("key".to_string()), Some(2));
+
+        assert_eq!(stalled.recv().unwrap(), 1);
+        map.insert("key".to_string(), 3).unwrap();
+        assert_eq!(stalled.recv().unwrap(), 3);
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn stalled_observer_doesnt_stall_concurrent_inserts_by_default() {
+        let mut map = ThreadSafeObserverMap::new();
+        let _stalled = map.observe_with_capacity("stalled".to_string(), 1).unwrap();
+        map.insert("stalled".to_string(), 1u32).unwrap();
+
+        let blocked = {
+            let mut map = map.clone();
+            thread::spawn(move || map.insert_reporting("stalled".to_string(), 2).unwrap())
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        {
+            let mut map = map.clone();
+            thread::spawn(move || {
+                map.insert("other".to_string(), 3).unwrap();
+                tx.send(()).unwrap();
+            });
+        }
+        rx.recv_timeout(DEFAULT_SEND_TIMEOUT * 10).unwrap();
+        assert_eq!(blocked.join().unwrap().timed_out, 1);
+        assert_eq!(map.get("other".to_string()), Some(3));
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn records_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        let mut map = ThreadSafeObserverMap::new();
+        map.set_metrics(metrics.clone());
+
+        let rx = map.observe_unbounded("key".to_string()).unwrap();
+        drop(map.observe("key".to_string()).unwrap());
+        map.insert("key".to_string(), 1u32).unwrap();
+        map.get("key".to_string());
+        assert_eq!(
+            map.wait_timeout("key".to_string(), Duration::from_millis(5)),
+            Err(WaitError::Timeout)
+        );
+        map.purge_dead_observers();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.inserts, 1);
+        assert_eq!(snapshot.gets, 1);
+        assert_eq!(snapshot.notifications, 1);
+        assert_eq!(snapshot.notification_failures, 1);
+        assert_eq!(snapshot.waits, 1);
+        assert!(snapshot.max_wait_time >= Duration::from_millis(5));
+        assert_eq!(snapshot.active_observers, 1);
+
+        drop(rx);
+        map.purge_dead_observers();
+        assert_eq!(metrics.snapshot().active_observers, 0);
+    }
+
+    #[test]
+    fn records_and_delivers_writer() {
+        let mut map = ObserverMap::new();
+        assert_eq!(map.write_info("key".to_string()), None);
+
+        let rx = map.observe_attributed("key".to_string()).unwrap();
+        map.insert_as("key".to_string(), 1u32, "feed-a").unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+
+        assert_eq!(
+            rx.recv().unwrap(),
+            Attributed {
+                value: 1,
+                writer: Some("feed-a".into())
+            }
+        );
+        assert_eq!(rx.recv().unwrap().writer, None);
+        assert_eq!(
+            map.write_info("key".to_string()),
+            Some(WriteInfo {
+                last_writer: None,
+                writes: 2
+            })
+        );
+    }
+
+    #[cfg(feature = "wal")]
+    #[test]
+    fn reopens_map_from_write_ahead_log() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("observable-maps-{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let mut map = ObserverMap::open_with_wal(&path).unwrap();
+            map.insert("a".to_string(), 1u32).unwrap();
+            map.insert("b".to_string(), 2).unwrap();
+            map.insert("a".to_string(), 3).unwrap();
+        }
+        // Simulate a crash part way through appending a record.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"Insert\":[\"c\"")
+            .unwrap();
+
+        let mut map = ObserverMap::<String, u32>::open_with_wal(&path).unwrap();
+        assert_eq!(map.get("a".to_string()), Some(3));
+        assert_eq!(map.get("b".to_string()), Some(2));
+        assert_eq!(map.get("c".to_string()), None);
+        assert_eq!(map.write_info("a".to_string()).unwrap().writes, 2);
+
+        map.insert("c".to_string(), 4).unwrap();
+        assert_eq!(map.remove("b".to_string()), Ok(Some(2)));
+        drop(map);
+        let map = ObserverMap::<String, u32>::open_with_wal(&path).unwrap();
+        assert_eq!(map.get("c".to_string()), Some(4));
+        assert_eq!(map.get("b".to_string()), None);
+        assert_eq!(map.get("a".to_string()), Some(3));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "wal", feature = "mock-clock", os))]
+    #[test]
+    fn write_ahead_log_replays_clears_and_idle_expiry() {
+        let path = std::env::temp_dir().join(format!(
+            "observable-maps-{}-removals.wal",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let clock = MockClock::new();
+            let mut map = ObserverMap::open_with_wal(&path).unwrap();
+            map.set_clock(clock.clone());
+            map.insert("cleared".to_string(), 1u32).unwrap();
+            map.clear().unwrap();
+            map.expire_after_idle(Duration::from_secs(10));
+            map.insert("idle".to_string(), 2).unwrap();
+            clock.advance(Duration::from_secs(6));
+            map.insert("kept".to_string(), 3).unwrap();
+            clock.advance(Duration::from_secs(6));
+            assert_eq!(map.expire_idle(), 1);
+        }
+
+        let map = ObserverMap::<String, u32>::open_with_wal(&path).unwrap();
+        assert_eq!(map.get("cleared".to_string()), None);
+        assert_eq!(map.get("idle".to_string()), None);
+        assert_eq!(map.get("kept".to_string()), Some(3));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "wal", feature = "encryption"))]
+    #[test]
+    fn reopens_map_from_encrypted_write_ahead_log() {
+        let path = std::env::temp_dir().join(format!(
+            "observable-maps-{}-encrypted.wal",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let key = EncryptionKey::new([3; 32]);
+        {
+            let mut map = ObserverMap::open_with_encrypted_wal(&path, key.clone()).unwrap();
+            map.insert("secret".to_string(), "hunter2".to_string())
+                .unwrap();
+        }
+        let contents = std::fs::read(&path).unwrap();
+        assert!(!contents.windows(6).any(|window| window == b"secret"));
+
+        let map = ObserverMap::<String, String>::open_with_encrypted_wal(&path, key).unwrap();
+        assert_eq!(map.get("secret".to_string()), Some("hunter2".to_string()));
+        let wrong = EncryptionKey::new([4; 32]);
+        assert!(ObserverMap::<String, String>::open_with_encrypted_wal(&path, wrong).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_copies_values_without_disturbing_observers() {
+        let mut map = ObserverMap::new();
+        map.insert("a".to_string(), 1).unwrap();
+        map.insert("b".to_string(), 2).unwrap();
+        let rx = map.observe_unbounded("c".to_string()).unwrap();
+
+        let snapshot = map.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot["a"], 1);
+        assert_eq!(snapshot["b"], 2);
+
+        let mut pairs = vec![];
+        map.snapshot_into(&mut pairs);
+        pairs.sort();
+        assert_eq!(pairs, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+
+        assert_eq!(map.observer_count("c".to_string()), 1);
+        map.insert("c".to_string(), 3).unwrap();
+        assert_eq!(rx.recv(), Ok(3));
+    }
+
+    #[test]
+    fn snapshot_iteration_does_not_hold_the_lock() {
+        let mut map = ThreadSafeObserverMap::new();
+        map.insert("a".to_string(), 1).unwrap();
+        let _rx = map.observe("unset".to_string()).unwrap();
+
+        assert_eq!(map.keys_snapshot(), vec!["a".to_string()]);
+        for (key, value) in map.iter_snapshot() {
+            // Would deadlock if the read lock were still held.
+            map.insert(format!("{key}{key}"), value + 1).unwrap();
+        }
+        let mut keys = map.keys_snapshot();
+        keys.sort();
+        assert_eq!(keys, vec!["a".to_string(), "aa".to_string()]);
+    }
+
+    #[test]
+    fn late_joiner_catches_up_from_journal() {
+        let mut map = ObserverMap::new();
+        assert_eq!(map.journal_since(0), Err(JournalError::Disabled));
+        map.enable_journal(8);
+
+        map.insert("a".to_string(), 1).unwrap();
+        let seq = map.next_seq();
+        map.insert("b".to_string(), 2).unwrap();
+        map.insert("a".to_string(), 3).unwrap();
+
+        let keys: Vec<_> = map
+            .journal_since(seq)
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.seq, event.key, event.change))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (1, "b".to_string(), Change::Inserted(2)),
+                (2, "a".to_string(), Change::Inserted(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn replica_follows_event_stream() {
+        let mut primary = ThreadSafeObserverMap::new();
+        let replica = ThreadSafeObserverMap::new();
+        let events = primary.events();
+        let mut replica_handle = replica.clone();
+        let rx = replica_handle.observe_unbounded("a".to_string()).unwrap();
+
+        let follower = {
+            let replica = replica.clone();
+            thread::spawn(move || {
+                for event in events.iter().take(3) {
+                    replica.apply_event(event).unwrap();
+                }
+            })
+        };
+        primary.insert("a".to_string(), 1).unwrap();
+        primary.insert("b".to_string(), 2).unwrap();
+        primary.insert("a".to_string(), 3).unwrap();
+        follower.join().unwrap();
+
+        assert_eq!(replica.snapshot(), primary.snapshot());
+        assert_eq!(replica.next_seq(), 3);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 3]);
 
-impl<K, V> Default for ObserverMap<K, V> {
-    fn default() -> Self {
-        Self::new()
+        // Replaying an already applied event is a no-op.
+        let stale = MapEvent {
+            seq: 0,
+            key: "a".to_string(),
+            change: Change::Inserted(0),
+        };
+        replica.apply_event(stale).unwrap();
+        assert_eq!(replica_handle.get("a".to_string()), Some(3));
     }
-}
 
-#[derive(Clone)]
-pub struct ThreadSafeObserverMap<K, V> {
-    inner: Arc<RwLock<ObserverMap<K, V>>>,
-}
-
-impl<K, V> ThreadSafeObserverMap<K, V> {
-    pub fn new() -> Self {
-        Self {
-            inner: Arc::new(RwLock::new(ObserverMap::new())),
+    #[test]
+    fn event_subscribers_that_fall_behind_are_unsubscribed() {
+        let mut map = ObserverMap::new();
+        let events = map.events_with_capacity(2);
+        for value in 0..3 {
+            map.insert("a", value).unwrap();
         }
-    }
-}
 
-impl<K, V> ObservableMap<K, V> for ThreadSafeObserverMap<K, V>
-where
-    K: Hash + Eq + PartialEq,
-    V: Clone,
-{
-    fn insert(&mut self, key: K, value: V) -> Result<(), SendError<V>> {
-        self.inner.write().unwrap().insert(key, value)
+        let seqs: Vec<_> = events.iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![0, 1]);
     }
 
-    fn get(&self, key: K) -> Option<V> {
-        self.inner.read().unwrap().get(key)
-    }
+    #[test]
+    fn closing_the_map_ends_event_streams() {
+        let mut map = ObserverMap::new();
+        let events = map.events();
+        map.insert("a", 1).unwrap();
+        map.close();
 
-    fn observe(&mut self, key: K) -> Receiver<V> {
-        self.inner.write().unwrap().observe(key)
+        assert_eq!(events.iter().count(), 1);
+        assert!(map.events().recv().is_err());
     }
 
-    fn wait(&mut self, key: K) -> Result<V, RecvError> {
-        self.observe(key).recv()
-    }
-}
+    #[test]
+    fn insert_merge_notifies_observers_of_merged_value() {
+        let map = ThreadSafeObserverMap::new();
+        let mut handle = map.clone();
+        let rx = handle.observe_unbounded("key".to_string()).unwrap();
 
-impl<K, V> Default for ThreadSafeObserverMap<K, V> {
-    fn default() -> Self {
-        Self::new()
+        map.insert_merge("key".to_string(), LwwRegister::new("b", 2))
+            .unwrap();
+        map.insert_merge("key".to_string(), LwwRegister::new("a", 1))
+            .unwrap();
+        map.insert_merge("key".to_string(), LwwRegister::new("c", 3))
+            .unwrap();
+
+        let values: Vec<_> = rx.try_iter().map(|register| *register.value()).collect();
+        assert_eq!(values, vec!["b", "b", "c"]);
     }
-}
 
-struct Item<T> {
-    value: Option<T>,
-    observers: Option<Vec<SyncSender<T>>>,
-}
+    #[test]
+    fn insert_merge_succeeds_once_written_despite_full_observers() {
+        let mut map = ObserverMap::new();
+        let rx = map
+            .observe_with_backpressure("key".to_string(), Backpressure::Error)
+            .unwrap();
+        let counter = |replica, by| {
+            let mut counter = GCounter::new();
+            counter.increment(replica, by);
+            counter
+        };
+        map.insert_merge("key".to_string(), counter("a", 1))
+            .unwrap();
 
-impl<T> Item<T>
-where
-    T: Clone,
-{
-    fn new(value: T) -> Self {
-        Self {
-            value: Some(value),
-            observers: None,
-        }
+        map.insert_merge("key".to_string(), counter("b", 2))
+            .unwrap();
+        assert_eq!(map.get("key".to_string()).unwrap().value(), 3);
+        assert_eq!(rx.try_iter().count(), 1);
     }
 
-    fn from_observer(observer: SyncSender<T>) -> Self {
-        Self {
-            value: None,
-            observers: Some(vec![observer]),
-        }
+    #[test]
+    fn resolvers_apply_on_overwrite() {
+        let mut map = ObserverMap::new();
+        map.set_resolver(|old: &u32, new| (*old).max(new));
+        map.set_key_resolver("sum".to_string(), |old, new| old + new);
+
+        map.insert("max".to_string(), 5).unwrap();
+        map.insert("max".to_string(), 3).unwrap();
+        map.insert("sum".to_string(), 5).unwrap();
+        let rx = map.observe("sum".to_string()).unwrap();
+        map.insert("sum".to_string(), 3).unwrap();
+
+        assert_eq!(map.get("max".to_string()), Some(5));
+        assert_eq!(rx.recv(), Ok(8));
     }
 
-    fn update(&mut self, value: T) -> Result<(), SendError<T>> {
-        self.value = Some(value.clone());
-        self.notify(value)
+    #[test]
+    fn insert_if_version_rejects_concurrent_writes() {
+        let map = ThreadSafeObserverMap::new();
+        assert_eq!(map.insert_if_version("key".to_string(), 1, 0), Ok(1));
+
+        let (value, version) = map.get_versioned("key".to_string()).unwrap();
+        assert_eq!((value, version), (1, 1));
+        map.insert_if_version("key".to_string(), 10, version)
+            .unwrap();
+
+        assert_eq!(
+            map.insert_if_version("key".to_string(), value + 1, version),
+            Err(InsertError::VersionConflict(2, 2))
+        );
+        assert_eq!(map.get_versioned("key".to_string()), Some((10, 2)));
     }
 
-    fn add_observer(&mut self, observer: SyncSender<T>) {
-        match &mut self.observers {
-            Some(observers) => observers.push(observer),
-            None => self.observers = Some(vec![observer]),
-        }
+    #[test]
+    fn insert_if_version_succeeds_once_written_despite_full_observers() {
+        let mut map = ObserverMap::new();
+        let rx = map
+            .observe_with_backpressure("key".to_string(), Backpressure::Error)
+            .unwrap();
+        map.insert("key".to_string(), 1u32).unwrap();
+
+        assert_eq!(map.insert_if_version("key".to_string(), 2, 1), Ok(2));
+        assert_eq!(map.get_versioned("key".to_string()), Some((2, 2)));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1]);
     }
 
-    fn notify(&mut self, value: T) -> Result<(), SendError<T>> {
-        if let Some(observers) = &self.observers {
-            for observer in observers {
-                observer.send(value.clone())?;
-            }
-            self.observers = None;
+    #[cfg(os)]
+    #[test]
+    fn get_or_wait_returns_current_value_or_waits_for_first() {
+        let map = ThreadSafeObserverMap::new();
+        let mut handle = map.clone();
+        handle.insert("set".to_string(), 1).unwrap();
+        assert_eq!(handle.get_or_wait("set".to_string()), Ok(1));
+
+        let waiter = {
+            let mut map = map.clone();
+            thread::spawn(move || map.get_or_wait("unset".to_string()))
+        };
+        while handle.observer_count("unset".to_string()) == 0 {
+            thread::yield_now();
         }
-        Ok(())
+        handle.insert("unset".to_string(), 2).unwrap();
+        assert_eq!(waiter.join().unwrap(), Ok(2));
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    use std::{sync::mpsc::RecvError, thread, time::Duration};
+    #[test]
+    fn observe_with_initial_delivers_current_value_first() {
+        let mut map = ObserverMap::new();
+        let unset = map.observe_with_initial("key".to_string()).unwrap();
+        map.insert("key".to_string(), 1).unwrap();
+        let set = map.observe_with_initial("key".to_string()).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
 
-    use num::bigint::ToBigUint;
-    use rust_decimal_macros::dec;
+        assert_eq!(unset.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(set.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
 
+    #[cfg(os)]
     #[test]
-    fn insert_and_get() {
+    fn history_keeps_recent_values_per_key() {
         let mut map = ObserverMap::new();
+        map.insert("key".to_string(), 0).unwrap();
+        map.enable_history(3);
+        for value in 1..=4 {
+            map.insert("key".to_string(), value).unwrap();
+        }
+        map.insert("other".to_string(), 10).unwrap();
 
-        map.insert("key".to_string(), 1u32).unwrap();
-        assert_eq!(map.get("key".to_string()).unwrap(), 1);
+        assert_eq!(map.last_n("key".to_string(), 2), vec![3, 4]);
+        assert_eq!(map.last_n("key".to_string(), 10), vec![2, 3, 4]);
+        assert_eq!(
+            map.history_since("key".to_string(), 3),
+            vec![(3, 3), (4, 4)]
+        );
+        assert_eq!(map.last_n("missing".to_string(), 1), Vec::<i32>::new());
+    }
 
+    #[cfg(os)]
+    #[test]
+    fn get_at_returns_value_at_time() {
+        let mut map = ObserverMap::new();
+        map.enable_history(2);
+        let before = SystemTime::now();
+        thread::sleep(Duration::from_millis(5));
+        map.insert("key".to_string(), 1).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        let first = SystemTime::now();
+        thread::sleep(Duration::from_millis(5));
         map.insert("key".to_string(), 2).unwrap();
-        assert_eq!(map.get("key".to_string()).unwrap(), 2);
 
-        map.insert("another_key".to_string(), 3).unwrap();
-        assert_eq!(map.get("another_key".to_string()).unwrap(), 3);
+        assert_eq!(map.get_at("key".to_string(), before), None);
+        assert_eq!(map.get_at("key".to_string(), first), Some(1));
+        assert_eq!(map.get_at("key".to_string(), SystemTime::now()), Some(2));
 
-        assert!(map.get("not_a_key".to_string()).is_none());
+        map.insert("key".to_string(), 3).unwrap();
+        assert_eq!(map.get_at("key".to_string(), first), None);
     }
 
+    #[cfg(os)]
     #[test]
-    fn value_is_arbitrary_structs_that_are_copy() {
-        #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-        struct Tmp();
-
+    fn key_stats_track_updates() {
         let mut map = ObserverMap::new();
-        map.insert("is_copy".to_string(), Tmp()).unwrap();
-        assert_eq!(map.get("is_copy".to_string()).unwrap(), Tmp());
+        let _rx = map.observe("key".to_string()).unwrap();
+        assert_eq!(map.key_stats("key".to_string()), None);
+
+        map.insert("key".to_string(), 1).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+
+        let stats = map.key_stats("key".to_string()).unwrap();
+        assert_eq!(stats.updates, 2);
+        assert!(stats.updates_per_second > 0.0);
+        assert!(stats.last_update <= SystemTime::now());
     }
 
     #[test]
-    fn value_is_arbitrary_precision_decimal_type() {
+    fn lifecycle_hooks_run_on_first_and_last_observer() {
+        let events = Arc::new(Mutex::new(Vec::new()));
         let mut map = ObserverMap::new();
-        map.insert("pi".to_string(), dec!(3.1415926535897932384))
-            .unwrap();
+        let first = events.clone();
+        map.on_first_observer(move |key: &String| first.lock().unwrap().push(format!("+{}", key)));
+        let last = events.clone();
+        map.on_last_observer(move |key: &String| last.lock().unwrap().push(format!("-{}", key)));
+
+        let a = map.observe_unbounded("key".to_string()).unwrap();
+        let b = map.observe_unbounded("key".to_string()).unwrap();
+        drop(a);
+        map.insert("key".to_string(), 1).unwrap();
+        drop(b);
+        map.insert("key".to_string(), 2).unwrap();
+        let _c = map.observe("key".to_string()).unwrap();
+        map.close();
+
         assert_eq!(
-            map.get("pi".to_string()).unwrap(),
-            dec!(3.1415926535897932384)
+            *events.lock().unwrap(),
+            vec!["+key", "-key", "+key", "-key"]
         );
     }
 
     #[test]
-    fn value_is_num_biguint_type() {
+    fn interceptors_transform_and_reject_values() {
         let mut map = ObserverMap::new();
-        map.insert("key".to_string(), 123.to_biguint()).unwrap();
-        assert_eq!(map.get("key".to_string()).unwrap(), 123.to_biguint(),);
-    }
-
-    #[test]
-    fn thread_safe_wait_for_next_value() {
-        let mut map = ThreadSafeObserverMap::new();
-
-        map.insert("key".to_string(), 1u64).unwrap();
+        map.add_interceptor(|_: &String, price: &mut i64| {
+            if *price < 0 {
+                return Err("negative price".into());
+            }
+            Ok(())
+        });
+        map.add_interceptor(|_, price| {
+            *price *= 100;
+            Ok(())
+        });
+        let rx = map.observe_unbounded("key".to_string()).unwrap();
 
-        {
-            let mut map = map.clone();
-            thread::spawn(move || {
-                thread::sleep(Duration::from_millis(100));
-                map.insert("key".to_string(), 2).unwrap();
-            })
-        };
+        map.insert("key".to_string(), 2).unwrap();
+        assert_eq!(
+            map.insert("key".to_string(), -1),
+            Err(InsertError::Invalid(-1, Rejection::new("negative price")))
+        );
 
-        assert_eq!(map.wait("key".to_string()).unwrap(), 2);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![200]);
+        assert_eq!(map.get("key".to_string()), Some(200));
     }
 
     #[test]
-    fn wait_for_value_of_new_key() {
-        let mut map = ThreadSafeObserverMap::new();
+    fn after_notify_hooks_see_each_delivery() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut map = ObserverMap::new();
+        let hook = seen.clone();
+        map.after_notify(move |key: &String, value: &i32, report: &DeliveryReport| {
+            hook.lock()
+                .unwrap()
+                .push((key.clone(), *value, report.notified));
+        });
 
-        {
-            let mut map = map.clone();
-            thread::spawn(move || {
-                thread::sleep(Duration::from_millis(100));
-                map.insert("key".to_string(), 2u64).unwrap();
-            })
-        };
+        map.insert("key".to_string(), 1).unwrap();
+        let _rx = map.observe("key".to_string()).unwrap();
+        map.insert("key".to_string(), 2).unwrap();
 
-        assert_eq!(map.wait("key".to_string()).unwrap(), 2);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("key".to_string(), 1, 0), ("key".to_string(), 2, 1)]
+        );
     }
 
     #[test]
-    fn multiple_observers() {
-        let mut map = ThreadSafeObserverMap::new();
+    fn write_once_keys_reject_later_inserts() {
+        let mut map = ObserverMap::write_once();
+        let rx = map.observe_unbounded("key".to_string()).unwrap();
 
-        let mut handles = vec![];
+        map.insert("key".to_string(), 1).unwrap();
+        assert_eq!(
+            map.insert("key".to_string(), 2),
+            Err(InsertError::AlreadySet(2))
+        );
+        map.insert("other".to_string(), 3).unwrap();
 
-        for _ in 1..=4 {
-            let mut map = map.clone();
-            let handle = thread::spawn(move || {
-                assert_eq!(map.wait("key".to_string()).unwrap(), 1);
-            });
-            handles.push(handle);
-        }
+        assert_eq!(map.get("key".to_string()), Some(1));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
 
-        thread::sleep(Duration::from_millis(100));
-        map.insert("key".to_string(), 1u8).unwrap();
+    #[cfg(os)]
+    #[test]
+    fn items_keep_rarely_used_state_out_of_line() {
+        // Down from 136 bytes on 64-bit targets when writers and history were stored inline.
+        assert!(std::mem::size_of::<Item<u64>>() <= 80);
 
-        for handle in handles {
-            handle.join().unwrap();
-        }
+        let mut map = ObserverMap::new();
+        map.enable_history(2);
+        map.insert_as("key".to_string(), 1, "writer").unwrap();
+        map.insert("key".to_string(), 2).unwrap();
+        let info = map.write_info("key".to_string()).unwrap();
+        assert_eq!(info.last_writer, None);
+        assert_eq!(map.last_n("key".to_string(), 2), vec![1, 2]);
     }
 
     #[test]
-    fn wait_for_next_value_multiple_times() {
-        let mut map = ThreadSafeObserverMap::new();
+    fn approx_memory_usage_counts_entries_observers_and_values() {
+        let mut map = ObserverMap::new();
+        let _rx = map.observe("observed".to_string()).unwrap();
+        map.insert("key".to_string(), "a".repeat(1000)).unwrap();
 
-        for v in 1u64..=4 {
-            {
-                let mut map = map.clone();
-                thread::spawn(move || {
-                    thread::sleep(Duration::from_millis(100));
-                    map.insert("key".to_string(), v).unwrap();
-                })
-            };
+        let shallow = map.approx_memory_usage();
+        assert_eq!(shallow.entries, 2);
+        assert_eq!(shallow.observers, 1);
+        assert!(shallow.bytes > 0);
 
-            assert_eq!(map.wait("key".to_string()).unwrap(), v);
-        }
+        let deep = map.approx_memory_usage_with(String::capacity);
+        assert_eq!(deep.bytes, shallow.bytes + 1000);
     }
 
+    #[cfg(feature = "mock-clock")]
     #[test]
-    fn next_value_is_the_same_as_current_value() {
-        let mut map = ThreadSafeObserverMap::new();
-
-        map.insert("key".to_string(), 1u32).unwrap();
+    fn mock_clock_drives_timeouts() {
+        let clock = MockClock::new();
+        let map = ThreadSafeObserverMap::<String, i32>::new();
+        map.set_clock(clock.clone());
 
-        {
+        let waiter = {
             let mut map = map.clone();
-            thread::spawn(move || {
-                thread::sleep(Duration::from_millis(100));
-                map.insert("key".to_string(), 1).unwrap();
-            })
+            thread::spawn(move || map.wait_timeout("key".to_string(), Duration::from_secs(60)))
         };
+        testing::assert_eventually_observed(&map, "key".to_string(), 1, Duration::from_secs(5));
+        clock.advance(Duration::from_secs(59));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
 
-        assert_eq!(map.wait("key".to_string()).unwrap(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(waiter.join().unwrap(), Err(WaitError::Timeout));
     }
 
-    #[tokio::test(flavor = "multi_thread")]
-    async fn get_value_multiple_times_whilst_waiting_for_next_value() {
-        let mut map = ThreadSafeObserverMap::new();
-
-        map.insert("key".to_string(), 1u64).unwrap();
+    #[cfg(all(feature = "mock-clock", os))]
+    #[test]
+    fn mock_clock_waits_forever_without_a_representable_deadline() {
+        let clock = MockClock::new();
+        let map = ThreadSafeObserverMap::<String, i32>::new();
+        map.set_clock(clock.clone());
 
-        {
+        let waiter = {
             let mut map = map.clone();
-            tokio::spawn(async move {
-                assert_eq!(map.wait("key".to_string()).unwrap(), 2);
-            });
-        }
-
-        let mut handles = vec![];
+            thread::spawn(move || map.wait_timeout("key".to_string(), Duration::MAX))
+        };
+        testing::assert_eventually_observed(&map, "key".to_string(), 1, Duration::from_secs(5));
+        clock.advance(Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
 
-        {
-            for _ in 0..100_000 {
-                let map = map.clone();
-                let handle = tokio::spawn(async move {
-                    assert_eq!(map.get("key".to_string()).unwrap(), 1);
-                });
-                handles.push(handle);
-            }
-        }
+        map.clone().insert("key".to_string(), 1).unwrap();
+        assert_eq!(waiter.join().unwrap(), Ok(1));
+    }
 
-        for handle in handles {
-            handle.await.unwrap();
-        }
+    #[cfg(feature = "mock-clock")]
+    #[test]
+    fn mock_clock_drives_timestamps() {
+        let clock = MockClock::new();
+        let mut map = ObserverMap::new();
+        map.enable_history(4);
+        map.set_clock(clock.clone());
 
+        map.insert("key".to_string(), 1).unwrap();
+        let first = clock.system_now();
+        clock.advance(Duration::from_secs(30));
         map.insert("key".to_string(), 2).unwrap();
+        clock.advance(Duration::from_secs(5));
+
+        assert_eq!(map.get_at("key".to_string(), first), Some(1));
+        let stats = map.key_stats("key".to_string()).unwrap();
+        assert_eq!(stats.idle, Duration::from_secs(5));
+        assert_eq!(stats.last_update, first + Duration::from_secs(30));
     }
 
     #[test]
-    fn thread_unsafe_channel_closed() {
-        let mut map: ObserverMap<String, u32> = ObserverMap::new();
-
-        let rx = map.observe("key".to_string());
-
-        // Close the channel
-        map.hashmap.get_mut("key").unwrap().observers = None;
+    fn index_reads_values_by_reference() {
+        let mut map = ObserverMap::new();
+        map.insert("key".to_string(), vec![1, 2]).unwrap();
+        let _rx = map.observe("unset".to_string()).unwrap();
 
-        assert_eq!(rx.recv().unwrap_err(), RecvError);
+        assert_eq!(map["key"], vec![1, 2]);
+        assert_eq!(map[&"key".to_string()].len(), 2);
+        let unset = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| map["unset"].len()));
+        assert!(unset.is_err());
     }
 
     #[test]
-    fn thread_safe_channel_closed() {
-        let mut map: ThreadSafeObserverMap<String, u64> = ThreadSafeObserverMap::new();
+    fn reserves_capacity_for_keys() {
+        let map = ThreadSafeObserverMap::<u32, u32>::with_capacity(100);
+        assert!(map.capacity() >= 100);
+        map.reserve(1000);
+        assert!(map.capacity() >= 1000);
 
-        let rx = map.observe("key".to_string());
+        let mut map = ObserverMap::new();
+        map.reserve(10);
+        let capacity = map.capacity();
+        for key in 0..10 {
+            map.insert(key, key).unwrap();
+        }
+        assert_eq!(map.capacity(), capacity);
+    }
 
-        // Close the channel
-        map.inner
-            .write()
-            .unwrap()
-            .hashmap
-            .get_mut("key")
-            .unwrap()
-            .observers = None;
+    #[test]
+    fn shrink_to_fit_releases_map_and_observer_capacity() {
+        let mut map = ObserverMap::with_capacity(1000);
+        map.insert("key".to_string(), 0).unwrap();
+        let receivers: Vec<_> = (0..100)
+            .map(|_| map.observe_unbounded("key".to_string()).unwrap())
+            .collect();
+        let kept = map.observe_unbounded("key".to_string()).unwrap();
+        drop(receivers);
+        map.purge_dead_observers();
 
-        assert_eq!(rx.recv().unwrap_err(), RecvError);
+        map.shrink_to_fit();
+        assert!(map.capacity() < 1000);
+        let observers = map.hashmap["key"].observers.as_ref().unwrap();
+        assert_eq!(observers.capacity(), 1);
+        map.insert("key".to_string(), 1).unwrap();
+        assert_eq!(kept.try_recv(), Ok(1));
     }
 }
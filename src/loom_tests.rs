@@ -0,0 +1,82 @@
+// Model checks of the map and its channels, run with
+// `RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests`.
+use std::sync::mpsc::TrySendError;
+use std::time::Duration;
+
+use loom::thread;
+
+use crate::{channel, ObservableMap, ThreadSafeObserverMap};
+
+#[test]
+fn bounded_channel_delivers_in_order() {
+    loom::model(|| {
+        let (tx, rx) = channel::bounded(1);
+        let sender = thread::spawn(move || {
+            tx.send(1).unwrap();
+            tx.send(2).unwrap();
+        });
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.recv(), Ok(2));
+        sender.join().unwrap();
+    });
+}
+
+#[test]
+fn send_timeout_waits_for_space_or_fails_full() {
+    loom::model(|| {
+        let (tx, rx) = channel::bounded(1);
+        tx.send(1).unwrap();
+        let receiver = thread::spawn(move || {
+            assert_eq!(rx.recv(), Ok(1));
+            rx
+        });
+        // Loom may time the wait out before the receiver makes room, but never loses a value.
+        let sent = tx.send_timeout(2, Duration::from_secs(1));
+        let rx = receiver.join().unwrap();
+        match sent {
+            Ok(()) => assert_eq!(rx.try_recv(), Ok(2)),
+            Err(TrySendError::Full(2)) => assert!(rx.try_recv().is_err()),
+            Err(error) => panic!("unexpected {error:?}"),
+        }
+    });
+}
+
+#[test]
+fn force_send_evicts_the_oldest_value_when_full() {
+    loom::model(|| {
+        let (tx, rx) = channel::bounded(1);
+        tx.send(1).unwrap();
+        let receiver = thread::spawn(move || rx.recv());
+        let evicted = tx.force_send(2).unwrap();
+        let received = receiver.join().unwrap();
+        // Either the receiver took 1 first and 2 fit, or 2 displaced 1 and was received.
+        match evicted {
+            None => assert_eq!(received, Ok(1)),
+            Some(evicted) => assert_eq!((evicted, received), (1, Ok(2))),
+        }
+    });
+}
+
+#[test]
+fn get_or_wait_never_misses_a_concurrent_insert() {
+    loom::model(|| {
+        let mut map = ThreadSafeObserverMap::new();
+        let mut writer = map.clone();
+        let inserter = thread::spawn(move || writer.insert("key", 1).unwrap());
+        assert_eq!(map.get_or_wait("key"), Ok(1));
+        inserter.join().unwrap();
+    });
+}
+
+#[test]
+fn dropped_receivers_are_unregistered() {
+    loom::model(|| {
+        let mut map = ThreadSafeObserverMap::new();
+        let rx = map.observe_unbounded("key").unwrap();
+        let dropper = thread::spawn(move || drop(rx));
+        map.insert("key", 1).unwrap();
+        dropper.join().unwrap();
+        map.insert("key", 2).unwrap();
+        assert_eq!(map.observer_count("key"), 0);
+    });
+}
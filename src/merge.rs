@@ -0,0 +1,95 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+
+/// A value that can be merged with another replica's value of the same key, as inserted with
+/// [`ObserverMap::insert_merge`](crate::ObserverMap::insert_merge).
+///
+/// Merging must be commutative, associative and idempotent, so that replicas receiving the
+/// same values in any order, any number of times, converge on the same state.
+pub trait MergeableValue {
+    fn merge(&mut self, other: Self);
+}
+
+/// A grow-only counter, counting separately for each replica so that concurrent increments
+/// are never lost.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GCounter {
+    counts: BTreeMap<String, u64>,
+}
+
+impl GCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn increment(&mut self, replica: &str, by: u64) {
+        *self.counts.entry(replica.to_string()).or_default() += by;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl MergeableValue for GCounter {
+    fn merge(&mut self, other: Self) {
+        for (replica, count) in other.counts {
+            let current = self.counts.entry(replica).or_default();
+            *current = (*current).max(count);
+        }
+    }
+}
+
+/// A last-writer-wins register, holding the value with the greatest timestamp. Of two values
+/// with the same timestamp, the one already held wins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LwwRegister<T> {
+    value: T,
+    timestamp: u64,
+}
+
+impl<T> LwwRegister<T> {
+    pub fn new(value: T, timestamp: u64) -> Self {
+        Self { value, timestamp }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+impl<T> MergeableValue for LwwRegister<T> {
+    fn merge(&mut self, other: Self) {
+        if other.timestamp > self.timestamp {
+            *self = other;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn g_counter_merge_is_idempotent_and_commutative() {
+        let mut a = GCounter::new();
+        a.increment("a", 2);
+        let mut b = GCounter::new();
+        b.increment("b", 3);
+
+        let mut ab = a.clone();
+        ab.merge(b.clone());
+        ab.merge(b.clone());
+        let mut ba = b;
+        ba.merge(a);
+
+        assert_eq!(ab, ba);
+        assert_eq!(ab.value(), 5);
+    }
+}
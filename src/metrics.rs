@@ -0,0 +1,225 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::clock::{Instant, SystemTime};
+
+use crate::clock::Clock;
+use crate::DeliveryReport;
+
+/// Counters describing how a map is used. Attach a collector to a map with
+/// [`ObserverMap::set_metrics`](crate::ObserverMap::set_metrics) and read it at any time with
+/// [`Metrics::snapshot`].
+///
+/// With the `metrics` feature, every collector also reports to the
+/// [`metrics`](https://docs.rs/metrics) facade, so any installed recorder, such as a Prometheus
+/// exporter, receives:
+///
+/// - `observable_maps_inserts_total`, `observable_maps_gets_total`,
+///   `observable_maps_notifications_total` and `observable_maps_notification_failures_total`
+///   counters
+/// - an `observable_maps_wait_seconds` histogram
+/// - an `observable_maps_active_observers` gauge
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inserts: AtomicU64,
+    gets: AtomicU64,
+    notifications: AtomicU64,
+    notification_failures: AtomicU64,
+    waits: AtomicU64,
+    wait_nanos: AtomicU64,
+    max_wait_nanos: AtomicU64,
+    observers: AtomicUsize,
+}
+
+/// A point-in-time copy of a [`Metrics`] collector.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub inserts: u64,
+    pub gets: u64,
+    /// Values delivered to observers.
+    pub notifications: u64,
+    /// Values that observers missed because they were disconnected, full or too slow.
+    pub notification_failures: u64,
+    /// Waits that have completed, successfully or not.
+    pub waits: u64,
+    pub total_wait_time: Duration,
+    pub max_wait_time: Duration,
+    /// Observers currently registered with the map.
+    pub active_observers: usize,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            inserts: self.inserts.load(Ordering::Relaxed),
+            gets: self.gets.load(Ordering::Relaxed),
+            notifications: self.notifications.load(Ordering::Relaxed),
+            notification_failures: self.notification_failures.load(Ordering::Relaxed),
+            waits: self.waits.load(Ordering::Relaxed),
+            total_wait_time: Duration::from_nanos(self.wait_nanos.load(Ordering::Relaxed)),
+            max_wait_time: Duration::from_nanos(self.max_wait_nanos.load(Ordering::Relaxed)),
+            active_observers: self.observers.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn record_insert(&self, report: &DeliveryReport) {
+        self.inserts.fetch_add(1, Ordering::Relaxed);
+        let failures = report.disconnected + report.dropped + report.rejected + report.timed_out;
+        self.notifications
+            .fetch_add(report.notified as u64, Ordering::Relaxed);
+        self.notification_failures
+            .fetch_add(failures as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            ::metrics::counter!("observable_maps_inserts_total").increment(1);
+            ::metrics::counter!("observable_maps_notifications_total")
+                .increment(report.notified as u64);
+            ::metrics::counter!("observable_maps_notification_failures_total")
+                .increment(failures as u64);
+        }
+    }
+
+    pub(crate) fn record_get(&self) {
+        self.gets.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::counter!("observable_maps_gets_total").increment(1);
+    }
+
+    #[cfg(os)]
+    pub(crate) fn record_wait(&self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.waits.fetch_add(1, Ordering::Relaxed);
+        self.wait_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_wait_nanos.fetch_max(nanos, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::histogram!("observable_maps_wait_seconds").record(elapsed);
+    }
+}
+
+/// How often a key is updated, returned by
+/// [`ObserverMap::key_stats`](crate::ObserverMap::key_stats).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyStats {
+    pub updates: u64,
+    /// The recent update rate, averaged over roughly the last ten seconds. It decays towards
+    /// zero whilst the key isn't updated.
+    pub updates_per_second: f64,
+    pub last_update: SystemTime,
+    /// How long ago the key was last updated.
+    pub idle: Duration,
+}
+
+/// An estimate of a map's memory use, returned by
+/// [`ObserverMap::approx_memory_usage`](crate::ObserverMap::approx_memory_usage).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Keys stored, including keys that are only observed.
+    pub entries: usize,
+    /// Live receivers observing any key.
+    pub observers: usize,
+    /// The bytes held by the map's table, entries and retained values. Memory that keys own
+    /// and observer channels' buffers aren't counted.
+    pub bytes: usize,
+}
+
+// How long the update rate is averaged over. Each update's weight decays by a factor of `e`
+// per window.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+// An exponentially decaying count of updates, giving a recent update rate in O(1) space.
+pub(crate) struct UpdateRate {
+    rate: f64,
+    updated: Instant,
+}
+
+impl UpdateRate {
+    pub(crate) fn new(clock: &Clock) -> Self {
+        Self {
+            rate: 1.0 / RATE_WINDOW.as_secs_f64(),
+            updated: clock.now(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, clock: &Clock) {
+        self.record_at(clock.now());
+    }
+
+    fn record_at(&mut self, now: Instant) {
+        self.rate = self.decayed(now) + 1.0 / RATE_WINDOW.as_secs_f64();
+        self.updated = now;
+    }
+
+    pub(crate) fn stats(&self, updates: u64, clock: &Clock) -> KeyStats {
+        let now = clock.now();
+        let idle = now - self.updated;
+        KeyStats {
+            updates,
+            updates_per_second: self.decayed(now),
+            last_update: clock.system_now() - idle,
+            idle,
+        }
+    }
+
+    fn decayed(&self, now: Instant) -> f64 {
+        let elapsed = (now - self.updated).as_secs_f64();
+        self.rate * decay(elapsed / RATE_WINDOW.as_secs_f64())
+    }
+}
+
+// How much a rate decays over `windows` rate windows.
+#[cfg(feature = "std")]
+fn decay(windows: f64) -> f64 {
+    (-windows).exp()
+}
+
+// Without the standard library there is no clock, so no time passes for a rate to decay over.
+#[cfg(not(feature = "std"))]
+fn decay(_windows: f64) -> f64 {
+    1.0
+}
+
+// Counts an observer as active for as long as it stays registered.
+pub(crate) struct ObserverGauge(Arc<Metrics>);
+
+impl ObserverGauge {
+    pub(crate) fn new(metrics: Arc<Metrics>) -> Self {
+        metrics.observers.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!("observable_maps_active_observers").increment(1.0);
+        Self(metrics)
+    }
+}
+
+impl Drop for ObserverGauge {
+    fn drop(&mut self) {
+        self.0.observers.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        ::metrics::gauge!("observable_maps_active_observers").decrement(1.0);
+    }
+}
+
+#[cfg(all(test, os))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_rate_approaches_steady_rate() {
+        let clock = Clock::default();
+        let mut rate = UpdateRate::new(&clock);
+        // Backdate the updates as if one arrived every 100ms for a minute.
+        let start = Instant::now() - Duration::from_secs(60);
+        rate.updated = start;
+        for i in 1..=600 {
+            rate.record_at(start + Duration::from_millis(100 * i));
+        }
+
+        let stats = rate.stats(601, &clock);
+        assert!((stats.updates_per_second - 10.0).abs() < 0.5);
+        assert!(stats.idle < Duration::from_secs(1));
+    }
+}
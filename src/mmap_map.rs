@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(os)]
+use std::time::Instant;
+
+use memmap2::MmapMut;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(os)]
+use crate::clock::Clock;
+use crate::registry::{self, Items, Registry};
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+#[cfg(os)]
+use crate::{throttle, wait_if_unset, WaitError};
+use crate::{
+    Attributed, Backpressure, DeliveryReport, InsertError, Item, Metrics, ObservableMap,
+    ObserveError, Receiver, WriteInfo,
+};
+
+// The file starts with the little-endian length of the records written to it, each of which is
+// the lengths of its bincode-encoded key and value, as little-endian `u32`s, then the key and
+// the value. The file is grown ahead of the records so the mapping is rarely replaced. Each
+// record is flushed before the length that counts it, so the length never covers a record
+// that didn't reach the disk.
+const HEADER_LEN: usize = 8;
+const RECORD_HEADER_LEN: usize = 8;
+const INITIAL_LEN: usize = 64 * 1024;
+
+/// An [`ObservableMap`] whose values live in a memory-mapped file rather than on the heap,
+/// so maps larger than RAM are paged in and out by the operating system. Only an index of
+/// where each value is stored is kept in memory, alongside the observers, which behave as
+/// they do for [`ObserverMap`](crate::ObserverMap) but aren't persisted.
+///
+/// Every insert appends the value to the file and waits until it is durable, so a value whose
+/// insert has returned survives a crash, and a crash mid-insert loses only that value. Space
+/// held by overwritten values isn't reclaimed. Values are decoded on every read.
+pub struct MmapObserverMap<K, V> {
+    registry: Registry<HashMap<K, Entry<V>>>,
+    file: File,
+    mmap: MmapMut,
+    // The end of the last record.
+    len: usize,
+}
+
+// A key's observers and the bytes of its value in the file. The item never holds the value.
+struct Entry<V> {
+    item: Item<V>,
+    value: Option<Range<usize>>,
+}
+
+impl<V> Entry<V> {
+    fn empty() -> Self {
+        Self {
+            item: Item::empty(),
+            value: None,
+        }
+    }
+}
+
+impl<K, V> Items for HashMap<K, Entry<V>>
+where
+    K: Hash + Eq,
+{
+    type Key = K;
+    type Value = V;
+
+    const KEEPS_VALUES: bool = false;
+
+    fn get(&self, key: &K) -> Option<&Item<V>> {
+        HashMap::get(self, key).map(|entry| &entry.item)
+    }
+
+    fn get_or_insert(&mut self, key: K) -> &mut Item<V> {
+        &mut self.entry(key).or_insert_with(Entry::empty).item
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Item<V>)) {
+        self.values().for_each(|entry| f(&entry.item))
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(&mut Item<V>)) {
+        self.values_mut().for_each(|entry| f(&mut entry.item))
+    }
+}
+
+fn invalid(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<usize> {
+    let bytes = bytes.get(at..at + 4)?.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes) as usize)
+}
+
+fn map(file: &File) -> io::Result<MmapMut> {
+    // SAFETY: the file is only written through this mapping, and isn't expected to be
+    // modified by other processes while the map is open.
+    unsafe { MmapMut::map_mut(file) }
+}
+
+impl<K, V> MmapObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Opens the file at `path`, creating it if it doesn't exist, and indexes the values
+    /// stored in it without reading them.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() < HEADER_LEN as u64 {
+            file.set_len(INITIAL_LEN as u64)?;
+        }
+        let mmap = map(&file)?;
+        let len = u64::from_le_bytes(mmap[..HEADER_LEN].try_into().unwrap()) as usize;
+        let len = len.max(HEADER_LEN);
+        if len > mmap.len() {
+            return Err(invalid("record length exceeds the file length"));
+        }
+
+        let mut entries = HashMap::new();
+        let mut at = HEADER_LEN;
+        while at < len {
+            let (Some(key_len), Some(value_len)) = (read_u32(&mmap, at), read_u32(&mmap, at + 4))
+            else {
+                return Err(invalid("truncated record"));
+            };
+            let key_start = at + RECORD_HEADER_LEN;
+            let value_start = key_start + key_len;
+            let end = value_start + value_len;
+            if end > len {
+                return Err(invalid("truncated record"));
+            }
+            let key = bincode::deserialize(&mmap[key_start..value_start]).map_err(invalid)?;
+            let entry = entries.entry(key).or_insert_with(Entry::empty);
+            entry.item.writes += 1;
+            entry.value = Some(value_start..end);
+            at = end;
+        }
+
+        Ok(Self {
+            registry: Registry::new(entries),
+            file,
+            mmap,
+            len,
+        })
+    }
+
+    /// Waits until every value written so far is durable on disk. Inserts already wait for
+    /// their own values, so this is rarely needed.
+    pub fn flush(&self) -> io::Result<()> {
+        self.mmap.flush()
+    }
+
+    /// See [`ObserverMap::close`](crate::ObserverMap::close). Values already written stay in
+    /// the file.
+    pub fn close(&mut self) {
+        self.registry.close()
+    }
+
+    /// See [`ObserverMap::set_send_timeout`](crate::ObserverMap::set_send_timeout).
+    pub fn set_send_timeout(&mut self, timeout: Duration) {
+        self.registry.set_send_timeout(timeout)
+    }
+
+    /// See [`ObserverMap::set_metrics`](crate::ObserverMap::set_metrics).
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.registry.set_metrics(metrics)
+    }
+
+    // Appends the key and value to the file, returning where the value was written.
+    fn append(&mut self, key: &K, value: &V) -> io::Result<Range<usize>> {
+        let encode = |error| io::Error::new(io::ErrorKind::InvalidInput, error);
+        let key = bincode::serialize(key).map_err(encode)?;
+        let value = bincode::serialize(value).map_err(encode)?;
+        let too_long = || io::Error::new(io::ErrorKind::InvalidInput, "record too long");
+        let key_len = u32::try_from(key.len()).map_err(|_| too_long())?;
+        let value_len = u32::try_from(value.len()).map_err(|_| too_long())?;
+
+        let value_start = self.len + RECORD_HEADER_LEN + key.len();
+        let end = value_start + value.len();
+        if end > self.mmap.len() {
+            let len = end.max(self.mmap.len() * 2);
+            self.mmap.flush()?;
+            self.file.set_len(len as u64)?;
+            self.mmap = map(&self.file)?;
+        }
+        let record = &mut self.mmap[self.len..end];
+        record[..4].copy_from_slice(&key_len.to_le_bytes());
+        record[4..8].copy_from_slice(&value_len.to_le_bytes());
+        record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + key.len()].copy_from_slice(&key);
+        record[RECORD_HEADER_LEN + key.len()..].copy_from_slice(&value);
+        // The record is only counted once it is on disk in full.
+        self.mmap.flush_range(self.len, end - self.len)?;
+        self.mmap[..HEADER_LEN].copy_from_slice(&(end as u64).to_le_bytes());
+        if let Err(error) = self.mmap.flush_range(0, HEADER_LEN) {
+            self.mmap[..HEADER_LEN].copy_from_slice(&(self.len as u64).to_le_bytes());
+            return Err(error);
+        }
+        self.len = end;
+        Ok(value_start..end)
+    }
+}
+
+impl<K, V> MmapObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    fn insert_notifying(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<DeliveryReport, InsertError<V>> {
+        let value = self.registry.check_open(value)?;
+        let range = match self.append(&key, &value) {
+            Ok(range) => range,
+            Err(error) => return Err(InsertError::Persist(value, error.kind())),
+        };
+        let entry = self.registry.items.entry(key.clone());
+        entry.or_insert_with(Entry::empty).value = Some(range);
+        Ok(self.registry.update(key, value, group, writer))
+    }
+
+    fn insert_checked(
+        &mut self,
+        key: K,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<(), InsertError<V>> {
+        let report = self.insert_notifying(key, value.clone(), group, writer)?;
+        registry::reject_if_full(report, value)
+    }
+
+    /// See [`ObserverMap::insert_as`](crate::ObserverMap::insert_as).
+    pub fn insert_as(&mut self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, Some(writer))
+    }
+
+    /// See [`ObserverMap::write_info`](crate::ObserverMap::write_info).
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        self.registry.write_info(&key)
+    }
+
+    /// See [`ObserverMap::observe_attributed`](crate::ObserverMap::observe_attributed).
+    pub fn observe_attributed(&mut self, key: K) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        self.registry.observe_attributed(key)
+    }
+}
+
+impl<K, V> ObservableMap<K, V> for MmapObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, None)
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        self.insert_notifying(key, value, None, None)
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, Some(group), None)
+    }
+
+    /// Decodes the value from the file. A value that can no longer be decoded, because the
+    /// file was modified externally, reads as `None`.
+    fn get(&self, key: K) -> Option<V> {
+        self.registry.record_get();
+        let range = self.registry.items.get(&key)?.value.clone()?;
+        bincode::deserialize(&self.mmap[range]).ok()
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let current = match self.get(key.clone()) {
+            Some(value) => Ok(value),
+            None => Err(self.observe(key)?),
+        };
+        wait_if_unset(self, current, started)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.registry.is_closed()
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.registry.observer_count(&key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.registry.total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.registry.metrics()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_backpressure(key, backpressure)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_capacity(key, capacity)
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_unbounded(key)
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let current = self.get(key.clone());
+        self.registry.observe_with_initial(key, current)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(&mut self, key: K, interval: Duration) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        let rx = self.observe_unbounded(key)?;
+        Ok(throttle(rx, interval, Clock::default()))
+    }
+
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_group(key, group)
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.on_update_with_priority(key, 0, callback)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.registry
+            .on_update_with_priority(key, priority, callback)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_priority(key, priority)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        let current = self.get(key.clone());
+        self.registry.watch(key, current)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        self.registry.broadcast(key, capacity)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async_with_capacity(key, capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A path unique to the test, removed when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let file = format!("observable-maps-{}-{name}.mmap", std::process::id());
+            let path = std::env::temp_dir().join(file);
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn write_header(path: &Path, len: u64) {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mut mmap = map(&file).unwrap();
+        mmap[..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+        mmap.flush().unwrap();
+    }
+
+    #[test]
+    fn values_survive_reopening_without_being_loaded() {
+        let path = TempPath::new("reopen");
+
+        {
+            let mut map = MmapObserverMap::open(&path.0).unwrap();
+            let rx = map.observe_unbounded("a".to_string()).unwrap();
+            map.insert("a".to_string(), vec![1u8; 40_000]).unwrap();
+            map.insert("b".to_string(), vec![2; 40_000]).unwrap();
+            map.insert("a".to_string(), vec![3; 10]).unwrap();
+            assert_eq!(rx.try_iter().count(), 2);
+            assert!(map
+                .registry
+                .items
+                .values()
+                .all(|entry| entry.item.value.is_none()));
+        }
+
+        let mut map = MmapObserverMap::<String, Vec<u8>>::open(&path.0).unwrap();
+        assert_eq!(map.get("a".to_string()), Some(vec![3; 10]));
+        assert_eq!(map.get("b".to_string()), Some(vec![2; 40_000]));
+        assert_eq!(map.write_info("a".to_string()).unwrap().writes, 2);
+        assert_eq!(map.total_observers(), 0);
+
+        map.insert("c".to_string(), vec![4]).unwrap();
+        drop(map);
+        let map = MmapObserverMap::<String, Vec<u8>>::open(&path.0).unwrap();
+        assert_eq!(map.get("c".to_string()), Some(vec![4]));
+    }
+
+    #[test]
+    fn growing_the_file_remaps_without_losing_values_or_observers() {
+        let path = TempPath::new("grow");
+        let metrics = Arc::new(Metrics::new());
+        let mut map = MmapObserverMap::open(&path.0).unwrap();
+        map.set_metrics(metrics.clone());
+        let rx = map.observe_unbounded(0u32).unwrap();
+
+        for key in 0..64u32 {
+            map.insert(key, vec![key as u8; 4096]).unwrap();
+        }
+
+        assert!(std::fs::metadata(&path.0).unwrap().len() > INITIAL_LEN as u64);
+        for key in 0..64u32 {
+            assert_eq!(map.get(key), Some(vec![key as u8; 4096]));
+        }
+        assert_eq!(rx.try_iter().count(), 1);
+        map.insert(0, vec![]).unwrap();
+        assert_eq!(rx.try_recv(), Ok(vec![]));
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.inserts, 65);
+        assert_eq!(snapshot.notifications, 2);
+        assert_eq!(snapshot.gets, 64);
+    }
+
+    #[test]
+    fn records_beyond_the_length_are_ignored_and_overwritten() {
+        let path = TempPath::new("torn");
+        let len = {
+            let mut map = MmapObserverMap::open(&path.0).unwrap();
+            map.insert("a".to_string(), 1u64).unwrap();
+            map.len
+        };
+        // A record written without its length, as if the process crashed mid-insert.
+        {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path.0)
+                .unwrap();
+            let mut mmap = map(&file).unwrap();
+            mmap[len..len + RECORD_HEADER_LEN].copy_from_slice(&[0xff; RECORD_HEADER_LEN]);
+            mmap.flush().unwrap();
+        }
+
+        let mut map = MmapObserverMap::<String, u64>::open(&path.0).unwrap();
+        assert_eq!(map.get("a".to_string()), Some(1));
+        map.insert("b".to_string(), 2).unwrap();
+        drop(map);
+
+        let map = MmapObserverMap::<String, u64>::open(&path.0).unwrap();
+        assert_eq!(map.get("a".to_string()), Some(1));
+        assert_eq!(map.get("b".to_string()), Some(2));
+    }
+
+    #[test]
+    fn lengths_covering_a_truncated_tail_fail_to_open() {
+        let path = TempPath::new("truncated");
+        let len = {
+            let mut map = MmapObserverMap::open(&path.0).unwrap();
+            map.insert("a".to_string(), 1u64).unwrap();
+            map.insert("b".to_string(), 2u64).unwrap();
+            map.len
+        };
+
+        write_header(&path.0, len as u64 - 1);
+        let error = MmapObserverMap::<String, u64>::open(&path.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        write_header(&path.0, INITIAL_LEN as u64 + 1);
+        let error = MmapObserverMap::<String, u64>::open(&path.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        write_header(&path.0, len as u64);
+        let map = MmapObserverMap::<String, u64>::open(&path.0).unwrap();
+        assert_eq!(map.get("b".to_string()), Some(2));
+    }
+}
@@ -0,0 +1,166 @@
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{MapEvent, ObservableMap, ThreadSafeObserverMap};
+
+// Sent to a client when it connects, then once per change to the served map.
+#[derive(Serialize, Deserialize)]
+enum Frame<K, V> {
+    Snapshot { next_seq: u64, values: Vec<(K, V)> },
+    Event(MapEvent<K, V>),
+}
+
+// The longest frame either end accepts, so a corrupt or hostile length prefix can't make the
+// reader allocate without limit.
+const MAX_FRAME_LEN: u32 = 256 << 20;
+
+// The changes held for a client that hasn't received them yet. A client that falls further
+// behind is disconnected.
+const CLIENT_BACKLOG: usize = 1 << 16;
+
+// Frames are bincode encoded, prefixed by their length as a big-endian `u32`.
+fn write_frame<T: Serialize>(writer: &mut impl Write, frame: &T) -> io::Result<()> {
+    let bytes = bincode::serialize(frame).map_err(invalid_data)?;
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(frame_too_long)?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+// Reads the next frame, or `None` if the stream ended cleanly between frames.
+fn read_frame<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<Option<T>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(frame_too_long());
+    }
+    let mut bytes = vec![0; len as usize];
+    reader.read_exact(&mut bytes)?;
+    bincode::deserialize(&bytes).map(Some).map_err(invalid_data)
+}
+
+fn invalid_data(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn frame_too_long() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "frame too long")
+}
+
+/// Accepts connections on `listener`, streaming the map's current values and then every
+/// subsequent insert to each client on its own thread. Runs until accepting a connection fails.
+///
+/// A client that falls too far behind the map's changes is disconnected, rather than the
+/// changes it hasn't received building up without limit.
+pub fn serve<K, V>(map: &ThreadSafeObserverMap<K, V>, listener: TcpListener) -> io::Result<()>
+where
+    K: Hash + Eq + Clone + Serialize + Send + Sync + 'static,
+    V: Clone + Serialize + Send + Sync + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept()?;
+        // Subscribe and snapshot under one lock, so the client misses no insert in between.
+        let (snapshot, events) = {
+            let mut map = map.write();
+            let values = map.snapshot().into_iter().collect();
+            let snapshot = Frame::<K, V>::Snapshot {
+                next_seq: map.next_seq(),
+                values,
+            };
+            (snapshot, map.events_with_capacity(CLIENT_BACKLOG))
+        };
+        thread::spawn(move || -> io::Result<()> {
+            let mut writer = BufWriter::new(stream);
+            write_frame(&mut writer, &snapshot)?;
+            for event in events.iter() {
+                write_frame(&mut writer, &Frame::Event(event))?;
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Connects to a map being served with [`serve`], returning a replica holding its current
+/// values. A background thread applies subsequent inserts to the replica, notifying its
+/// observers, until the connection closes.
+pub fn connect<K, V>(addr: impl ToSocketAddrs) -> io::Result<ThreadSafeObserverMap<K, V>>
+where
+    K: Hash + Eq + Clone + DeserializeOwned + Send + Sync + 'static,
+    V: Clone + DeserializeOwned + Send + Sync + 'static,
+{
+    let mut reader = BufReader::new(TcpStream::connect(addr)?);
+    let replica = ThreadSafeObserverMap::new();
+    match read_frame(&mut reader)? {
+        Some(Frame::Snapshot { next_seq, values }) => {
+            let mut map = replica.write();
+            for (key, value) in values {
+                // Can't fail: the map is open and has no observers.
+                let _ = map.insert(key, value);
+            }
+            map.seq = next_seq;
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a snapshot",
+            ))
+        }
+    }
+    let map = replica.clone();
+    thread::spawn(move || -> io::Result<()> {
+        while let Some(frame) = read_frame(&mut reader)? {
+            if let Frame::Event(event) = frame {
+                if map.apply_event(event).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    });
+    Ok(replica)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn replica_receives_snapshot_and_updates() {
+        let map = ThreadSafeObserverMap::new();
+        let mut primary = map.clone();
+        primary.insert("a".to_string(), 1u32).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(&map, listener));
+
+        let mut replica = connect::<String, u32>(addr).unwrap();
+        assert_eq!(replica.get("a".to_string()), Some(1));
+
+        let rx = replica.observe("b".to_string()).unwrap();
+        primary.insert("b".to_string(), 2).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(2));
+        assert_eq!(replica.next_seq(), primary.next_seq());
+    }
+
+    #[test]
+    fn rejects_frames_longer_than_the_maximum() {
+        let mut stream = &(MAX_FRAME_LEN + 1).to_be_bytes()[..];
+        let error = read_frame::<u32>(&mut stream).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,318 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+#[cfg(feature = "tokio")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use crate::channel::{SendError, TrySendError};
+use crate::sync::lock::{Mutex, PoisonError};
+
+use crate::channel::Sender;
+use crate::delta::DeltaObserver;
+use crate::project::Projection;
+use crate::{Attributed, Generational};
+
+/// What happens when an observer's channel is full at the time a value is inserted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Block the inserting thread until the observer makes room, or until the map's
+    /// [send timeout](crate::ObserverMap::set_send_timeout) elapses, in which case the
+    /// observer misses the value. The map's lock is held whilst blocked, so a stalled
+    /// observer stalls every other caller until then.
+    #[default]
+    Block,
+    /// Discard the value being inserted for this observer.
+    DropNewest,
+    /// Discard the oldest value still queued for this observer to make room.
+    DropOldest,
+    /// Fail the insert with [`InsertError::Full`](crate::InsertError::Full).
+    Error,
+}
+
+// The outcome of sending a value to a single observer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    Delivered,
+    // Delivered, but only after blocking because the channel was full.
+    Lagged,
+    // Delivered by evicting the oldest value queued because the channel was full.
+    Displaced,
+    // Discarded by the observer's backpressure policy.
+    Dropped,
+    // The observer's receiver has gone away.
+    Disconnected,
+    // The observer's channel was full and its backpressure policy rejects the value.
+    Rejected,
+    // The observer's channel stayed full for longer than the map's send timeout.
+    TimedOut,
+}
+
+impl Delivery {
+    pub(crate) fn record(self, report: &mut DeliveryReport) {
+        match self {
+            Delivery::Delivered | Delivery::Lagged | Delivery::Displaced => report.notified += 1,
+            Delivery::Dropped => report.dropped += 1,
+            Delivery::Disconnected => report.disconnected += 1,
+            Delivery::Rejected => report.rejected += 1,
+            Delivery::TimedOut => report.timed_out += 1,
+        }
+    }
+
+    pub(crate) fn is_delivered(self) -> bool {
+        matches!(
+            self,
+            Delivery::Delivered | Delivery::Lagged | Delivery::Displaced
+        )
+    }
+
+    // Whether the observer failed to keep up with this delivery.
+    pub(crate) fn is_slow(self) -> bool {
+        self == Delivery::Lagged || self.lost_value()
+    }
+
+    // Whether the observer missed a value, this one or an older one, because it was full.
+    pub(crate) fn lost_value(self) -> bool {
+        matches!(
+            self,
+            Delivery::Displaced | Delivery::Dropped | Delivery::Rejected | Delivery::TimedOut
+        )
+    }
+}
+
+/// A summary of how an inserted value was delivered to the key's observers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryReport {
+    /// Observers the value was delivered to.
+    pub notified: usize,
+    /// Observers found disconnected, which have now been removed.
+    pub disconnected: usize,
+    /// Observers whose [`Backpressure::DropNewest`] policy discarded the value.
+    pub dropped: usize,
+    /// Observers whose [`Backpressure::Error`] policy rejected the value.
+    pub rejected: usize,
+    /// [`Backpressure::Block`] observers that didn't make room for the value within the map's
+    /// send timeout. See
+    /// [`ObserverMap::set_send_timeout`](crate::ObserverMap::set_send_timeout).
+    pub timed_out: usize,
+    /// Observers removed for repeatedly failing to keep up. See
+    /// [`ObserverMap::evict_slow_observers`](crate::ObserverMap::evict_slow_observers).
+    pub evicted: usize,
+}
+
+pub(crate) type Callback<T> = Box<dyn FnMut(&T) + Send>;
+
+pub(crate) enum Observer<T> {
+    Channel {
+        tx: Sender<T>,
+        backpressure: Backpressure,
+        // Stream observers receive every value until their receiver is dropped, otherwise
+        // only the next value is delivered.
+        stream: bool,
+    },
+    // Locked so the map stays `Sync` even though the callback needn't be.
+    Callback(Mutex<Callback<T>>),
+    // Receives every value along with its writer, over an unbounded channel.
+    Attributed(Sender<Attributed<T>>),
+    // Receives every value along with the map's generation, over an unbounded channel.
+    Generational(Sender<Generational<T>>, Arc<AtomicU64>),
+    // Receives the change from the previous value, over an unbounded channel.
+    Delta(Box<dyn DeltaObserver<T>>),
+    // Receives a projection of each value, over an unbounded channel.
+    Projected(Box<dyn Projection<T>>),
+    #[cfg(feature = "tokio")]
+    Watch(tokio::sync::watch::Sender<Option<T>>),
+    #[cfg(feature = "tokio")]
+    Broadcast(tokio::sync::broadcast::Sender<T>),
+    #[cfg(feature = "tokio")]
+    Async(tokio::sync::mpsc::UnboundedSender<T>),
+    // Receives values over a bounded channel, and is disconnected once it falls so far behind
+    // that the channel is full.
+    #[cfg(feature = "tokio")]
+    AsyncBounded {
+        tx: tokio::sync::mpsc::Sender<T>,
+        lagged: AtomicBool,
+    },
+}
+
+impl<T> Observer<T> {
+    pub(crate) fn once(tx: Sender<T>) -> Self {
+        Observer::Channel {
+            tx,
+            backpressure: Backpressure::Block,
+            stream: false,
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn async_bounded(tx: tokio::sync::mpsc::Sender<T>) -> Self {
+        Observer::AsyncBounded {
+            tx,
+            lagged: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn stream(tx: Sender<T>, backpressure: Backpressure) -> Self {
+        Observer::Channel {
+            tx,
+            backpressure,
+            stream: true,
+        }
+    }
+
+    // Delivers `value`, which replaces `previous`. Only delta observers need `previous`. They
+    // and projected observers are delivered without a clone of `value`.
+    pub(crate) fn notify(
+        &self,
+        previous: Option<&T>,
+        value: &T,
+        writer: Option<&Arc<str>>,
+        timeout: Option<Duration>,
+    ) -> Delivery
+    where
+        T: Clone,
+    {
+        match self {
+            Observer::Delta(observer) => observer.send(previous, value),
+            Observer::Projected(observer) => observer.send(value),
+            observer => observer.send(value.clone(), writer, timeout),
+        }
+    }
+
+    pub(crate) fn send(
+        &self,
+        value: T,
+        writer: Option<&Arc<str>>,
+        timeout: Option<Duration>,
+    ) -> Delivery {
+        match self {
+            Observer::Channel {
+                tx, backpressure, ..
+            } => {
+                let result = match backpressure {
+                    Backpressure::Block => match tx.try_send(value) {
+                        Err(TrySendError::Full(value)) => {
+                            let result = match timeout {
+                                Some(timeout) => tx.send_timeout(value, timeout),
+                                None => tx
+                                    .send(value)
+                                    .map_err(|SendError(value)| TrySendError::Disconnected(value)),
+                            };
+                            return match result {
+                                Ok(()) => Delivery::Lagged,
+                                Err(TrySendError::Full(_)) => Delivery::TimedOut,
+                                Err(TrySendError::Disconnected(_)) => Delivery::Disconnected,
+                            };
+                        }
+                        result => result,
+                    },
+                    Backpressure::DropNewest => match tx.try_send(value) {
+                        Err(TrySendError::Full(_)) => return Delivery::Dropped,
+                        result => result,
+                    },
+                    Backpressure::DropOldest => match tx.force_send(value) {
+                        Ok(Some(_)) => return Delivery::Displaced,
+                        Ok(None) => Ok(()),
+                        Err(SendError(value)) => Err(TrySendError::Disconnected(value)),
+                    },
+                    Backpressure::Error => tx.try_send(value),
+                };
+                match result {
+                    Ok(()) => Delivery::Delivered,
+                    Err(TrySendError::Disconnected(_)) => Delivery::Disconnected,
+                    Err(TrySendError::Full(_)) => Delivery::Rejected,
+                }
+            }
+            Observer::Attributed(tx) => {
+                let writer = writer.cloned();
+                match tx.send(Attributed { value, writer }) {
+                    Ok(()) => Delivery::Delivered,
+                    Err(_) => Delivery::Disconnected,
+                }
+            }
+            Observer::Generational(tx, generation) => {
+                let generation = generation.load(Ordering::Acquire);
+                match tx.send(Generational { value, generation }) {
+                    Ok(()) => Delivery::Delivered,
+                    Err(_) => Delivery::Disconnected,
+                }
+            }
+            Observer::Callback(callback) => {
+                callback.lock().unwrap_or_else(PoisonError::into_inner)(&value);
+                Delivery::Delivered
+            }
+            Observer::Delta(observer) => observer.send(None, &value),
+            Observer::Projected(observer) => observer.send(&value),
+            #[cfg(feature = "tokio")]
+            Observer::Watch(tx) => {
+                tx.send_replace(Some(value));
+                Delivery::Delivered
+            }
+            #[cfg(feature = "tokio")]
+            Observer::Broadcast(tx) => match tx.send(value) {
+                Ok(_) => Delivery::Delivered,
+                Err(_) => Delivery::Disconnected,
+            },
+            #[cfg(feature = "tokio")]
+            Observer::Async(tx) => match tx.send(value) {
+                Ok(()) => Delivery::Delivered,
+                Err(_) => Delivery::Disconnected,
+            },
+            #[cfg(feature = "tokio")]
+            Observer::AsyncBounded { tx, lagged } => match tx.try_send(value) {
+                Ok(()) => Delivery::Delivered,
+                Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                    lagged.store(true, Ordering::Release);
+                    Delivery::Dropped
+                }
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => Delivery::Disconnected,
+            },
+        }
+    }
+
+    // Whether the observer should remain registered after a value has been delivered.
+    pub(crate) fn is_subscribed(&self) -> bool {
+        let once = matches!(self, Observer::Channel { stream: false, .. });
+        !once && self.receivers() > 0
+    }
+
+    // The number of values queued for the observer's receiver, unless it doesn't queue them.
+    pub(crate) fn pending(&self) -> Option<usize> {
+        match self {
+            Observer::Channel { tx, .. } => Some(tx.len()),
+            Observer::Callback(_) => None,
+            Observer::Attributed(tx) => Some(tx.len()),
+            Observer::Generational(tx, _) => Some(tx.len()),
+            Observer::Delta(observer) => Some(observer.pending()),
+            Observer::Projected(observer) => Some(observer.pending()),
+            #[cfg(feature = "tokio")]
+            Observer::Watch(_) => None,
+            #[cfg(feature = "tokio")]
+            Observer::Broadcast(tx) => Some(tx.len()),
+            #[cfg(feature = "tokio")]
+            Observer::Async(_) | Observer::AsyncBounded { .. } => None,
+        }
+    }
+
+    // The number of live receivers listening through this observer.
+    pub(crate) fn receivers(&self) -> usize {
+        match self {
+            Observer::Channel { tx, .. } => usize::from(!tx.is_disconnected()),
+            Observer::Callback(_) => 1,
+            Observer::Attributed(tx) => usize::from(!tx.is_disconnected()),
+            Observer::Generational(tx, _) => usize::from(!tx.is_disconnected()),
+            Observer::Delta(observer) => observer.receivers(),
+            Observer::Projected(observer) => observer.receivers(),
+            #[cfg(feature = "tokio")]
+            Observer::Watch(tx) => tx.receiver_count(),
+            #[cfg(feature = "tokio")]
+            Observer::Broadcast(tx) => tx.receiver_count(),
+            #[cfg(feature = "tokio")]
+            Observer::Async(tx) => usize::from(!tx.is_closed()),
+            #[cfg(feature = "tokio")]
+            Observer::AsyncBounded { tx, lagged } => {
+                usize::from(!tx.is_closed() && !lagged.load(Ordering::Acquire))
+            }
+        }
+    }
+}
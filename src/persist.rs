@@ -0,0 +1,225 @@
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "encryption")]
+use crate::EncryptionKey;
+use crate::{ObserverMap, ThreadSafeObserverMap};
+
+/// The encoding of a file written by [`ObserverMap::save_to`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Bincode,
+}
+
+fn invalid_data(error: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// The file written before being renamed over `path`, named after the whole of `path` so that
+// files differing only in their extension don't share it.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    partial.into()
+}
+
+// Writes a file alongside `path` then renames it over `path`, so a crash part way through
+// leaves any previous file intact.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let partial = partial_path(path);
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(partial, path)
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    /// Writes the map's keys and current values to `path`. The file is written alongside
+    /// `path` then renamed over it, so a crash part way through leaves any previous file intact.
+    pub fn save_to(&self, path: impl AsRef<Path>, format: Format) -> io::Result<()> {
+        write_atomically(path.as_ref(), |writer| match format {
+            Format::Json => serde_json::to_writer(writer, self).map_err(invalid_data),
+            Format::Bincode => bincode::serialize_into(writer, self).map_err(invalid_data),
+        })
+    }
+
+    /// Like [`save_to`](ObserverMap::save_to), but encrypts the file with `key`.
+    #[cfg(feature = "encryption")]
+    pub fn save_encrypted_to(
+        &self,
+        path: impl AsRef<Path>,
+        format: Format,
+        key: &EncryptionKey,
+    ) -> io::Result<()> {
+        let plaintext = match format {
+            Format::Json => serde_json::to_vec(self).map_err(invalid_data)?,
+            Format::Bincode => bincode::serialize(self).map_err(invalid_data)?,
+        };
+        write_atomically(path.as_ref(), |writer| {
+            writer.write_all(&key.encrypt(&plaintext))
+        })
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: DeserializeOwned + Hash + Eq,
+    V: DeserializeOwned + Clone,
+{
+    /// Creates a map holding the values saved to `path` with [`save_to`](ObserverMap::save_to).
+    pub fn load_from(path: impl AsRef<Path>, format: Format) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        match format {
+            Format::Json => serde_json::from_reader(reader).map_err(invalid_data),
+            Format::Bincode => bincode::deserialize_from(reader).map_err(invalid_data),
+        }
+    }
+
+    /// Creates a map holding the values saved to `path` with
+    /// [`save_encrypted_to`](ObserverMap::save_encrypted_to).
+    #[cfg(feature = "encryption")]
+    pub fn load_encrypted_from(
+        path: impl AsRef<Path>,
+        format: Format,
+        key: &EncryptionKey,
+    ) -> io::Result<Self> {
+        let plaintext = key.decrypt(&fs::read(path)?)?;
+        match format {
+            Format::Json => serde_json::from_slice(&plaintext).map_err(invalid_data),
+            Format::Bincode => bincode::deserialize(&plaintext).map_err(invalid_data),
+        }
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    /// See [`ObserverMap::save_to`]. The map is read-locked whilst it is written.
+    pub fn save_to(&self, path: impl AsRef<Path>, format: Format) -> io::Result<()> {
+        self.read().save_to(path, format)
+    }
+
+    /// See [`ObserverMap::save_encrypted_to`]. The map is read-locked whilst it is serialized.
+    #[cfg(feature = "encryption")]
+    pub fn save_encrypted_to(
+        &self,
+        path: impl AsRef<Path>,
+        format: Format,
+        key: &EncryptionKey,
+    ) -> io::Result<()> {
+        self.read().save_encrypted_to(path, format, key)
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: DeserializeOwned + Hash + Eq,
+    V: DeserializeOwned + Clone,
+{
+    /// See [`ObserverMap::load_from`].
+    pub fn load_from(path: impl AsRef<Path>, format: Format) -> io::Result<Self> {
+        Ok(Self::from(ObserverMap::load_from(path, format)?))
+    }
+
+    /// See [`ObserverMap::load_encrypted_from`].
+    #[cfg(feature = "encryption")]
+    pub fn load_encrypted_from(
+        path: impl AsRef<Path>,
+        format: Format,
+        key: &EncryptionKey,
+    ) -> io::Result<Self> {
+        Ok(Self::from(ObserverMap::load_encrypted_from(
+            path, format, key,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn saves_and_loads_in_each_format() {
+        let map = ThreadSafeObserverMap::new();
+        let mut handle = map.clone();
+        handle.insert("a".to_string(), 1u64).unwrap();
+        handle.insert("b".to_string(), 2).unwrap();
+
+        for format in [Format::Json, Format::Bincode] {
+            let path = std::env::temp_dir().join(format!(
+                "observable-maps-{}-{:?}.snapshot",
+                std::process::id(),
+                format
+            ));
+            map.save_to(&path, format).unwrap();
+            let loaded = ThreadSafeObserverMap::<String, u64>::load_from(&path, format).unwrap();
+            assert_eq!(loaded.snapshot(), map.snapshot());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_snapshot_needs_the_key() {
+        let mut map = ObserverMap::new();
+        map.insert("password".to_string(), "hunter2".to_string())
+            .unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "observable-maps-{}-encrypted.snapshot",
+            std::process::id()
+        ));
+        let key = EncryptionKey::new([1; 32]);
+        map.save_encrypted_to(&path, Format::Json, &key).unwrap();
+
+        let contents = fs::read(&path).unwrap();
+        assert!(!contents.windows(7).any(|window| window == b"hunter2"));
+        let loaded =
+            ObserverMap::<String, String>::load_encrypted_from(&path, Format::Json, &key).unwrap();
+        assert_eq!(loaded.snapshot(), map.snapshot());
+        let wrong = EncryptionKey::new([2; 32]);
+        let error = ObserverMap::<String, String>::load_encrypted_from(&path, Format::Json, &wrong)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn partial_files_are_named_after_the_whole_path() {
+        let json = partial_path(Path::new("dir/state.json"));
+        assert_eq!(json, Path::new("dir/state.json.partial"));
+        assert_ne!(json, partial_path(Path::new("dir/state.bin")));
+    }
+
+    #[test]
+    fn load_fails_on_corrupt_file() {
+        let path = std::env::temp_dir().join(format!(
+            "observable-maps-{}-corrupt.snapshot",
+            std::process::id()
+        ));
+        fs::write(&path, b"{\"a\":").unwrap();
+
+        let error = ObserverMap::<String, u64>::load_from(&path, Format::Json)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use alloc::boxed::Box;
+use core::hash::Hash;
+
+use crate::channel::{self, Sender};
+use crate::observer::Delivery;
+use crate::{ObserveError, Observer, ObserverMap, Receiver, ThreadSafeObserverMap};
+
+// An observer sending something computed from a reference to each value, whose type is
+// erased so the map's observers only depend on `T`.
+pub(crate) trait Projection<T>: Send + Sync {
+    fn send(&self, value: &T) -> Delivery;
+    fn receivers(&self) -> usize;
+    fn pending(&self) -> usize;
+}
+
+struct Projected<U, F> {
+    tx: Sender<U>,
+    f: F,
+}
+
+impl<T, U, F> Projection<T> for Projected<U, F>
+where
+    U: Send,
+    F: Fn(&T) -> U + Send + Sync,
+{
+    fn send(&self, value: &T) -> Delivery {
+        match self.tx.send((self.f)(value)) {
+            Ok(()) => Delivery::Delivered,
+            Err(_) => Delivery::Disconnected,
+        }
+    }
+
+    fn receivers(&self) -> usize {
+        usize::from(!self.tx.is_disconnected())
+    }
+
+    fn pending(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone + 'static,
+{
+    /// Observes every update to `key` as the projection `f` of the new value, for example one
+    /// field of a large value, which is computed from a reference to the value instead of a
+    /// clone of it. `f` runs on the inserting thread whilst the map is locked, so it should be
+    /// quick and must not access the map.
+    ///
+    /// Projections queue up without limit if the receiver falls behind.
+    pub fn observe_map<U, F>(&mut self, key: K, f: F) -> Result<Receiver<U>, ObserveError>
+    where
+        U: Send + 'static,
+        F: Fn(&V) -> U + Send + Sync + 'static,
+    {
+        let (tx, rx) = channel::unbounded();
+        self.add_observer(key, Observer::Projected(Box::new(Projected { tx, f })))?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone + 'static,
+{
+    /// See [`ObserverMap::observe_map`].
+    pub fn observe_map<U, F>(&self, key: K, f: F) -> Result<Receiver<U>, ObserveError>
+    where
+        U: Send + 'static,
+        F: Fn(&V) -> U + Send + Sync + 'static,
+    {
+        self.write().observe_map(key, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    // Fails the test if a value is cloned.
+    #[derive(Debug)]
+    struct Book {
+        bids: Vec<u32>,
+    }
+
+    impl Clone for Book {
+        fn clone(&self) -> Self {
+            panic!("cloned a book")
+        }
+    }
+
+    #[test]
+    fn projections_are_sent_without_cloning_values() {
+        let mut map = ObserverMap::new();
+        let best_bid = map
+            .observe_map("book", |book: &Book| book.bids.iter().max().copied())
+            .unwrap();
+        map.insert("book", Book { bids: vec![3, 5] }).unwrap();
+        map.insert("book", Book { bids: vec![] }).unwrap();
+
+        assert_eq!(best_bid.try_iter().collect::<Vec<_>>(), vec![Some(5), None]);
+        drop(best_bid);
+        map.insert("book", Book { bids: vec![1] }).unwrap();
+        assert_eq!(map.observer_count("book"), 0);
+    }
+}
@@ -0,0 +1,312 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::mem;
+
+use crate::collections::HashMap;
+
+use crate::journal::Change;
+use crate::{ObserverMap, ThreadSafeObserverMap};
+
+/// The bytes a value accounts for towards a map's
+/// [memory quota](ObserverMap::set_memory_quota): its inline size plus the heap memory it
+/// owns. Implement it for value types that own memory through pointers the map can't see.
+pub trait SizeOf {
+    fn size_of(&self) -> usize;
+}
+
+macro_rules! inline_size_of {
+    ($($t:ty),*) => {
+        $(impl SizeOf for $t {
+            fn size_of(&self) -> usize {
+                mem::size_of::<$t>()
+            }
+        })*
+    };
+}
+
+inline_size_of!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+impl SizeOf for String {
+    fn size_of(&self) -> usize {
+        mem::size_of::<String>() + self.capacity()
+    }
+}
+
+impl<T: SizeOf> SizeOf for Vec<T> {
+    fn size_of(&self) -> usize {
+        let spare = self.capacity() - self.len();
+        mem::size_of::<Vec<T>>()
+            + spare * mem::size_of::<T>()
+            + self.iter().map(SizeOf::size_of).sum::<usize>()
+    }
+}
+
+impl<T: SizeOf> SizeOf for Option<T> {
+    fn size_of(&self) -> usize {
+        match self {
+            Some(value) => value.size_of() + mem::size_of::<Self>() - mem::size_of::<T>(),
+            None => mem::size_of::<Self>(),
+        }
+    }
+}
+
+impl<T: SizeOf + ?Sized> SizeOf for Box<T> {
+    fn size_of(&self) -> usize {
+        mem::size_of::<Self>() + T::size_of(self)
+    }
+}
+
+/// Which values a map evicts first once it exceeds its memory quota.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// The values that were written longest ago.
+    #[default]
+    LeastRecentlyWritten,
+    /// The largest values, freeing the most memory per eviction.
+    Largest,
+}
+
+// The bytes accounted for by each of a map's values, and the order to evict them in.
+pub(crate) struct MemoryQuota<K, V> {
+    max_bytes: usize,
+    used: usize,
+    policy: EvictionPolicy,
+    size_of: Box<dyn Fn(&V) -> usize + Send + Sync>,
+    // Each value's size and its place in `order`.
+    values: HashMap<K, (usize, Place)>,
+    order: BTreeMap<Place, K>,
+    // Breaks ties between places of the same rank, in the order they were written.
+    writes: u64,
+    // Monomorphised when the quota is set, so that the map needn't require `K: Clone`.
+    clone_key: fn(&K) -> K,
+}
+
+type Place = (usize, u64);
+
+impl<K, V> MemoryQuota<K, V>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn written(&mut self, key: &K, value: &V) {
+        self.removed(key);
+        let bytes = (self.size_of)(value);
+        let rank = match self.policy {
+            EvictionPolicy::LeastRecentlyWritten => 0,
+            EvictionPolicy::Largest => usize::MAX - bytes,
+        };
+        let place = (rank, self.writes);
+        self.writes += 1;
+        self.values.insert((self.clone_key)(key), (bytes, place));
+        self.order.insert(place, (self.clone_key)(key));
+        self.used += bytes;
+    }
+
+    pub(crate) fn removed(&mut self, key: &K) {
+        if let Some((bytes, place)) = self.values.remove(key) {
+            self.order.remove(&place);
+            self.used -= bytes;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+        self.order.clear();
+        self.used = 0;
+    }
+
+    // The next value to evict, other than `keep`'s, if the quota is exceeded.
+    fn victim(&self, keep: Option<&K>) -> Option<K> {
+        if self.used <= self.max_bytes {
+            return None;
+        }
+        let key = self.order.values().find(|key| Some(*key) != keep)?;
+        Some((self.clone_key)(key))
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// Limits the values the map holds to `max_bytes`, as estimated by [`SizeOf`]. Whenever
+    /// an insert exceeds the quota, values are evicted in the order given by `policy` until
+    /// it is met again, so the map can run as a shared cache. Evicted values read as `None`,
+    /// and each eviction is recorded in the journal and sent to event subscribers as a
+    /// [`Change::Evicted`]. Evictions start a new [`generation`](ObserverMap::generation).
+    /// Observers of evicted keys stay registered and aren't notified.
+    ///
+    /// The value just inserted is never evicted, so a single value larger than the quota is
+    /// kept until the next insert. Values the map already holds count towards the quota
+    /// immediately.
+    pub fn set_memory_quota(&mut self, max_bytes: usize, policy: EvictionPolicy)
+    where
+        K: Clone,
+        V: SizeOf + 'static,
+    {
+        self.set_memory_quota_with(max_bytes, policy, V::size_of)
+    }
+
+    /// Like [`set_memory_quota`](ObserverMap::set_memory_quota), but estimates the bytes each
+    /// value accounts for with `size_of`.
+    pub fn set_memory_quota_with<F>(&mut self, max_bytes: usize, policy: EvictionPolicy, size_of: F)
+    where
+        K: Clone,
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        let mut quota = MemoryQuota {
+            max_bytes,
+            used: 0,
+            policy,
+            size_of: Box::new(size_of),
+            values: HashMap::default(),
+            order: BTreeMap::new(),
+            writes: 0,
+            clone_key: K::clone,
+        };
+        for (key, item) in &self.hashmap {
+            if let Some(value) = &item.value {
+                quota.written(key, value);
+            }
+        }
+        self.quota = Some(quota);
+        self.enforce_quota(None);
+    }
+
+    /// The bytes the map's values account for towards its memory quota, or `None` if it has
+    /// none.
+    pub fn memory_quota_used(&self) -> Option<usize> {
+        self.quota.as_ref().map(|quota| quota.used)
+    }
+
+    // Accounts for the value being inserted, evicting other values to make room for it.
+    pub(crate) fn charge_quota(&mut self, key: &K, value: &V) {
+        if let Some(quota) = &mut self.quota {
+            quota.written(key, value);
+            self.enforce_quota(Some(key));
+        }
+    }
+
+    // Evicts values, other than `keep`'s, until the quota is met, starting a new generation if
+    // any were.
+    pub(crate) fn enforce_quota(&mut self, keep: Option<&K>) {
+        let mut evicted = false;
+        while let Some(key) = self.quota.as_ref().and_then(|quota| quota.victim(keep)) {
+            self.evict(&key);
+            evicted = true;
+        }
+        if evicted {
+            self.next_generation();
+        }
+    }
+
+    // Removes the key's value, recording the eviction.
+    pub(crate) fn evict(&mut self, key: &K) {
+        if let Some(quota) = &mut self.quota {
+            quota.removed(key);
+        }
+        let Some(item) = self.hashmap.get_mut(key) else {
+            return;
+        };
+        if item.value.take().is_none() {
+            return;
+        }
+        if item.is_empty() {
+            self.hashmap.remove(key);
+        }
+        self.sequence_change(key, || Change::Evicted);
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObserverMap::set_memory_quota`].
+    pub fn set_memory_quota(&self, max_bytes: usize, policy: EvictionPolicy)
+    where
+        K: Clone,
+        V: SizeOf + 'static,
+    {
+        self.write().set_memory_quota(max_bytes, policy)
+    }
+
+    /// See [`ObserverMap::set_memory_quota_with`].
+    pub fn set_memory_quota_with<F>(&self, max_bytes: usize, policy: EvictionPolicy, size_of: F)
+    where
+        K: Clone,
+        F: Fn(&V) -> usize + Send + Sync + 'static,
+    {
+        self.write()
+            .set_memory_quota_with(max_bytes, policy, size_of)
+    }
+
+    /// See [`ObserverMap::memory_quota_used`].
+    pub fn memory_quota_used(&self) -> Option<usize> {
+        self.read().memory_quota_used()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MapEvent, ObservableMap};
+
+    #[test]
+    fn evicts_the_least_recently_written_values_beyond_the_quota() {
+        let mut map = ObserverMap::new();
+        let events = map.events();
+        map.set_memory_quota_with(10, EvictionPolicy::LeastRecentlyWritten, |v: &Vec<u8>| {
+            v.len()
+        });
+        let rx = map.observe_unbounded("a").unwrap();
+        map.insert("a", vec![0; 4]).unwrap();
+        map.insert("b", vec![0; 4]).unwrap();
+        map.insert("a", vec![0; 4]).unwrap();
+        assert_eq!(map.memory_quota_used(), Some(8));
+        assert_eq!(map.generation(), 0);
+
+        map.insert("c", vec![0; 4]).unwrap();
+        assert_eq!(map.generation(), 1);
+        assert_eq!(map.get("b"), None);
+        assert!(map.get("a").is_some());
+        assert_eq!(map.memory_quota_used(), Some(8));
+
+        map.insert_reporting("d", vec![0; 20]).unwrap();
+        assert_eq!(map.get("d"), Some(vec![0; 20]));
+        assert_eq!(map.get("a"), None);
+        assert_eq!(map.get("c"), None);
+        assert_eq!(map.generation(), 2);
+        assert_eq!(map.observer_count("a"), 1);
+        assert_eq!(rx.try_iter().count(), 2);
+
+        let evicted: Vec<_> = events
+            .try_iter()
+            .filter(|event| event.change == Change::Evicted)
+            .map(|MapEvent { key, .. }| key)
+            .collect();
+        assert_eq!(evicted, vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn evicts_the_largest_values_first() {
+        let mut map = ObserverMap::new();
+        map.insert(1, "x".repeat(100)).unwrap();
+        map.insert(2, "x".repeat(10)).unwrap();
+        map.insert(3, "x".repeat(50)).unwrap();
+        map.set_memory_quota(200, EvictionPolicy::Largest);
+
+        assert_eq!(map.get(1), None);
+        assert!(map.get(2).is_some() && map.get(3).is_some());
+        assert_eq!(
+            map.memory_quota_used(),
+            Some(2 * mem::size_of::<String>() + 60)
+        );
+    }
+}
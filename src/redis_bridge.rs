@@ -0,0 +1,90 @@
+use std::hash::Hash;
+use std::io;
+
+use redis::{Commands, Connection, RedisResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Change, InsertError, ObservableMap, ThreadSafeObserverMap};
+
+/// Publishes every subsequent insert into `map` to Redis, as the JSON-encoded value on the
+/// channel named by `channel` for the key. Blocks until the map is
+/// [closed](ThreadSafeObserverMap::close) or publishing fails, so is typically run on its own
+/// thread.
+pub fn publish<K, V, F>(
+    map: &ThreadSafeObserverMap<K, V>,
+    connection: &mut Connection,
+    channel: F,
+) -> RedisResult<()>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + Serialize,
+    F: Fn(&K) -> String,
+{
+    for event in map.events().iter() {
+        let Change::Inserted(value) = event.change else {
+            continue;
+        };
+        let payload = serde_json::to_vec(&value)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        connection.publish::<_, _, ()>(channel(&event.key), payload)?;
+    }
+    Ok(())
+}
+
+/// Subscribes to the Redis channels matching `pattern`, inserting each message into `map`,
+/// notifying its observers. `key` maps a channel name to the key to insert, and the payload is
+/// decoded from JSON. Messages on channels without a key, or with payloads that aren't a
+/// JSON-encoded value, are skipped. Blocks until the connection fails or the map is closed.
+pub fn subscribe<K, V, F>(
+    map: &mut ThreadSafeObserverMap<K, V>,
+    connection: &mut Connection,
+    pattern: &str,
+    key: F,
+) -> RedisResult<()>
+where
+    K: Hash + Eq,
+    V: Clone + DeserializeOwned,
+    F: Fn(&str) -> Option<K>,
+{
+    let mut pubsub = connection.as_pubsub();
+    pubsub.psubscribe(pattern)?;
+    loop {
+        let message = pubsub.get_message()?;
+        let Some((key, value)) = decode(
+            message.get_channel_name(),
+            message.get_payload_bytes(),
+            &key,
+        ) else {
+            continue;
+        };
+        if let Err(InsertError::Closed(_)) = map.insert(key, value) {
+            return Ok(());
+        }
+    }
+}
+
+fn decode<K, V, F>(channel: &str, payload: &[u8], key: &F) -> Option<(K, V)>
+where
+    V: DeserializeOwned,
+    F: Fn(&str) -> Option<K>,
+{
+    Some((key(channel)?, serde_json::from_slice(payload).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_messages_with_a_key_and_value() {
+        let key = |channel: &str| channel.strip_prefix("prices.").map(str::to_string);
+
+        assert_eq!(
+            decode("prices.btc", b"42", &key),
+            Some(("btc".to_string(), 42u32))
+        );
+        assert_eq!(decode::<_, u32, _>("volumes.btc", b"42", &key), None);
+        assert_eq!(decode::<_, u32, _>("prices.btc", b"\"42\"", &key), None);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::collections::HashMap;
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::hash::Hash;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+
+use crate::clock::Clock;
+use crate::metrics::ObserverGauge;
+use crate::sync::lock::Mutex;
+use crate::sync::{self, AtomicUsize};
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+use crate::{
+    channel, Attributed, Backpressure, DeliveryReport, InsertError, Item, Limits, Metrics,
+    ObserveError, Observer, Receiver, Registration, WriteInfo,
+};
+
+// Where a backend keeps each key's `Item`, which holds the key's observers and write info.
+pub(crate) trait Items {
+    type Key;
+    type Value;
+
+    // Whether the items hold each key's value, rather than the backend storing it elsewhere.
+    const KEEPS_VALUES: bool = true;
+
+    fn get(&self, key: &Self::Key) -> Option<&Item<Self::Value>>;
+
+    // The key's item, adding an empty one if it has none.
+    fn get_or_insert(&mut self, key: Self::Key) -> &mut Item<Self::Value>;
+
+    fn for_each(&self, f: &mut dyn FnMut(&Item<Self::Value>));
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(&mut Item<Self::Value>));
+}
+
+impl<K, V> Items for HashMap<K, Item<V>>
+where
+    K: Hash + Eq,
+{
+    type Key = K;
+    type Value = V;
+
+    fn get(&self, key: &K) -> Option<&Item<V>> {
+        HashMap::get(self, key)
+    }
+
+    fn get_or_insert(&mut self, key: K) -> &mut Item<V> {
+        self.entry(key).or_insert_with(Item::empty)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Item<V>)) {
+        self.values().for_each(f)
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(&mut Item<V>)) {
+        self.values_mut().for_each(f)
+    }
+}
+
+// Registers observers of each key and notifies them of inserts, for the backends other than
+// `ObserverMap`, which they delegate to so that every backend applies the same send timeout,
+// metrics and unregistration of dropped receivers.
+pub(crate) struct Registry<S> {
+    pub(crate) items: S,
+    closed: bool,
+    // Receivers dropped since their senders were last unregistered.
+    dropped: sync::Arc<AtomicUsize>,
+    limits: Limits,
+    metrics: Option<Arc<Metrics>>,
+}
+
+// Fails with `Full` if an observer rejected the value, as `insert` does.
+pub(crate) fn reject_if_full<V>(report: DeliveryReport, value: V) -> Result<(), InsertError<V>> {
+    match report.rejected {
+        0 => Ok(()),
+        _ => Err(InsertError::Full(value)),
+    }
+}
+
+impl<S> Registry<S> {
+    pub(crate) fn new(items: S) -> Self {
+        Self {
+            items,
+            closed: false,
+            dropped: sync::Arc::new(AtomicUsize::new(0)),
+            limits: Limits::default(),
+            metrics: None,
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub(crate) fn set_send_timeout(&mut self, timeout: Duration) {
+        self.limits.send_timeout = Some(timeout);
+    }
+
+    pub(crate) fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    pub(crate) fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.metrics.clone()
+    }
+
+    pub(crate) fn record_get(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_get();
+        }
+    }
+}
+
+impl<S> Registry<S>
+where
+    S: Items,
+{
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+        self.items.for_each_mut(&mut |item| item.observers = None);
+    }
+
+    // Returns the value if the map is open, for backends that store it before notifying.
+    pub(crate) fn check_open(&self, value: S::Value) -> Result<S::Value, InsertError<S::Value>> {
+        match self.closed {
+            true => Err(InsertError::Closed(value)),
+            false => Ok(value),
+        }
+    }
+}
+
+impl<S> Registry<S>
+where
+    S: Items,
+    S::Value: Clone,
+{
+    pub(crate) fn insert(
+        &mut self,
+        key: S::Key,
+        value: S::Value,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<DeliveryReport, InsertError<S::Value>> {
+        let value = self.check_open(value)?;
+        Ok(self.update(key, value, group, writer))
+    }
+
+    // Records the write and notifies the key's observers, whether or not the map is closed.
+    pub(crate) fn update(
+        &mut self,
+        key: S::Key,
+        value: S::Value,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> DeliveryReport {
+        let item = self.items.get_or_insert(key);
+        let report = item.update(value, group, writer, self.limits, &Clock::default());
+        if !S::KEEPS_VALUES {
+            item.value = None;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_insert(&report);
+        }
+        self.unregister_dropped();
+        report
+    }
+
+    fn unregister_dropped(&mut self) {
+        if self.dropped.swap(0, Ordering::Acquire) > 0 {
+            self.items.for_each_mut(&mut |item| {
+                item.purge_dead_observers();
+            });
+        }
+    }
+
+    pub(crate) fn register(
+        &mut self,
+        key: S::Key,
+        mut registration: Registration<S::Value>,
+    ) -> Result<(), ObserveError> {
+        if self.closed {
+            return Err(ObserveError::Closed);
+        }
+        registration.gauge = self.metrics.clone().map(ObserverGauge::new);
+        self.unregister_dropped();
+        self.items.get_or_insert(key).register(registration);
+        Ok(())
+    }
+
+    fn add_observer(
+        &mut self,
+        key: S::Key,
+        observer: Observer<S::Value>,
+    ) -> Result<(), ObserveError> {
+        self.register(key, Registration::new(observer))
+    }
+
+    // Registers the sender, returning its receiver.
+    pub(crate) fn add_channel<T>(
+        &mut self,
+        key: S::Key,
+        observer: Observer<S::Value>,
+        rx: Receiver<T>,
+    ) -> Result<Receiver<T>, ObserveError> {
+        self.add_observer(key, observer)?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    pub(crate) fn observer_count(&self, key: &S::Key) -> usize {
+        self.items.get(key).map_or(0, Item::observer_count)
+    }
+
+    pub(crate) fn total_observers(&self) -> usize {
+        let mut total = 0;
+        self.items
+            .for_each(&mut |item| total += item.observer_count());
+        total
+    }
+
+    pub(crate) fn write_info(&self, key: &S::Key) -> Option<WriteInfo> {
+        let item = self.items.get(key)?;
+        (item.writes > 0).then(|| WriteInfo {
+            last_writer: item.writer().cloned(),
+            writes: item.writes,
+        })
+    }
+
+    pub(crate) fn observe(&mut self, key: S::Key) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        self.add_channel(key, Observer::once(tx), rx)
+    }
+
+    pub(crate) fn observe_with_backpressure(
+        &mut self,
+        key: S::Key,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        self.add_channel(key, Observer::stream(tx, backpressure), rx)
+    }
+
+    pub(crate) fn observe_with_capacity(
+        &mut self,
+        key: S::Key,
+        capacity: usize,
+    ) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::bounded(capacity);
+        self.add_channel(key, Observer::stream(tx, Backpressure::Block), rx)
+    }
+
+    pub(crate) fn observe_unbounded(
+        &mut self,
+        key: S::Key,
+    ) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.add_channel(key, Observer::stream(tx, Backpressure::Block), rx)
+    }
+
+    // Like `observe_unbounded`, starting with `current`, the key's value if it has one.
+    pub(crate) fn observe_with_initial(
+        &mut self,
+        key: S::Key,
+        current: Option<S::Value>,
+    ) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        if let Some(value) = current {
+            // Can't fail: the receiver is still held.
+            let _ = tx.send(value);
+        }
+        self.add_channel(key, Observer::stream(tx, Backpressure::Block), rx)
+    }
+
+    pub(crate) fn observe_attributed(
+        &mut self,
+        key: S::Key,
+    ) -> Result<Receiver<Attributed<S::Value>>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.add_channel(key, Observer::Attributed(tx), rx)
+    }
+
+    pub(crate) fn observe_group(
+        &mut self,
+        key: S::Key,
+        group: &str,
+    ) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        let observer = Observer::stream(tx, Backpressure::Block);
+        self.register(
+            key,
+            Registration {
+                group: Some(group.to_string()),
+                ..Registration::new(observer)
+            },
+        )?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    pub(crate) fn observe_with_priority(
+        &mut self,
+        key: S::Key,
+        priority: i32,
+    ) -> Result<Receiver<S::Value>, ObserveError> {
+        let (tx, rx) = channel::bounded(1);
+        let observer = Observer::stream(tx, Backpressure::Block);
+        self.register(
+            key,
+            Registration {
+                priority,
+                ..Registration::new(observer)
+            },
+        )?;
+        Ok(rx.unregister_on_drop(&self.dropped))
+    }
+
+    pub(crate) fn on_update_with_priority<F>(
+        &mut self,
+        key: S::Key,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&S::Value) + Send + 'static,
+    {
+        let observer = Observer::Callback(Mutex::new(Box::new(callback)));
+        self.register(
+            key,
+            Registration {
+                priority,
+                ..Registration::new(observer)
+            },
+        )
+    }
+
+    // Watches the key, starting from `current`, its value if it has one.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn watch(
+        &mut self,
+        key: S::Key,
+        current: Option<S::Value>,
+    ) -> Result<tokio::sync::watch::Receiver<Option<S::Value>>, ObserveError> {
+        let (tx, rx) = tokio::sync::watch::channel(current);
+        self.add_observer(key, Observer::Watch(tx))?;
+        Ok(rx)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn broadcast(
+        &mut self,
+        key: S::Key,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<S::Value>, ObserveError> {
+        let existing = self.items.get(&key).and_then(Item::broadcaster);
+        if let Some(tx) = existing {
+            return Ok(tx.subscribe());
+        }
+        let (tx, rx) = tokio::sync::broadcast::channel(capacity);
+        self.add_observer(key, Observer::Broadcast(tx))?;
+        Ok(rx)
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn observe_async(
+        &mut self,
+        key: S::Key,
+    ) -> Result<AsyncReceiver<S::Value>, ObserveError> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.add_observer(key, Observer::Async(tx))?;
+        Ok(AsyncReceiver::new(rx))
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn observe_async_with_capacity(
+        &mut self,
+        key: S::Key,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<S::Value>, ObserveError> {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        self.add_observer(key, Observer::async_bounded(tx))?;
+        Ok(AsyncReceiver::bounded(rx))
+    }
+}
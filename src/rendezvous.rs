@@ -0,0 +1,113 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::{InsertError, ObservableMap, ObserverMap, Rejection, ThreadSafeObserverMap};
+
+type Combine<V> = Box<dyn Fn(Vec<V>) -> V + Send + Sync>;
+
+// The contributions to the current round of a key's rendezvous.
+pub(crate) struct Rendezvous<V> {
+    participants: usize,
+    arrived: Vec<V>,
+    combine: Combine<V>,
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// Makes `key` a rendezvous of `participants`, each contributing a value with
+    /// [`arrive`](ObserverMap::arrive). Once the last has arrived, their values, in order of
+    /// arrival, are combined and inserted, and the next round begins. Observers of the key
+    /// therefore see one value per round, for example to fan in the partial results of
+    /// several workers. Replaces any rendezvous already set for the key.
+    ///
+    /// `combine` runs whilst the map is locked, so it must not access the map.
+    pub fn rendezvous<F>(&mut self, key: K, participants: usize, combine: F)
+    where
+        F: Fn(Vec<V>) -> V + Send + Sync + 'static,
+    {
+        let rendezvous = Rendezvous {
+            participants: participants.max(1),
+            arrived: Vec::new(),
+            combine: Box::new(combine),
+        };
+        self.rendezvous.insert(key, rendezvous);
+    }
+
+    /// Contributes `value` to the key's current round, returning how many participants are
+    /// still awaited, which is 0 once the combined value has been inserted. Fails with
+    /// [`InsertError::Invalid`] if the key isn't a rendezvous, or with the error from
+    /// inserting the combined value.
+    pub fn arrive(&mut self, key: K, value: V) -> Result<usize, InsertError<V>> {
+        if self.closed {
+            return Err(InsertError::Closed(value));
+        }
+        let Some(rendezvous) = self.rendezvous.get_mut(&key) else {
+            return Err(InsertError::Invalid(
+                value,
+                Rejection::new("key is not a rendezvous"),
+            ));
+        };
+        rendezvous.arrived.push(value);
+        let awaited = rendezvous.participants - rendezvous.arrived.len();
+        if awaited > 0 {
+            return Ok(awaited);
+        }
+        let combined = (rendezvous.combine)(core::mem::take(&mut rendezvous.arrived));
+        self.insert(key, combined)?;
+        Ok(0)
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObserverMap::rendezvous`].
+    pub fn rendezvous<F>(&self, key: K, participants: usize, combine: F)
+    where
+        F: Fn(Vec<V>) -> V + Send + Sync + 'static,
+    {
+        self.write().rendezvous(key, participants, combine)
+    }
+
+    /// See [`ObserverMap::arrive`].
+    pub fn arrive(&self, key: K, value: V) -> Result<usize, InsertError<V>> {
+        self.write_unleased(&key).arrive(key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn publishes_once_every_participant_has_arrived() {
+        let mut map = ThreadSafeObserverMap::new();
+        map.rendezvous("sum", 3, |parts: Vec<u32>| parts.iter().sum());
+        let rx = map.observe_unbounded("sum").unwrap();
+
+        let workers: Vec<_> = (1..=3)
+            .map(|part| {
+                let map = map.clone();
+                thread::spawn(move || map.arrive("sum", part).unwrap())
+            })
+            .collect();
+        let mut awaited: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        awaited.sort();
+        assert_eq!(awaited, vec![0, 1, 2]);
+        assert_eq!(rx.recv(), Ok(6));
+
+        assert_eq!(map.arrive("sum", 10), Ok(2));
+        assert!(rx.try_recv().is_err());
+        assert!(matches!(
+            map.arrive("other", 1),
+            Err(InsertError::Invalid(1, _))
+        ));
+    }
+}
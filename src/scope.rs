@@ -0,0 +1,118 @@
+use alloc::sync::Arc;
+use core::hash::Hash;
+
+use crate::{
+    channel, run_hook, Backpressure, ObserveError, Observer, ObserverMap, Receiver, Registration,
+    ThreadSafeObserverMap,
+};
+
+/// Keeps an observer registered by [`ThreadSafeObserverMap::observe_scoped`] in the map.
+/// Dropping the guard removes the observer before `drop` returns, even if its receiver is
+/// still held, so a subscription can't outlive the request that made it.
+pub struct ScopeGuard<K, V>
+where
+    K: Hash + Eq,
+{
+    map: ThreadSafeObserverMap<K, V>,
+    key: K,
+    // Taken when the guard is dropped, leaving the observer's scope dangling.
+    scope: Option<Arc<()>>,
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq,
+{
+    // Removes the key's observers whose scope has been dropped.
+    fn unregister_scoped(&mut self, key: &K) {
+        let Some(item) = self.hashmap.get_mut(key) else {
+            return;
+        };
+        let Some(observers) = &mut item.observers else {
+            return;
+        };
+        observers.retain(|registration| {
+            registration
+                .scope
+                .as_ref()
+                .is_none_or(|scope| scope.strong_count() > 0)
+        });
+        if observers.is_empty() {
+            item.observers = None;
+            run_hook(&self.on_last_observer, key);
+            if item.is_empty() {
+                self.hashmap.remove(key);
+            }
+        }
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    /// Like [`observe_unbounded`](crate::ObservableMap::observe_unbounded), but the observer
+    /// is removed when the returned guard is dropped.
+    pub fn observe_scoped(&self, key: K) -> Result<(ScopeGuard<K, V>, Receiver<V>), ObserveError> {
+        let scope = Arc::new(());
+        let (tx, rx) = channel::unbounded();
+        let mut map = self.write();
+        map.register(
+            key.clone(),
+            Registration {
+                scope: Some(Arc::downgrade(&scope)),
+                ..Registration::new(Observer::stream(tx, Backpressure::Block))
+            },
+        )?;
+        let rx = rx.unregister_on_drop(&map.dropped);
+        drop(map);
+        let guard = ScopeGuard {
+            map: self.clone(),
+            key,
+            scope: Some(scope),
+        };
+        Ok((guard, rx))
+    }
+}
+
+impl<K, V> ScopeGuard<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
+
+impl<K, V> Drop for ScopeGuard<K, V>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        self.scope = None;
+        self.map.write().unregister_scoped(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ObservableMap;
+
+    #[test]
+    fn dropping_the_guard_removes_the_observer() {
+        let mut map = ThreadSafeObserverMap::new();
+        let _other = map.observe_unbounded("key".to_string()).unwrap();
+        let (guard, rx) = map.observe_scoped("key".to_string()).unwrap();
+        map.insert("key".to_string(), 1).unwrap();
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(guard.key(), "key");
+        assert_eq!(map.observer_count("key".to_string()), 2);
+
+        drop(guard);
+        assert_eq!(map.observer_count("key".to_string()), 1);
+        map.insert("key".to_string(), 2).unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::collections::HashMap;
+use alloc::sync::Arc;
+use core::cell::UnsafeCell;
+use core::hash::Hash;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+use crate::sync::lock::{PoisonError, RwLock};
+
+use crate::{InsertError, Reader, ThreadSafeObserverMap};
+
+// A value written by one thread at a time and read without locking. The sequence number is odd
+// whilst a write is in progress, and readers retry if it was odd or changed during their read.
+//
+// The value isn't stored in atomics, since `V` may have padding that can't be copied into
+// them, so a read overlapping a write is a data race under Rust's memory model, as in other
+// seqlocks, crossbeam's `AtomicCell` among them. Volatile accesses keep the compiler from
+// assuming the value is unchanged between the sequence number checks. A torn copy may not be a
+// valid `Option<V>`, since its discriminant or a `bool` or `char` in `V` may be half written, so
+// it's copied as a `MaybeUninit` and only assumed to be initialised once the sequence number
+// shows it wasn't torn.
+struct Seqlock<V> {
+    seq: AtomicUsize,
+    value: UnsafeCell<Option<V>>,
+}
+
+// SAFETY: writes are serialised by the map's lock, and readers only keep copies of values
+// that the sequence number shows weren't being written whilst they were read. See `Seqlock`
+// for the race between a read and a write.
+unsafe impl<V: Copy + Send> Sync for Seqlock<V> {}
+
+impl<V: Copy> Seqlock<V> {
+    fn new(value: Option<V>) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn read(&self) -> Option<V> {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before & 1 == 0 {
+                // SAFETY: the pointer is valid and aligned for the value. The read may race with
+                // a write, so the copy is made as a `MaybeUninit`, which may hold any bytes.
+                let value = unsafe {
+                    ptr::read_volatile(self.value.get().cast::<MaybeUninit<Option<V>>>())
+                };
+                atomic::fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == before {
+                    // SAFETY: the sequence number didn't change, so no write overlapped the
+                    // copy and it holds the whole of the value last written.
+                    return unsafe { value.assume_init() };
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    // Must only be called by one thread at a time.
+    fn write(&self, value: V) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+        // SAFETY: writers are serialised, and concurrent readers discard what they read.
+        unsafe { ptr::write_volatile(self.value.get(), Some(value)) };
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+/// A map of small `Copy` values, such as the latest price of each instrument, whose values can
+/// be read without locking through a [`SeqlockReader`]. Each value is guarded by a sequence
+/// counter that readers check to retry reads that overlapped a write, so readers never block
+/// writers, though they spin whilst a write is in progress. Inserts lock the map and notify
+/// observers as a [`ThreadSafeObserverMap`] does, so writes are slower and reads don't see a
+/// value until its observers have been notified.
+///
+/// [`get`](SeqlockObserverMap::get) briefly takes a read lock to look the key up, so only
+/// readers are lock-free.
+pub struct SeqlockObserverMap<K, V> {
+    map: ThreadSafeObserverMap<K, V>,
+    slots: RwLock<HashMap<K, Arc<Seqlock<V>>>>,
+}
+
+/// Reads one key of a [`SeqlockObserverMap`] without locking, returned by
+/// [`SeqlockObserverMap::reader`].
+pub struct SeqlockReader<V> {
+    slot: Arc<Seqlock<V>>,
+}
+
+impl<V> Clone for SeqlockReader<V> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<V: Copy> SeqlockReader<V> {
+    pub fn get(&self) -> Option<V> {
+        self.slot.read()
+    }
+}
+
+impl<K, V> SeqlockObserverMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Copy + Send,
+{
+    pub fn new() -> Self {
+        Self {
+            map: ThreadSafeObserverMap::new(),
+            slots: RwLock::new(HashMap::default()),
+        }
+    }
+
+    /// Inserts a value as [`ObservableMap::insert`](crate::ObservableMap::insert) does,
+    /// publishing it to lock-free readers once observers have been notified.
+    pub fn insert(&self, key: K, value: V) -> Result<(), InsertError<V>> {
+        let mut map = self.map.write();
+        let result = crate::ObservableMap::insert(&mut *map, key.clone(), value);
+        // Interceptors and resolvers may have changed the value, and a `Full` error still
+        // stores it.
+        if let Some(stored) = map.hashmap.get(&key).and_then(|item| item.value) {
+            self.slot(key).write(stored);
+        }
+        result
+    }
+
+    /// The key's value. Takes a read lock on the map's keys to look the key up, which waits
+    /// whilst a key is written for the first time, then reads the value without locking. Use
+    /// a [`reader`](SeqlockObserverMap::reader) to read without locking at all.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let slots = self.slots.read().unwrap_or_else(PoisonError::into_inner);
+        slots.get(key)?.read()
+    }
+
+    /// A handle that reads `key` without locking or looking it up.
+    pub fn reader(&self, key: K) -> SeqlockReader<V> {
+        SeqlockReader {
+            slot: self.slot(key),
+        }
+    }
+
+    /// A handle to observe and wait for the map's keys, which can't insert.
+    pub fn observers(&self) -> Reader<K, V> {
+        self.map.clone().split().1
+    }
+
+    pub fn close(&self) {
+        self.map.close()
+    }
+
+    fn slot(&self, key: K) -> Arc<Seqlock<V>> {
+        let slots = self.slots.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(slot) = slots.get(&key) {
+            return slot.clone();
+        }
+        drop(slots);
+        let mut slots = self.slots.write().unwrap_or_else(PoisonError::into_inner);
+        slots
+            .entry(key)
+            .or_insert_with(|| Arc::new(Seqlock::new(None)))
+            .clone()
+    }
+}
+
+impl<K, V> Default for SeqlockObserverMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Copy + Send,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn readers_never_see_torn_values() {
+        let map = Arc::new(SeqlockObserverMap::new());
+        let reader = map.reader("px");
+        assert_eq!(reader.get(), None);
+        let rx = map.observers().observe_unbounded("px").unwrap();
+
+        let writer = {
+            let map = map.clone();
+            thread::spawn(move || {
+                for i in 0..10_000u64 {
+                    map.insert("px", (i, i * 2)).unwrap();
+                }
+            })
+        };
+        while reader.get() != Some((9_999, 19_998)) {
+            if let Some((price, size)) = reader.get() {
+                assert_eq!(size, price * 2);
+            }
+        }
+        writer.join().unwrap();
+
+        assert_eq!(map.get(&"px"), Some((9_999, 19_998)));
+        assert_eq!(rx.try_iter().count(), 10_000);
+        assert_eq!(map.get(&"missing"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::de::Error as _;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Item, ObservableMap, ObserverMap, ThreadSafeObserverMap};
+
+// Maps serialize as their keys and current values. Observers, and keys that are observed but
+// have no value yet, are omitted.
+impl<K, V> Serialize for ObserverMap<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let values = self
+            .hashmap
+            .iter()
+            .filter_map(|(key, item)| Some((key, self.read(item)?)));
+        // Some formats need the length up front.
+        let mut map = serializer.serialize_map(Some(values.clone().count()))?;
+        for (key, value) in values {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for ObserverMap<K, V>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de> + Clone,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = HashMap::<K, V>::deserialize(deserializer)?;
+        let mut map = Self::new();
+        map.hashmap = values
+            .into_iter()
+            .map(|(key, value)| (key, Item::new(value, None, &map.clock)))
+            .collect();
+        Ok(map)
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// Inserts every value serialized by `deserializer`, notifying observers of each as if it
+    /// had been inserted directly. Observers rejecting a value don't stop the restore. Starts
+    /// a new [`generation`](ObserverMap::generation).
+    pub fn restore<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        let values = HashMap::<K, V>::deserialize(deserializer)?;
+        self.next_generation();
+        for (key, value) in values {
+            self.insert_reporting(key, value)
+                .map_err(D::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the contents under a single read lock. See [`ObserverMap`].
+impl<K, V> Serialize for ThreadSafeObserverMap<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.read().serialize(serializer)
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for ThreadSafeObserverMap<K, V>
+where
+    K: Deserialize<'de> + Hash + Eq,
+    V: Deserialize<'de> + Clone,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = ObserverMap::deserialize(deserializer)?;
+        Ok(Self::from(map))
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObserverMap::restore`].
+    pub fn restore<'de, D>(&self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        self.write().restore(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_values_through_json() {
+        let mut map = ObserverMap::new();
+        map.insert("a".to_string(), 1).unwrap();
+        map.insert("b".to_string(), 2).unwrap();
+        let _rx = map.observe("c".to_string()).unwrap();
+
+        let json = serde_json::to_string(&map).unwrap();
+        let restored: ObserverMap<String, i32> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.snapshot(), map.snapshot());
+        assert_eq!(restored.get("c".to_string()), None);
+        assert_eq!(restored.total_observers(), 0);
+    }
+
+    #[test]
+    fn restore_notifies_observers() {
+        let mut map = ObserverMap::<String, i32>::new();
+        let rx = map.observe("a".to_string()).unwrap();
+
+        let mut json = serde_json::Deserializer::from_str(r#"{"a": 1}"#);
+        map.restore(&mut json).unwrap();
+
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(map.get("a".to_string()), Some(1));
+    }
+}
@@ -0,0 +1,130 @@
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_sink::Sink;
+
+use crate::{InsertError, ObservableMap, ThreadSafeObserverMap};
+
+/// A [`Sink`] that inserts every value it receives into a map under a single key.
+///
+/// Created by [`ThreadSafeObserverMap::sink_for`].
+pub struct KeySink<K, V> {
+    map: ThreadSafeObserverMap<K, V>,
+    key: K,
+}
+
+/// A [`Sink`] that inserts every `(key, value)` pair it receives into a map.
+///
+/// Created by [`ThreadSafeObserverMap::sink`].
+pub struct MapSink<K, V> {
+    map: ThreadSafeObserverMap<K, V>,
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V> {
+    pub fn sink_for(&self, key: K) -> KeySink<K, V> {
+        KeySink {
+            map: ThreadSafeObserverMap {
+                inner: self.inner.clone(),
+                leases: self.leases.clone(),
+            },
+            key,
+        }
+    }
+
+    pub fn sink(&self) -> MapSink<K, V> {
+        MapSink {
+            map: ThreadSafeObserverMap {
+                inner: self.inner.clone(),
+                leases: self.leases.clone(),
+            },
+        }
+    }
+}
+
+// Neither sink is self-referential, so they can be moved freely whilst pinned.
+impl<K, V> Unpin for KeySink<K, V> {}
+impl<K, V> Unpin for MapSink<K, V> {}
+
+impl<K, V> Sink<V> for KeySink<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone,
+{
+    type Error = InsertError<V>;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, value: V) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        this.map.insert(this.key.clone(), value)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<K, V> Sink<(K, V)> for MapSink<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    type Error = InsertError<V>;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, (key, value): (K, V)) -> Result<(), Self::Error> {
+        self.get_mut().map.insert(key, value)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{stream, StreamExt};
+
+    #[tokio::test]
+    async fn forward_stream_into_key() {
+        let mut map = ThreadSafeObserverMap::new();
+        let rx = map.observe_unbounded("key".to_string()).unwrap();
+
+        stream::iter((1u32..=3).map(Ok))
+            .forward(map.sink_for("key".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(map.get("key".to_string()), Some(3));
+    }
+
+    #[tokio::test]
+    async fn forward_stream_of_pairs_into_map() {
+        let map = ThreadSafeObserverMap::new();
+
+        stream::iter([("a".to_string(), 1u32), ("b".to_string(), 2)].map(Ok))
+            .forward(map.sink())
+            .await
+            .unwrap();
+
+        assert_eq!(map.get("a".to_string()), Some(1));
+        assert_eq!(map.get("b".to_string()), Some(2));
+    }
+}
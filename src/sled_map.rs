@@ -0,0 +1,283 @@
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+use crate::{
+    Attributed, Backpressure, DeliveryReport, InsertError, Metrics, ObservableMap, ObserveError,
+    ObserverMap, Receiver, WaitError, WriteInfo,
+};
+
+/// An [`ObserverMap`] whose values are written through to a [`sled`] database on every insert,
+/// and read back when the map is reopened. Observers behave exactly as they do for an
+/// in-memory map; they aren't persisted.
+pub struct PersistentObserverMap<K, V> {
+    map: ObserverMap<K, V>,
+    db: sled::Db,
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    bincode::deserialize(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+impl<K, V> PersistentObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Opens the database at `path`, creating it if it doesn't exist, and loads its values.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let db = sled::open(path)?;
+        let mut map = ObserverMap::new();
+        for entry in db.iter() {
+            let (key, value) = entry?;
+            // Can't fail: the map is open and has no observers.
+            let _ = map.insert(decode(&key)?, decode(&value)?);
+        }
+        Ok(Self { map, db })
+    }
+
+    /// Waits until every value written so far is durable on disk. sled otherwise flushes
+    /// periodically in the background.
+    pub fn flush(&self) -> io::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// See [`ObserverMap::close`]. Values already written stay in the database.
+    pub fn close(&mut self) {
+        self.map.close()
+    }
+
+    // Writes the value to the database before it is inserted into the map.
+    fn write_through(&self, key: &K, value: V) -> Result<V, InsertError<V>> {
+        if self.map.is_closed() {
+            return Err(InsertError::Closed(value));
+        }
+        match self.store(key, &value) {
+            Ok(()) => Ok(value),
+            Err(error) => Err(InsertError::Persist(value, error.kind())),
+        }
+    }
+
+    fn store(&self, key: &K, value: &V) -> io::Result<()> {
+        let encode = |error| io::Error::new(io::ErrorKind::InvalidInput, error);
+        let key = bincode::serialize(key).map_err(encode)?;
+        let value = bincode::serialize(value).map_err(encode)?;
+        self.db.insert(key, value)?;
+        Ok(())
+    }
+
+    /// See [`ObserverMap::insert_as`](crate::ObserverMap::insert_as).
+    pub fn insert_as(&mut self, key: K, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        let value = self.write_through(&key, value)?;
+        self.map.insert_as(key, value, writer)
+    }
+
+    /// See [`ObserverMap::write_info`](crate::ObserverMap::write_info).
+    pub fn write_info(&self, key: K) -> Option<WriteInfo> {
+        self.map.write_info(key)
+    }
+
+    /// See [`ObserverMap::observe_attributed`](crate::ObserverMap::observe_attributed).
+    pub fn observe_attributed(&mut self, key: K) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        self.map.observe_attributed(key)
+    }
+}
+
+impl<K, V> ObservableMap<K, V> for PersistentObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        let value = self.write_through(&key, value)?;
+        self.map.insert(key, value)
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        let value = self.write_through(&key, value)?;
+        self.map.insert_reporting(key, value)
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, group: &str) -> Result<(), InsertError<V>> {
+        let value = self.write_through(&key, value)?;
+        self.map.insert_for_group(key, value, group)
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        self.map.get(key)
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        self.map.get_or_wait(key)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.map.is_closed()
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.map.observer_count(key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.map.total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.map.metrics()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_with_backpressure(key, backpressure)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_with_capacity(key, capacity)
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_unbounded(key)
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_with_initial(key)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(&mut self, key: K, interval: Duration) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        self.map.observe_throttled(key, interval)
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.map.on_update(key, callback)
+    }
+
+    fn observe_group(&mut self, key: K, group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_group(key, group)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.map.on_update_with_priority(key, priority, callback)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.map.observe_with_priority(key, priority)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        self.map.watch(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        self.map.broadcast(key, capacity)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.map.observe_async(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.map.observe_async_with_capacity(key, capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_reopening() {
+        let path =
+            std::env::temp_dir().join(format!("observable-maps-{}.sled", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+
+        {
+            let mut map = PersistentObserverMap::open(&path).unwrap();
+            let rx = map.observe("a".to_string()).unwrap();
+            map.insert("a".to_string(), 1u32).unwrap();
+            map.insert("b".to_string(), 2).unwrap();
+            map.insert("a".to_string(), 3).unwrap();
+            assert_eq!(rx.recv(), Ok(1));
+            map.flush().unwrap();
+        }
+
+        let map = PersistentObserverMap::<String, u32>::open(&path).unwrap();
+        assert_eq!(map.get("a".to_string()), Some(3));
+        assert_eq!(map.get("b".to_string()), Some(2));
+        assert_eq!(map.total_observers(), 0);
+
+        drop(map);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn closed_map_does_not_write_through() {
+        let path = std::env::temp_dir().join(format!(
+            "observable-maps-{}-closed.sled",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+
+        let mut map = PersistentObserverMap::open(&path).unwrap();
+        map.close();
+        assert!(matches!(
+            map.insert("a".to_string(), 1u32),
+            Err(InsertError::Closed(1))
+        ));
+        assert!(map.db.is_empty());
+
+        drop(map);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
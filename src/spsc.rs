@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc};
+
+use crossbeam_epoch::{self as epoch, Atomic, Owned};
+
+use crate::channel::{self, Sender};
+use crate::sync::{self, AtomicUsize};
+use crate::{InsertError, ObserveError, Receiver};
+
+// A key's latest value. Replaced values are freed once every reader that could have loaded
+// them has unpinned its epoch.
+struct Slot<V> {
+    value: Atomic<V>,
+}
+
+impl<V> Drop for Slot<V> {
+    fn drop(&mut self) {
+        // SAFETY: keys are never removed, so a slot is only dropped with the last index that
+        // refers to it, once no reader can load either.
+        unsafe {
+            let value = self.value.load(Ordering::Relaxed, epoch::unprotected());
+            if !value.is_null() {
+                drop(value.into_owned());
+            }
+        }
+    }
+}
+
+type Index<K, V> = HashMap<K, Arc<Slot<V>>>;
+
+struct Shared<K, V> {
+    // Replaced with a copy whenever a key is added, and never mutated once published.
+    index: Atomic<Index<K, V>>,
+    // Observers registered by readers, picked up by the writer's next insert.
+    registrations: mpsc::Sender<(K, Sender<V>)>,
+    // Receivers dropped since the writer last unregistered their senders.
+    dropped: sync::Arc<AtomicUsize>,
+}
+
+impl<K, V> Drop for Shared<K, V> {
+    fn drop(&mut self) {
+        // SAFETY: the map and every reader have been dropped, so nothing can load the index.
+        unsafe {
+            drop(
+                self.index
+                    .load(Ordering::Relaxed, epoch::unprotected())
+                    .into_owned(),
+            )
+        }
+    }
+}
+
+/// A map with exactly one writer, for feed handlers that apply a single stream of updates
+/// for many readers. The map itself is the writer and can't be cloned or shared, so inserts
+/// take `&mut self` instead of a lock. Readers, returned by [`reader`](SpscObserverMap::reader),
+/// load values without locking: each value is published by swapping an atomic pointer, and
+/// replaced values are freed once no reader can still be reading them.
+///
+/// Readers' observers are handed to the writer through a queue and registered by its next
+/// insert, which notifies them as [`ObserverMap`](crate::ObserverMap) does, and unregisters
+/// those whose receivers have been dropped, whichever keys they observe. Adding a key copies
+/// the map's index of keys, so the writer is fastest once its keys have been added.
+pub struct SpscObserverMap<K, V> {
+    shared: Arc<Shared<K, V>>,
+    slots: Index<K, V>,
+    observers: HashMap<K, Vec<Sender<V>>>,
+    // `None` once the map is closed, disconnecting readers' registrations.
+    registrations: Option<mpsc::Receiver<(K, Sender<V>)>>,
+}
+
+/// Reads and observes an [`SpscObserverMap`] from any number of threads, returned by
+/// [`SpscObserverMap::reader`].
+pub struct SpscReader<K, V> {
+    shared: Arc<Shared<K, V>>,
+}
+
+impl<K, V> Clone for SpscReader<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<K, V> SpscObserverMap<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            shared: Arc::new(Shared {
+                index: Atomic::new(HashMap::new()),
+                registrations: tx,
+                dropped: sync::Arc::new(AtomicUsize::new(0)),
+            }),
+            slots: HashMap::new(),
+            observers: HashMap::new(),
+            registrations: Some(rx),
+        }
+    }
+
+    pub fn reader(&self) -> SpscReader<K, V> {
+        SpscReader {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Publishes `value` to readers, then sends it to the key's observers, including those
+    /// registered since the last insert.
+    pub fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        let Some(registrations) = &self.registrations else {
+            return Err(InsertError::Closed(value));
+        };
+        for (key, tx) in registrations.try_iter() {
+            self.observers.entry(key).or_default().push(tx);
+        }
+        self.unregister_dropped();
+
+        let slot = match self.slots.get(&key) {
+            Some(slot) => slot.clone(),
+            None => self.add_key(key.clone()),
+        };
+        let observers = self.observers.get_mut(&key).filter(|o| !o.is_empty());
+        let notify = observers.is_some().then(|| value.clone());
+
+        let guard = epoch::pin();
+        let replaced = slot.value.swap(Owned::new(value), Ordering::AcqRel, &guard);
+        if !replaced.is_null() {
+            // SAFETY: the value is unreachable now, and is freed once readers that may have
+            // loaded it unpin.
+            unsafe { guard.defer_destroy(replaced) };
+        }
+
+        if let (Some(observers), Some(value)) = (observers, notify) {
+            observers.retain(|tx| tx.send(value.clone()).is_ok());
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let guard = epoch::pin();
+        let value = self.slots.get(key)?.value.load(Ordering::Acquire, &guard);
+        // SAFETY: a value is only freed through `defer_destroy` once it has been swapped out,
+        // and then only after every guard pinned before the swap is dropped. `guard` was
+        // pinned before the load and outlives the clone, so the value is still allocated.
+        unsafe { value.as_ref() }.cloned()
+    }
+
+    /// Stops inserts and disconnects every observer's receiver. Readers can still read the
+    /// map's values.
+    pub fn close(&mut self) {
+        self.registrations = None;
+        self.observers.clear();
+    }
+
+    fn unregister_dropped(&mut self) {
+        if self.shared.dropped.swap(0, Ordering::Acquire) > 0 {
+            self.observers.retain(|_, observers| {
+                observers.retain(|tx| !tx.is_disconnected());
+                !observers.is_empty()
+            });
+        }
+    }
+
+    fn add_key(&mut self, key: K) -> Arc<Slot<V>> {
+        let slot = Arc::new(Slot {
+            value: Atomic::null(),
+        });
+        self.slots.insert(key, slot.clone());
+        let guard = epoch::pin();
+        let index = Owned::new(self.slots.clone());
+        let replaced = self.shared.index.swap(index, Ordering::AcqRel, &guard);
+        // SAFETY: as for replaced values.
+        unsafe { guard.defer_destroy(replaced) };
+        slot
+    }
+}
+
+impl<K, V> Default for SpscObserverMap<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> SpscReader<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    pub fn get(&self, key: &K) -> Option<V> {
+        let guard = epoch::pin();
+        let index = self.shared.index.load(Ordering::Acquire, &guard);
+        // SAFETY: the index is never null, and neither it nor its values can be freed whilst
+        // the epoch is pinned.
+        let slot = unsafe { index.deref() }.get(key)?;
+        let value = slot.value.load(Ordering::Acquire, &guard);
+        // SAFETY: as in `SpscObserverMap::get`, the value was loaded under `guard`, which is
+        // held until it has been cloned, so its deferred destruction can't have run yet.
+        unsafe { value.as_ref() }.cloned()
+    }
+
+    /// Observes `key`'s values from the writer's next insert. Fails if the map has been
+    /// closed or dropped.
+    pub fn observe(&self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.shared
+            .registrations
+            .send((key, tx))
+            .map_err(|_| ObserveError::Closed)?;
+        Ok(rx.unregister_on_drop(&self.shared.dropped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn readers_see_the_writers_inserts() {
+        let mut map = SpscObserverMap::new();
+        let reader = map.reader();
+        let rx = reader.observe("px").unwrap();
+        assert_eq!(reader.get(&"px"), None);
+
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let reader = reader.clone();
+                thread::spawn(move || while reader.get(&"px") != Some(999) {})
+            })
+            .collect();
+        for i in 0..1000 {
+            map.insert("px", i).unwrap();
+        }
+        map.insert("qty", 5).unwrap();
+        for r in readers {
+            r.join().unwrap();
+        }
+
+        assert_eq!(map.get(&"px"), Some(999));
+        assert_eq!(reader.get(&"qty"), Some(5));
+        assert_eq!(rx.try_iter().count(), 1000);
+    }
+
+    #[test]
+    fn dropped_observers_of_cold_keys_are_unregistered() {
+        let mut map = SpscObserverMap::new();
+        let reader = map.reader();
+        let cold = reader.observe("cold").unwrap();
+        let hot = reader.observe("hot").unwrap();
+        map.insert("hot", 1).unwrap();
+        assert_eq!(map.observers.len(), 2);
+
+        drop(cold);
+        map.insert("hot", 2).unwrap();
+
+        assert_eq!(map.observers.len(), 1);
+        assert_eq!(hot.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn closing_disconnects_observers() {
+        let mut map = SpscObserverMap::new();
+        let reader = map.reader();
+        let rx = reader.observe("px").unwrap();
+        map.insert("px", 1).unwrap();
+        map.close();
+
+        assert!(matches!(map.insert("px", 2), Err(InsertError::Closed(2))));
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1]);
+        assert!(matches!(reader.observe("px"), Err(ObserveError::Closed)));
+        assert_eq!(reader.get(&"px"), Some(1));
+    }
+}
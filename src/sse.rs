@@ -0,0 +1,148 @@
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use serde::Serialize;
+
+use crate::{AsyncReceiver, Change, MapEvent, ObservableMap, ObserveError, ThreadSafeObserverMap};
+
+type Frame<T> = Box<dyn FnMut(T) -> Option<String> + Send>;
+
+// The events held for a client that hasn't received them yet. A client that falls further
+// behind is disconnected, ending its stream.
+const CLIENT_BACKLOG: usize = 1 << 16;
+
+/// A stream of [Server-Sent Events](https://html.spec.whatwg.org/multipage/server-sent-events.html)
+/// encoding a subscription's values as JSON. Each item is one complete event, ready to be
+/// written to a `text/event-stream` response body; most HTTP frameworks can build a streaming
+/// body directly from it. Values that can't be encoded as JSON are skipped.
+pub struct SseStream<T> {
+    rx: AsyncReceiver<T>,
+    frame: Frame<T>,
+}
+
+impl<T> Stream for SseStream<T> {
+    type Item = Result<String, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.rx).poll_next(cx) {
+                Poll::Ready(Some(value)) => {
+                    if let Some(event) = (self.frame)(value) {
+                        return Poll::Ready(Some(Ok(event)));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Streams every value subsequently inserted for `key`, each as the data of one event. The
+/// stream ends if the client falls too far behind.
+pub fn observe_key<K, V, M>(map: &mut M, key: K) -> Result<SseStream<V>, ObserveError>
+where
+    M: ObservableMap<K, V>,
+    V: Serialize,
+{
+    Ok(SseStream {
+        rx: map.observe_async_with_capacity(key, CLIENT_BACKLOG)?,
+        frame: Box::new(|value| Some(format!("data: {}\n\n", serde_json::to_string(&value).ok()?))),
+    })
+}
+
+#[derive(Serialize)]
+struct KeyedValue<K, V> {
+    key: K,
+    value: V,
+}
+
+#[derive(Serialize)]
+struct Key<K> {
+    key: K,
+}
+
+/// Streams every change to any key starting with `prefix`, with the change's sequence number
+/// as each event's id. Inserts are sent as events whose data is a JSON object with the `key`
+/// and `value`. Values that are removed, evicted or expire are sent as `delete` events whose
+/// data is a JSON object with the `key`. The stream ends if the client falls too far behind.
+pub fn observe_prefix<K, V>(
+    map: &ThreadSafeObserverMap<K, V>,
+    prefix: &str,
+) -> SseStream<MapEvent<K, V>>
+where
+    K: Hash + Eq + Clone + AsRef<str> + Serialize,
+    V: Clone + Serialize,
+{
+    let prefix = prefix.to_string();
+    SseStream {
+        rx: map.events_async_with_capacity(CLIENT_BACKLOG),
+        frame: Box::new(move |event| {
+            if !event.key.as_ref().starts_with(&prefix) {
+                return None;
+            }
+            let (kind, data) = match event.change {
+                Change::Inserted(value) => (
+                    "",
+                    serde_json::to_string(&KeyedValue {
+                        key: event.key,
+                        value,
+                    }),
+                ),
+                _ => (
+                    "event: delete\n",
+                    serde_json::to_string(&Key { key: event.key }),
+                ),
+            };
+            Some(format!("id: {}\n{kind}data: {}\n\n", event.seq, data.ok()?))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn streams_key_and_prefix_events() {
+        let mut map = ThreadSafeObserverMap::new();
+        let mut key = observe_key(&mut map, "prices.btc".to_string()).unwrap();
+        let mut prefix = observe_prefix(&map, "prices.");
+
+        map.insert("volumes.btc".to_string(), 7).unwrap();
+        map.insert("prices.btc".to_string(), 42).unwrap();
+
+        assert_eq!(key.next().await, Some(Ok("data: 42\n\n".to_string())));
+        assert_eq!(
+            prefix.next().await,
+            Some(Ok(
+                "id: 1\ndata: {\"key\":\"prices.btc\",\"value\":42}\n\n".to_string()
+            ))
+        );
+
+        // The keys are cleared in arbitrary order, so the removal's id isn't known.
+        map.clear().unwrap();
+        let removal = prefix.next().await.unwrap().unwrap();
+        assert!(
+            removal.ends_with("\nevent: delete\ndata: {\"key\":\"prices.btc\"}\n\n"),
+            "{removal}"
+        );
+    }
+
+    #[tokio::test]
+    async fn clients_that_fall_behind_are_disconnected() {
+        let mut map = ThreadSafeObserverMap::new();
+        let key = observe_key(&mut map, "a".to_string()).unwrap();
+        let prefix = observe_prefix(&map, "");
+        for value in 0..=CLIENT_BACKLOG {
+            map.insert("a".to_string(), value).unwrap();
+        }
+
+        assert_eq!(key.count().await, CLIENT_BACKLOG);
+        assert_eq!(prefix.count().await, CLIENT_BACKLOG);
+    }
+}
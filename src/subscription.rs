@@ -0,0 +1,249 @@
+use alloc::boxed::Box;
+use core::time::Duration;
+
+use crate::channel::{RecvError, RecvTimeoutError, TryRecvError};
+use crate::clock::Instant;
+
+use crate::Receiver;
+
+// Where a subscription's values come from, after any adapters. `deadline` is `None` to block
+// until a value arrives, and a deadline that has passed doesn't block at all.
+trait Source<T>: Send {
+    fn next(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError>;
+}
+
+impl<T: Send> Source<T> for Receiver<T> {
+    fn next(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        match deadline {
+            None => self
+                .recv()
+                .map_err(|RecvError| RecvTimeoutError::Disconnected),
+            Some(deadline) => match self.try_recv() {
+                Ok(value) => Ok(value),
+                Err(TryRecvError::Disconnected) => Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {
+                    self.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+            },
+        }
+    }
+}
+
+struct Take<T> {
+    source: Box<dyn Source<T>>,
+    remaining: usize,
+}
+
+impl<T> Source<T> for Take<T> {
+    fn next(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        if self.remaining == 0 {
+            return Err(RecvTimeoutError::Disconnected);
+        }
+        let value = self.source.next(deadline)?;
+        self.remaining -= 1;
+        Ok(value)
+    }
+}
+
+struct Skip<T> {
+    source: Box<dyn Source<T>>,
+    remaining: usize,
+}
+
+impl<T> Source<T> for Skip<T> {
+    fn next(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        while self.remaining > 0 {
+            self.source.next(deadline)?;
+            self.remaining -= 1;
+        }
+        self.source.next(deadline)
+    }
+}
+
+struct Filter<T, F> {
+    source: Box<dyn Source<T>>,
+    predicate: F,
+}
+
+impl<T, F> Source<T> for Filter<T, F>
+where
+    F: FnMut(&T) -> bool + Send,
+{
+    fn next(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        loop {
+            let value = self.source.next(deadline)?;
+            if (self.predicate)(&value) {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+struct Map<T, F> {
+    source: Box<dyn Source<T>>,
+    f: F,
+}
+
+impl<T, U, F> Source<U> for Map<T, F>
+where
+    F: FnMut(T) -> U + Send,
+{
+    fn next(&mut self, deadline: Option<Instant>) -> Result<U, RecvTimeoutError> {
+        self.source.next(deadline).map(&mut self.f)
+    }
+}
+
+/// A [`Receiver`] with adapters applied to the values it receives, created with
+/// [`Receiver::into_subscription`]. Adapters are applied as values are received, on the
+/// receiving thread, and iterating blocks for each value until the subscription ends.
+///
+/// Async receivers are streams, so use `futures::StreamExt` to adapt them instead.
+pub struct Subscription<T> {
+    source: Box<dyn Source<T>>,
+}
+
+impl<T: Send + 'static> Receiver<T> {
+    pub fn into_subscription(self) -> Subscription<T> {
+        Subscription {
+            source: Box::new(self),
+        }
+    }
+}
+
+impl<T: Send + 'static> From<Receiver<T>> for Subscription<T> {
+    fn from(rx: Receiver<T>) -> Self {
+        rx.into_subscription()
+    }
+}
+
+impl<T: 'static> Subscription<T> {
+    /// Ends the subscription after `n` values, as if the map had been dropped.
+    pub fn take(self, n: usize) -> Self {
+        Subscription {
+            source: Box::new(Take {
+                source: self.source,
+                remaining: n,
+            }),
+        }
+    }
+
+    /// Discards the first `n` values.
+    pub fn skip(self, n: usize) -> Self {
+        Subscription {
+            source: Box::new(Skip {
+                source: self.source,
+                remaining: n,
+            }),
+        }
+    }
+
+    /// Discards the values for which `predicate` returns `false`.
+    pub fn filter<F>(self, predicate: F) -> Self
+    where
+        F: FnMut(&T) -> bool + Send + 'static,
+    {
+        Subscription {
+            source: Box::new(Filter {
+                source: self.source,
+                predicate,
+            }),
+        }
+    }
+
+    pub fn map<U, F>(self, f: F) -> Subscription<U>
+    where
+        F: FnMut(T) -> U + Send + 'static,
+    {
+        Subscription {
+            source: Box::new(Map {
+                source: self.source,
+                f,
+            }),
+        }
+    }
+
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        self.source.next(None).map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.source
+            .next(Some(Instant::now()))
+            .map_err(|error| match error {
+                RecvTimeoutError::Timeout => TryRecvError::Empty,
+                RecvTimeoutError::Disconnected => TryRecvError::Disconnected,
+            })
+    }
+
+    /// Waits up to `timeout` in total, however many values adapters discard meanwhile.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout too long to represent as a deadline never elapses.
+        self.source.next(Instant::now().checked_add(timeout))
+    }
+}
+
+impl<T: 'static> Iterator for Subscription<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObservableMap, ObserverMap};
+
+    #[test]
+    fn adapters_apply_in_order() {
+        let mut map = ObserverMap::new();
+        let rx = map.observe_unbounded("a").unwrap();
+        for i in 0..10 {
+            map.insert("a", i).unwrap();
+        }
+
+        let values: Vec<_> = rx
+            .into_subscription()
+            .skip(1)
+            .filter(|value| value % 2 == 0)
+            .map(|value| value * 10)
+            .take(3)
+            .collect();
+        assert_eq!(values, vec![20, 40, 60]);
+    }
+
+    #[test]
+    fn try_recv_does_not_block_on_discarded_values() {
+        let mut map = ObserverMap::new();
+        let mut sub = map
+            .observe_unbounded("a")
+            .unwrap()
+            .into_subscription()
+            .filter(|value| *value > 1);
+        map.insert("a", 1).unwrap();
+        assert_eq!(sub.try_recv(), Err(TryRecvError::Empty));
+        map.insert("a", 2).unwrap();
+        assert_eq!(sub.try_recv(), Ok(2));
+        drop(map);
+        assert_eq!(sub.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn recv_timeout_without_a_representable_deadline_waits_for_a_value() {
+        let mut map = ObserverMap::new();
+        let mut sub = map
+            .observe_unbounded("a")
+            .unwrap()
+            .into_subscription()
+            .filter(|value| *value > 1);
+        map.insert("a", 1).unwrap();
+        map.insert("a", 2).unwrap();
+        assert_eq!(sub.recv_timeout(Duration::MAX), Ok(2));
+        drop(map);
+        assert_eq!(
+            sub.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,38 @@
+// The primitives shared between threads by maps and their channels, taken from here alone so
+// that a platform without the standard library gets spin locks instead. Building with
+// `RUSTFLAGS="--cfg loom"` swaps them for loom's, so that `loom::model` can check every
+// interleaving of their use.
+#[cfg(not(feature = "std"))]
+mod spin;
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{atomic::AtomicUsize, Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(all(not(loom), feature = "std"))]
+pub(crate) use std::sync::{atomic::AtomicUsize, Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(feature = "std"))]
+pub(crate) use {
+    self::spin::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    alloc::sync::Arc,
+    core::sync::atomic::AtomicUsize,
+};
+
+// Used by the default channel backend.
+#[cfg(not(feature = "std"))]
+pub(crate) use self::spin::{Condvar, Mutex, MutexGuard};
+#[cfg(all(loom, not(any(feature = "crossbeam", feature = "flume"))))]
+pub(crate) use loom::sync::{Condvar, Mutex, MutexGuard};
+#[cfg(all(
+    not(loom),
+    feature = "std",
+    not(any(feature = "crossbeam", feature = "flume"))
+))]
+pub(crate) use std::sync::{Condvar, Mutex, MutexGuard};
+
+// Locks that loom doesn't model, guarding hooks, callbacks and leases rather than the map.
+pub(crate) mod lock {
+    #[cfg(feature = "std")]
+    pub(crate) use std::sync::{Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) use super::spin::{Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+}
@@ -0,0 +1,142 @@
+// Spin locks with the parts of the standard library's API that the crate uses, for platforms
+// without it. They can't be poisoned, so locking never fails, and as there is no clock to
+// measure a timeout, a timed wait returns at once.
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+pub(crate) type LockResult<G> = Result<G, PoisonError<G>>;
+
+// Never returned, but lets callers recover from poisoning as they do with the standard
+// library's locks.
+pub(crate) struct PoisonError<T>(T);
+
+impl<T> fmt::Debug for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<T> PoisonError<T> {
+    pub(crate) fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+pub(crate) struct Mutex<T> {
+    inner: spin::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self {
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        Ok(MutexGuard {
+            mutex: self,
+            guard: self.inner.lock(),
+        })
+    }
+}
+
+pub(crate) struct MutexGuard<'a, T> {
+    // So that a `Condvar` can take the lock again once it has waited.
+    mutex: &'a Mutex<T>,
+    guard: spin::MutexGuard<'a, T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+// Waiters spin until the number of notifications changes. A notification is only sent after
+// the notifier has changed the guarded state, so one sent after a waiter last saw the state
+// is never missed.
+pub(crate) struct Condvar {
+    notifications: AtomicUsize,
+}
+
+impl Condvar {
+    pub(crate) const fn new() -> Self {
+        Self {
+            notifications: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        let seen = self.notifications.load(Ordering::Acquire);
+        let mutex = guard.mutex;
+        drop(guard);
+        while self.notifications.load(Ordering::Acquire) == seen {
+            core::hint::spin_loop();
+        }
+        mutex.lock()
+    }
+
+    pub(crate) fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> LockResult<MutexGuard<'a, T>> {
+        while condition(&mut guard) {
+            guard = self.wait(guard).unwrap_or_else(PoisonError::into_inner);
+        }
+        Ok(guard)
+    }
+
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        _timeout: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        Ok((guard, WaitTimeoutResult))
+    }
+
+    pub(crate) fn notify_one(&self) {
+        self.notifications.fetch_add(1, Ordering::Release);
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.notifications.fetch_add(1, Ordering::Release);
+    }
+}
+
+pub(crate) struct WaitTimeoutResult;
+
+pub(crate) struct RwLock<T> {
+    inner: spin::RwLock<T>,
+}
+
+pub(crate) type RwLockReadGuard<'a, T> = spin::RwLockReadGuard<'a, T>;
+
+pub(crate) type RwLockWriteGuard<'a, T> = spin::RwLockWriteGuard<'a, T>;
+
+impl<T> RwLock<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Self {
+            inner: spin::RwLock::new(value),
+        }
+    }
+
+    pub(crate) fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        Ok(self.inner.read())
+    }
+
+    pub(crate) fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        Ok(self.inner.write())
+    }
+}
@@ -0,0 +1,522 @@
+//! Test doubles and assertions for code that depends on [`ObservableMap`].
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::collections::HashMap;
+use crate::registry::{Items, Registry};
+use crate::sync::lock::{Mutex, PoisonError};
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+use crate::{
+    channel, Backpressure, DeliveryReport, InsertError, Item, Metrics, ObservableMap, ObserveError,
+    Observer, Receiver, Registration, WaitError,
+};
+
+/// A call made to a [`MockObservableMap`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Call<K, V> {
+    /// Any of the insert methods.
+    Insert(K, V),
+    Get(K),
+    /// Any of the observe methods, including callbacks.
+    Observe(K),
+    /// Any of the wait methods.
+    Wait(K),
+}
+
+/// An [`ObservableMap`] for unit tests of code that depends on the trait, with scripted
+/// values, recorded calls and injectable errors. It never blocks or sleeps: waits return
+/// scripted updates immediately, or fail if none are scripted.
+pub struct MockObservableMap<K, V> {
+    // Each key's value and observers. Observers of a mock are always unbounded.
+    registry: Registry<HashMap<K, Item<V>>>,
+    // Updates for each key that haven't been delivered yet, each with the delay before it
+    // would arrive.
+    updates: HashMap<K, VecDeque<(V, Duration)>>,
+    calls: Mutex<Vec<Call<K, V>>>,
+    insert_errors: VecDeque<fn(V) -> InsertError<V>>,
+    observe_errors: VecDeque<ObserveError>,
+    wait_errors: VecDeque<WaitError>,
+}
+
+impl<K, V> MockObservableMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            registry: Registry::new(HashMap::default()),
+            updates: HashMap::default(),
+            calls: Mutex::new(Vec::new()),
+            insert_errors: VecDeque::new(),
+            observe_errors: VecDeque::new(),
+            wait_errors: VecDeque::new(),
+        }
+    }
+
+    /// Fails the next insert with the error `error` builds from the inserted value.
+    pub fn fail_next_insert(&mut self, error: fn(V) -> InsertError<V>) {
+        self.insert_errors.push_back(error);
+    }
+
+    /// Fails the next observe, or wait that needs to observe, with `error`.
+    pub fn fail_next_observe(&mut self, error: ObserveError) {
+        self.observe_errors.push_back(error);
+    }
+
+    /// Fails the next wait with `error`.
+    pub fn fail_next_wait(&mut self, error: WaitError) {
+        self.wait_errors.push_back(error);
+    }
+
+    /// Every call made to the map so far, oldest first.
+    pub fn calls(&self) -> Vec<Call<K, V>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn record(&self, call: Call<K, V>) {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(call);
+    }
+}
+
+impl<K, V> MockObservableMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    /// Makes the map report itself closed, failing subsequent inserts and observes.
+    pub fn close(&mut self) {
+        self.registry.close()
+    }
+
+    /// Sets the current value of `key`, as if it had been inserted before the test began.
+    pub fn set(&mut self, key: K, value: V) {
+        self.registry.items.get_or_insert(key).value = Some(value);
+    }
+
+    fn value(&self, key: &K) -> Option<V> {
+        self.registry.items.get(key)?.value.clone()
+    }
+
+    /// Scripts `value` as the next update of `key`. It is delivered to observers registered
+    /// after this call, and returned by the next wait for the key.
+    pub fn push_update(&mut self, key: K, value: V) {
+        self.push_update_after(key, value, Duration::ZERO);
+    }
+
+    /// Scripts `value` as the next update of `key`, arriving `delay` after it is waited for.
+    /// [`wait_timeout`](ObservableMap::wait_timeout) with a shorter timeout fails with
+    /// [`WaitError::Timeout`] and leaves the update scripted.
+    pub fn push_update_after(&mut self, key: K, value: V, delay: Duration) {
+        self.updates
+            .entry(key)
+            .or_default()
+            .push_back((value, delay));
+    }
+
+    #[cfg(os)]
+    fn next_update(&mut self, key: &K, timeout: Option<Duration>) -> Result<V, WaitError> {
+        if let Some(error) = self.wait_errors.pop_front() {
+            return Err(error);
+        }
+        if let Some(error) = self.observe_errors.pop_front() {
+            return Err(error.into());
+        }
+        let updates = self.updates.entry(key.clone()).or_default();
+        match updates.front() {
+            Some((_, delay)) if timeout.is_some_and(|timeout| *delay > timeout) => {
+                Err(WaitError::Timeout)
+            }
+            Some(_) => {
+                let (value, _) = updates.pop_front().unwrap();
+                self.set(key.clone(), value.clone());
+                Ok(value)
+            }
+            None if timeout.is_some() => Err(WaitError::Timeout),
+            None => Err(WaitError::Disconnected),
+        }
+    }
+
+    fn check_observe(&mut self, key: &K) -> Result<(), ObserveError> {
+        self.record(Call::Observe(key.clone()));
+        if self.registry.is_closed() {
+            return Err(ObserveError::Closed);
+        }
+        match self.observe_errors.pop_front() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    // Delivers the key's scripted updates to a new observer, before it is registered.
+    fn deliver_updates(&mut self, key: &K, observer: &Observer<V>) -> Result<(), ObserveError> {
+        self.check_observe(key)?;
+        for (value, _) in self.updates.remove(key).into_iter().flatten() {
+            observer.notify(None, &value, None, None);
+            self.set(key.clone(), value);
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self, key: K, observer: Observer<V>) -> Result<(), ObserveError> {
+        self.deliver_updates(&key, &observer)?;
+        self.registry.register(key, Registration::new(observer))
+    }
+
+    fn subscribe_channel(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        self.subscribe_with(key, tx, rx)
+    }
+
+    fn subscribe_with(
+        &mut self,
+        key: K,
+        tx: channel::Sender<V>,
+        rx: Receiver<V>,
+    ) -> Result<Receiver<V>, ObserveError> {
+        let observer = Observer::stream(tx, Backpressure::Block);
+        self.deliver_updates(&key, &observer)?;
+        self.registry.add_channel(key, observer, rx)
+    }
+}
+
+impl<K, V> Default for MockObservableMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> ObservableMap<K, V> for MockObservableMap<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone + Send + 'static,
+{
+    fn insert(&mut self, key: K, value: V) -> Result<(), InsertError<V>> {
+        self.insert_reporting(key, value).map(|_| ())
+    }
+
+    fn insert_reporting(&mut self, key: K, value: V) -> Result<DeliveryReport, InsertError<V>> {
+        self.record(Call::Insert(key.clone(), value.clone()));
+        if self.registry.is_closed() {
+            return Err(InsertError::Closed(value));
+        }
+        if let Some(error) = self.insert_errors.pop_front() {
+            return Err(error(value));
+        }
+        Ok(self.registry.update(key, value, None, None))
+    }
+
+    fn insert_for_group(&mut self, key: K, value: V, _group: &str) -> Result<(), InsertError<V>> {
+        self.insert(key, value)
+    }
+
+    fn get(&self, key: K) -> Option<V> {
+        self.record(Call::Get(key.clone()));
+        self.value(&key)
+    }
+
+    fn observe(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.registry.is_closed()
+    }
+
+    fn observer_count(&self, key: K) -> usize {
+        self.registry.observer_count(&key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.registry.total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        None
+    }
+
+    #[cfg(os)]
+    fn wait(&mut self, key: K) -> Result<V, WaitError> {
+        self.record(Call::Wait(key.clone()));
+        self.next_update(&key, None)
+    }
+
+    #[cfg(os)]
+    fn wait_timeout(&mut self, key: K, timeout: Duration) -> Result<V, WaitError> {
+        self.record(Call::Wait(key.clone()));
+        self.next_update(&key, Some(timeout))
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: K) -> Result<V, WaitError> {
+        self.record(Call::Wait(key.clone()));
+        match self.value(&key) {
+            Some(value) => Ok(value),
+            None => self.next_update(&key, None),
+        }
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: K,
+        _backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: K,
+        _capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn observe_unbounded(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn observe_with_initial(&mut self, key: K) -> Result<Receiver<V>, ObserveError> {
+        let (tx, rx) = channel::unbounded();
+        if let Some(value) = self.value(&key) {
+            // Can't fail: the receiver is still held.
+            let _ = tx.send(value);
+        }
+        self.subscribe_with(key, tx, rx)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(
+        &mut self,
+        key: K,
+        _interval: Duration,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: K,
+        _priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn observe_group(&mut self, key: K, _group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.subscribe_channel(key)
+    }
+
+    fn on_update<F>(&mut self, key: K, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.on_update_with_priority(key, 0, callback)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: K,
+        _priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.subscribe(key, Observer::Callback(Mutex::new(Box::new(callback))))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(&mut self, key: K) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        let (tx, rx) = tokio::sync::watch::channel(self.value(&key));
+        self.subscribe(key, Observer::Watch(tx))?;
+        Ok(rx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        let (tx, rx) = tokio::sync::broadcast::channel(capacity);
+        self.subscribe(key, Observer::Broadcast(tx))?;
+        Ok(rx)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: K) -> Result<AsyncReceiver<V>, ObserveError> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        self.subscribe(key, Observer::Async(tx))?;
+        Ok(AsyncReceiver::new(rx))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: K,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        self.subscribe(key, Observer::async_bounded(tx))?;
+        Ok(AsyncReceiver::bounded(rx))
+    }
+}
+
+// The longest pause between polls of a map.
+const MAX_BACKOFF: Duration = Duration::from_millis(50);
+
+// Polls `condition` with exponential backoff until it holds, returning whether it did
+// before `timeout` elapsed.
+fn eventually(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut backoff = Duration::from_millis(1);
+    loop {
+        if condition() {
+            return true;
+        }
+        let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+            return false;
+        };
+        thread::sleep(backoff.min(remaining));
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Asserts that `key` holds `expected` within `timeout`, polling the map until it does.
+#[track_caller]
+pub fn assert_eventually_eq<K, V, M>(map: &M, key: K, expected: V, timeout: Duration)
+where
+    K: Clone,
+    V: PartialEq + Debug,
+    M: ObservableMap<K, V>,
+{
+    let mut last = None;
+    let held = eventually(timeout, || {
+        last = map.get(key.clone());
+        last.as_ref() == Some(&expected)
+    });
+    assert!(
+        held,
+        "expected {:?} within {:?}, last saw {:?}",
+        expected, timeout, last
+    );
+}
+
+/// Asserts that `key` has at least `count` observers within `timeout`, for example to wait
+/// until threads blocked in [`wait`](ObservableMap::wait) have registered.
+#[track_caller]
+pub fn assert_eventually_observed<K, V, M>(map: &M, key: K, count: usize, timeout: Duration)
+where
+    K: Clone,
+    M: ObservableMap<K, V>,
+{
+    let mut last = 0;
+    let held = eventually(timeout, || {
+        last = map.observer_count(key.clone());
+        last >= count
+    });
+    assert!(
+        held,
+        "expected {} observers within {:?}, last saw {}",
+        count, timeout, last
+    );
+}
+
+/// Asserts that no value is inserted for `key` for the next `duration`.
+#[cfg(os)]
+#[track_caller]
+pub fn assert_never_updated<K, V, M>(map: &mut M, key: K, duration: Duration)
+where
+    V: Debug,
+    M: ObservableMap<K, V>,
+{
+    let rx = map.observe_unbounded(key).expect("failed to observe key");
+    if let Ok(value) = rx.recv_timeout(duration) {
+        panic!("expected no update within {:?}, got {:?}", duration, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A component under test, generic over the map it reads from.
+    #[cfg(os)]
+    fn double_next<M: ObservableMap<&'static str, u32>>(map: &mut M) -> Result<u32, WaitError> {
+        Ok(map.wait_timeout("key", Duration::from_secs(1))? * 2)
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn scripts_updates_and_records_calls() {
+        let mut map = MockObservableMap::new();
+        map.push_update("key", 21);
+        map.push_update_after("key", 5, Duration::from_secs(2));
+
+        assert_eq!(double_next(&mut map), Ok(42));
+        assert_eq!(double_next(&mut map), Err(WaitError::Timeout));
+
+        let rx = map.observe("key").unwrap();
+        map.insert("key", 7).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![5, 7]);
+        assert_eq!(
+            map.calls(),
+            vec![
+                Call::Wait("key"),
+                Call::Wait("key"),
+                Call::Observe("key"),
+                Call::Insert("key", 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn injects_errors() {
+        let mut map = MockObservableMap::new();
+        map.fail_next_insert(InsertError::Full);
+        map.fail_next_observe(ObserveError::TooManyObservers);
+
+        assert_eq!(map.insert("key", 1), Err(InsertError::Full(1)));
+        assert_eq!(map.get("key"), None);
+        assert_eq!(
+            map.observe("key").err(),
+            Some(ObserveError::TooManyObservers)
+        );
+        map.insert("key", 2).unwrap();
+        assert_eq!(map.get("key"), Some(2));
+    }
+
+    #[cfg(os)]
+    #[test]
+    fn eventual_assertions_poll_until_they_hold() {
+        let mut map = crate::ThreadSafeObserverMap::new();
+        let mut writer = map.clone();
+        let inserter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.insert("key", 1).unwrap();
+        });
+
+        assert_eventually_eq(&map, "key", 1, Duration::from_secs(5));
+        assert_never_updated(&mut map, "other", Duration::from_millis(20));
+        inserter.join().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "expected 2 within")]
+    fn assert_eventually_eq_panics_on_timeout() {
+        let map = crate::ThreadSafeObserverMap::new();
+        assert_eventually_eq(&map, "key", 2, Duration::from_millis(10));
+    }
+}
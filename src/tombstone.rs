@@ -0,0 +1,187 @@
+use crate::clock::SystemTime;
+use crate::collections::HashMap;
+use core::hash::Hash;
+
+use crate::{Change, ObserverMap, RemoveError, ThreadSafeObserverMap, HAS_CLOCK};
+
+/// A record that a key's value was removed, kept by maps that
+/// [keep tombstones](ObserverMap::keep_tombstones).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tombstone {
+    /// When the value was removed, or `None` on `wasm32`, which has no clock, or without the
+    /// `std` feature.
+    pub removed_at: Option<SystemTime>,
+}
+
+/// A key's value, or why it has none, returned by [`ObserverMap::lookup`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lookup<V> {
+    Present(V),
+    /// The value was removed with [`remove`](ObserverMap::remove) and nothing has been
+    /// inserted since.
+    Removed(Tombstone),
+    /// The key has no value and no tombstone, because it never had a value, or its tombstone
+    /// wasn't kept or has been purged.
+    Absent,
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// Removes the key's value, returning it. The removal is recorded in the journal and sent
+    /// to event subscribers as a [`Change::Removed`], and starts a new
+    /// [`generation`](ObserverMap::generation). Observers of the key stay registered and
+    /// aren't notified. Fails if the map is closed or the removal can't be written to the
+    /// map's write-ahead log, in which case nothing is removed.
+    pub fn remove(&mut self, key: K) -> Result<Option<V>, RemoveError> {
+        if self.closed {
+            return Err(RemoveError::Closed);
+        }
+        let live = match self.hashmap.get(&key) {
+            Some(item) if item.value.is_some() => self.read(item).is_some(),
+            _ => return Ok(None),
+        };
+        #[cfg(feature = "wal")]
+        self.log_removal(crate::wal::Record::Remove(&key))?;
+        let Some(item) = self.hashmap.get_mut(&key) else {
+            return Ok(None);
+        };
+        // An expired value is removed all the same, but isn't returned.
+        let Some(value) = item.value.take() else {
+            return Ok(None);
+        };
+        if item.is_empty() {
+            self.hashmap.remove(&key);
+        }
+        if let Some(quota) = &mut self.quota {
+            quota.removed(&key);
+        }
+        self.sequence_change(&key, || Change::Removed);
+        self.next_generation();
+        if let Some(tombstones) = &mut self.tombstones {
+            let removed_at = HAS_CLOCK.then(|| self.clock.system_now());
+            tombstones.insert(key, Tombstone { removed_at });
+        }
+        Ok(live.then_some(value))
+    }
+
+    /// Keeps a [`Tombstone`] for each key whose value is [removed](ObserverMap::remove), until
+    /// a value is inserted for the key again, so that [`lookup`](ObserverMap::lookup) can tell
+    /// a removed key from one that never had a value, including for observers that arrive
+    /// after the removal. Tombstones are kept until they're
+    /// [purged](ObserverMap::purge_tombstones) or the map is cleared.
+    pub fn keep_tombstones(&mut self) {
+        self.tombstones.get_or_insert_with(HashMap::default);
+    }
+
+    /// Discards the tombstones of values removed before `removed_before`, and any without a
+    /// removal time, returning how many were discarded.
+    pub fn purge_tombstones(&mut self, removed_before: SystemTime) -> usize {
+        let Some(tombstones) = &mut self.tombstones else {
+            return 0;
+        };
+        let before = tombstones.len();
+        tombstones.retain(|_, tombstone| {
+            tombstone
+                .removed_at
+                .is_some_and(|removed_at| removed_at >= removed_before)
+        });
+        before - tombstones.len()
+    }
+
+    /// The key's value, or its tombstone if the value was removed.
+    pub fn lookup(&self, key: K) -> Lookup<V> {
+        if let Some(value) = self.hashmap.get(&key).and_then(|item| self.read(item)) {
+            return Lookup::Present(value.clone());
+        }
+        match self
+            .tombstones
+            .as_ref()
+            .and_then(|tombstones| tombstones.get(&key))
+        {
+            Some(tombstone) => Lookup::Removed(*tombstone),
+            None => Lookup::Absent,
+        }
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq,
+    V: Clone,
+{
+    /// See [`ObserverMap::remove`].
+    pub fn remove(&self, key: K) -> Result<Option<V>, RemoveError> {
+        self.write_unleased(&key).remove(key)
+    }
+
+    /// See [`ObserverMap::keep_tombstones`].
+    pub fn keep_tombstones(&self) {
+        self.write().keep_tombstones()
+    }
+
+    /// See [`ObserverMap::purge_tombstones`].
+    pub fn purge_tombstones(&self, removed_before: SystemTime) -> usize {
+        self.write().purge_tombstones(removed_before)
+    }
+
+    /// See [`ObserverMap::lookup`].
+    pub fn lookup(&self, key: K) -> Lookup<V> {
+        self.read().lookup(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::ObservableMap;
+
+    #[test]
+    fn removed_keys_leave_tombstones_until_reinserted() {
+        let mut map = ObserverMap::new();
+        map.keep_tombstones();
+        let events = map.events();
+        map.insert("a", 1).unwrap();
+
+        assert_eq!(map.remove("a"), Ok(Some(1)));
+        assert_eq!(map.remove("a"), Ok(None));
+        assert_eq!(map.get("a"), None);
+        assert!(matches!(map.lookup("a"), Lookup::Removed(_)));
+        assert_eq!(map.lookup("b"), Lookup::Absent);
+        assert_eq!(map.generation(), 1);
+        let changes: Vec<_> = events.try_iter().map(|event| event.change).collect();
+        assert_eq!(changes, vec![Change::Inserted(1), Change::Removed]);
+
+        map.insert("a", 2).unwrap();
+        assert_eq!(map.lookup("a"), Lookup::Present(2));
+        map.remove("a").unwrap();
+        assert_eq!(
+            map.purge_tombstones(SystemTime::UNIX_EPOCH),
+            usize::from(!HAS_CLOCK)
+        );
+        assert_eq!(
+            map.purge_tombstones(SystemTime::now() + Duration::from_secs(1)),
+            usize::from(HAS_CLOCK)
+        );
+        assert_eq!(map.lookup("a"), Lookup::Absent);
+    }
+
+    #[test]
+    fn replicas_keep_tombstones_for_removals() {
+        let mut map = ObserverMap::new();
+        let events = map.events();
+        map.insert("a", 1).unwrap();
+        map.remove("a").unwrap();
+
+        let mut replica = ObserverMap::new();
+        replica.keep_tombstones();
+        for event in events.try_iter() {
+            replica.apply_event(event).unwrap();
+        }
+        assert!(matches!(replica.lookup("a"), Lookup::Removed(_)));
+    }
+}
@@ -0,0 +1,518 @@
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
+use core::time::Duration;
+#[cfg(os)]
+use std::time::Instant;
+
+use crate::channel::Sender;
+#[cfg(os)]
+use crate::clock::Clock;
+use crate::registry::{self, Items, Registry};
+#[cfg(feature = "tokio")]
+use crate::AsyncReceiver;
+use crate::{
+    channel, Attributed, Backpressure, DeliveryReport, InsertError, Item, Metrics, ObservableMap,
+    ObserveError, Receiver, WriteInfo,
+};
+#[cfg(os)]
+use crate::{throttle, wait_if_unset, WaitError};
+
+/// An [`ObservableMap`] of string keys stored in a radix trie, so keys sharing a prefix, such
+/// as the hierarchical `"a/b/c"`, share its storage, and every key under a prefix can be read
+/// without visiting the others. It supports the [`ObservableMap`] API and prefix reads only,
+/// without the extensions of [`ObserverMap`](crate::ObserverMap).
+pub struct TrieObserverMap<V> {
+    registry: Registry<Node<V>>,
+}
+
+// A node of the trie, reached from its parent by the bytes of `label`. Children are ordered
+// by the first byte of their labels, which are distinct.
+struct Node<V> {
+    label: Vec<u8>,
+    item: Option<Item<V>>,
+    // Observers of every key starting with the key leading to this node.
+    prefix_observers: Vec<Sender<(String, V)>>,
+    children: Vec<Node<V>>,
+}
+
+impl<V> Node<V> {
+    fn new(label: Vec<u8>) -> Self {
+        Self {
+            label,
+            item: None,
+            prefix_observers: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn child(&self, byte: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&byte, |child| child.label[0])
+    }
+
+    fn node(&self, key: &[u8]) -> Option<&Node<V>> {
+        let Some((&first, _)) = key.split_first() else {
+            return Some(self);
+        };
+        let child = &self.children[self.child(first).ok()?];
+        child.node(key.strip_prefix(child.label.as_slice())?)
+    }
+
+    // The node for `key`, splitting labels and adding nodes as needed.
+    fn node_mut(&mut self, key: &[u8]) -> &mut Node<V> {
+        let Some((&first, _)) = key.split_first() else {
+            return self;
+        };
+        let index = match self.child(first) {
+            Ok(index) => index,
+            Err(index) => {
+                self.children.insert(index, Node::new(key.to_vec()));
+                return &mut self.children[index];
+            }
+        };
+        let child = &mut self.children[index];
+        let common = child
+            .label
+            .iter()
+            .zip(key)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common < child.label.len() {
+            let lower = Node {
+                label: child.label.split_off(common),
+                item: child.item.take(),
+                prefix_observers: mem::take(&mut child.prefix_observers),
+                children: mem::take(&mut child.children),
+            };
+            child.children.push(lower);
+        }
+        child.node_mut(&key[common..])
+    }
+
+    // The node whose subtree holds every key starting with `prefix`, and the key leading to it.
+    fn subtree(&self, prefix: &[u8], mut path: Vec<u8>) -> Option<(&Node<V>, Vec<u8>)> {
+        let Some((&first, _)) = prefix.split_first() else {
+            return Some((self, path));
+        };
+        let child = &self.children[self.child(first).ok()?];
+        path.extend_from_slice(&child.label);
+        match prefix.strip_prefix(child.label.as_slice()) {
+            Some(rest) => child.subtree(rest, path),
+            None => child.label.starts_with(prefix).then_some((child, path)),
+        }
+    }
+
+    fn close_prefixes(&mut self) {
+        self.prefix_observers.clear();
+        for child in &mut self.children {
+            child.close_prefixes();
+        }
+    }
+}
+
+impl<V> Items for Node<V> {
+    type Key = String;
+    type Value = V;
+
+    fn get(&self, key: &String) -> Option<&Item<V>> {
+        self.node(key.as_bytes())?.item.as_ref()
+    }
+
+    fn get_or_insert(&mut self, key: String) -> &mut Item<V> {
+        self.node_mut(key.as_bytes())
+            .item
+            .get_or_insert_with(Item::empty)
+    }
+
+    fn for_each(&self, f: &mut dyn FnMut(&Item<V>)) {
+        if let Some(item) = &self.item {
+            f(item);
+        }
+        for child in &self.children {
+            child.for_each(f);
+        }
+    }
+
+    fn for_each_mut(&mut self, f: &mut dyn FnMut(&mut Item<V>)) {
+        if let Some(item) = &mut self.item {
+            f(item);
+        }
+        for child in &mut self.children {
+            child.for_each_mut(f);
+        }
+    }
+}
+
+impl<V> Node<V>
+where
+    V: Clone,
+{
+    // Appends the values in this subtree, reached by `path`, in key order.
+    fn collect(&self, path: &mut Vec<u8>, values: &mut Vec<(String, V)>) {
+        if let Some(value) = self.item.as_ref().and_then(|item| item.value.clone()) {
+            // Every stored key is valid UTF-8, even though labels may split a character.
+            values.push((String::from_utf8_lossy(path).into_owned(), value));
+        }
+        for child in &self.children {
+            path.extend_from_slice(&child.label);
+            child.collect(path, values);
+            path.truncate(path.len() - child.label.len());
+        }
+    }
+
+    // Sends the key and value to the observers of each prefix of `rest`, the remainder of
+    // `key` below this node, dropping those whose receivers have gone away.
+    fn notify_prefixes(&mut self, rest: &[u8], key: &str, value: &V) {
+        self.prefix_observers
+            .retain(|tx| tx.send((key.to_string(), value.clone())).is_ok());
+        let Some((&first, _)) = rest.split_first() else {
+            return;
+        };
+        let Ok(index) = self.child(first) else {
+            return;
+        };
+        let child = &mut self.children[index];
+        if let Some(rest) = rest.strip_prefix(child.label.as_slice()) {
+            child.notify_prefixes(rest, key, value);
+        }
+    }
+}
+
+impl<V> TrieObserverMap<V> {
+    pub fn new() -> Self {
+        Self {
+            registry: Registry::new(Node::new(Vec::new())),
+        }
+    }
+
+    /// See [`ObserverMap::close`](crate::ObserverMap::close). Prefix observers are
+    /// disconnected too.
+    pub fn close(&mut self) {
+        self.registry.close();
+        self.registry.items.close_prefixes();
+    }
+
+    /// See [`ObserverMap::set_send_timeout`](crate::ObserverMap::set_send_timeout).
+    pub fn set_send_timeout(&mut self, timeout: Duration) {
+        self.registry.set_send_timeout(timeout)
+    }
+
+    /// See [`ObserverMap::set_metrics`](crate::ObserverMap::set_metrics).
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.registry.set_metrics(metrics)
+    }
+}
+
+impl<V> TrieObserverMap<V>
+where
+    V: Clone,
+{
+    /// The keys starting with `prefix` that have values, with their values, in key order.
+    pub fn get_prefix(&self, prefix: &str) -> Vec<(String, V)> {
+        let mut values = Vec::new();
+        if let Some((node, mut path)) = self.registry.items.subtree(prefix.as_bytes(), Vec::new()) {
+            node.collect(&mut path, &mut values);
+        }
+        values
+    }
+
+    /// Observes every update to the keys starting with `prefix`, receiving each key with its
+    /// value, until the receiver is dropped. Updates sent to a
+    /// [group](ObservableMap::insert_for_group) aren't seen by prefix observers.
+    pub fn observe_prefix(&mut self, prefix: &str) -> Result<Receiver<(String, V)>, ObserveError> {
+        if self.registry.is_closed() {
+            return Err(ObserveError::Closed);
+        }
+        let (tx, rx) = channel::unbounded();
+        let node = self.registry.items.node_mut(prefix.as_bytes());
+        node.prefix_observers.push(tx);
+        Ok(rx)
+    }
+
+    fn insert_notifying(
+        &mut self,
+        key: String,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<DeliveryReport, InsertError<V>> {
+        let value = self.registry.check_open(value)?;
+        if group.is_none() {
+            let root = &mut self.registry.items;
+            root.notify_prefixes(key.as_bytes(), &key, &value);
+        }
+        Ok(self.registry.update(key, value, group, writer))
+    }
+
+    fn insert_checked(
+        &mut self,
+        key: String,
+        value: V,
+        group: Option<&str>,
+        writer: Option<&str>,
+    ) -> Result<(), InsertError<V>> {
+        let report = self.insert_notifying(key, value.clone(), group, writer)?;
+        registry::reject_if_full(report, value)
+    }
+}
+
+impl<V> Default for TrieObserverMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> TrieObserverMap<V>
+where
+    V: Clone,
+{
+    /// See [`ObserverMap::insert_as`](crate::ObserverMap::insert_as).
+    pub fn insert_as(&mut self, key: String, value: V, writer: &str) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, Some(writer))
+    }
+
+    /// See [`ObserverMap::write_info`](crate::ObserverMap::write_info).
+    pub fn write_info(&self, key: String) -> Option<WriteInfo> {
+        self.registry.write_info(&key)
+    }
+
+    /// See [`ObserverMap::observe_attributed`](crate::ObserverMap::observe_attributed).
+    pub fn observe_attributed(
+        &mut self,
+        key: String,
+    ) -> Result<Receiver<Attributed<V>>, ObserveError> {
+        self.registry.observe_attributed(key)
+    }
+}
+
+impl<V> ObservableMap<String, V> for TrieObserverMap<V>
+where
+    V: Clone,
+{
+    fn insert(&mut self, key: String, value: V) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, None, None)
+    }
+
+    fn insert_reporting(
+        &mut self,
+        key: String,
+        value: V,
+    ) -> Result<DeliveryReport, InsertError<V>> {
+        self.insert_notifying(key, value, None, None)
+    }
+
+    fn insert_for_group(
+        &mut self,
+        key: String,
+        value: V,
+        group: &str,
+    ) -> Result<(), InsertError<V>> {
+        self.insert_checked(key, value, Some(group), None)
+    }
+
+    fn get(&self, key: String) -> Option<V> {
+        self.registry.record_get();
+        self.registry.items.get(&key)?.value.clone()
+    }
+
+    #[cfg(os)]
+    fn get_or_wait(&mut self, key: String) -> Result<V, WaitError> {
+        let started = Instant::now();
+        let current = match self.get(key.clone()) {
+            Some(value) => Ok(value),
+            None => Err(self.observe(key)?),
+        };
+        wait_if_unset(self, current, started)
+    }
+
+    fn observe(&mut self, key: String) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe(key)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.registry.is_closed()
+    }
+
+    fn observer_count(&self, key: String) -> usize {
+        self.registry.observer_count(&key)
+    }
+
+    fn total_observers(&self) -> usize {
+        self.registry.total_observers()
+    }
+
+    fn metrics(&self) -> Option<Arc<Metrics>> {
+        self.registry.metrics()
+    }
+
+    fn observe_with_backpressure(
+        &mut self,
+        key: String,
+        backpressure: Backpressure,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_backpressure(key, backpressure)
+    }
+
+    fn observe_with_capacity(
+        &mut self,
+        key: String,
+        capacity: usize,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_capacity(key, capacity)
+    }
+
+    fn observe_unbounded(&mut self, key: String) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_unbounded(key)
+    }
+
+    fn observe_with_initial(&mut self, key: String) -> Result<Receiver<V>, ObserveError> {
+        let current = self.get(key.clone());
+        self.registry.observe_with_initial(key, current)
+    }
+
+    #[cfg(os)]
+    fn observe_throttled(
+        &mut self,
+        key: String,
+        interval: Duration,
+    ) -> Result<Receiver<V>, ObserveError>
+    where
+        V: Send + 'static,
+    {
+        let rx = self.observe_unbounded(key)?;
+        Ok(throttle(rx, interval, Clock::default()))
+    }
+
+    fn observe_group(&mut self, key: String, group: &str) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_group(key, group)
+    }
+
+    fn on_update<F>(&mut self, key: String, callback: F) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.on_update_with_priority(key, 0, callback)
+    }
+
+    fn on_update_with_priority<F>(
+        &mut self,
+        key: String,
+        priority: i32,
+        callback: F,
+    ) -> Result<(), ObserveError>
+    where
+        F: FnMut(&V) + Send + 'static,
+    {
+        self.registry
+            .on_update_with_priority(key, priority, callback)
+    }
+
+    fn observe_with_priority(
+        &mut self,
+        key: String,
+        priority: i32,
+    ) -> Result<Receiver<V>, ObserveError> {
+        self.registry.observe_with_priority(key, priority)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn watch(
+        &mut self,
+        key: String,
+    ) -> Result<tokio::sync::watch::Receiver<Option<V>>, ObserveError> {
+        let current = self.get(key.clone());
+        self.registry.watch(key, current)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn broadcast(
+        &mut self,
+        key: String,
+        capacity: usize,
+    ) -> Result<tokio::sync::broadcast::Receiver<V>, ObserveError> {
+        self.registry.broadcast(key, capacity)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async(&mut self, key: String) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async(key)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn observe_async_with_capacity(
+        &mut self,
+        key: String,
+        capacity: usize,
+    ) -> Result<AsyncReceiver<V>, ObserveError> {
+        self.registry.observe_async_with_capacity(key, capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_keys_sharing_prefixes() {
+        let mut map = TrieObserverMap::new();
+        let rx = map.observe_unbounded("a/b".to_string()).unwrap();
+        map.insert("a/b/c".to_string(), 1).unwrap();
+        map.insert("a/b".to_string(), 2).unwrap();
+        map.insert("a/bd".to_string(), 3).unwrap();
+        map.insert("a/x".to_string(), 4).unwrap();
+        map.insert("é".to_string(), 5).unwrap();
+        map.insert("è".to_string(), 6).unwrap();
+
+        assert_eq!(map.get("a/b/c".to_string()), Some(1));
+        assert_eq!(map.get("a/b".to_string()), Some(2));
+        assert_eq!(map.get("a/".to_string()), None);
+        assert_eq!(map.get("é".to_string()), Some(5));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            map.get_prefix("a/b"),
+            vec![
+                ("a/b".to_string(), 2),
+                ("a/b/c".to_string(), 1),
+                ("a/bd".to_string(), 3),
+            ]
+        );
+        assert_eq!(map.get_prefix("a/b/").len(), 1);
+        assert_eq!(map.get_prefix("").len(), 6);
+        assert!(map.get_prefix("b").is_empty());
+    }
+
+    #[test]
+    fn prefix_observers_see_only_matching_keys() {
+        let mut map = TrieObserverMap::new();
+        map.insert("a/bc".to_string(), 0).unwrap();
+        // "a/b" ends inside the label of the existing "a/bc" node, which is split for it.
+        let rx = map.observe_prefix("a/b").unwrap();
+        let all = map.observe_prefix("").unwrap();
+
+        map.insert("a/bc".to_string(), 1).unwrap();
+        map.insert("a/b".to_string(), 2).unwrap();
+        map.insert("a/x".to_string(), 3).unwrap();
+        map.insert("a/".to_string(), 4).unwrap();
+        map.insert("b".to_string(), 5).unwrap();
+        map.insert("a/bd/e".to_string(), 6).unwrap();
+        map.insert_for_group("a/bc".to_string(), 7, "g").unwrap();
+
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![
+                ("a/bc".to_string(), 1),
+                ("a/b".to_string(), 2),
+                ("a/bd/e".to_string(), 6),
+            ]
+        );
+        assert_eq!(all.try_iter().count(), 6);
+        assert_eq!(map.get("a/bc".to_string()), Some(7));
+
+        drop(all);
+        map.close();
+        assert!(rx.recv().is_err());
+        assert_eq!(map.observe_prefix("a").unwrap_err(), ObserveError::Closed);
+    }
+}
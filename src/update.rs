@@ -0,0 +1,168 @@
+use core::hash::Hash;
+use core::ops::{Deref, DerefMut};
+
+use crate::{InsertError, ObservableMap, ObserverMap};
+
+/// Mutable access to a key's value, returned by [`ObserverMap::get_mut`]. The guard takes the
+/// value out of the map to edit it, and inserts it when the guard is dropped, so observers,
+/// interceptors and the rest of the insert path see the change as they would any other insert.
+///
+/// The value is only copied if the map has interceptors or a write-ahead log, which could
+/// reject the edit, so that the previous value can be put back first and stays in place if
+/// the edit is rejected. Otherwise the edit is inserted as though the key had no value:
+/// delta observers receive it whole, and resolvers and observer filters don't see the
+/// previous value.
+///
+/// Dropping the guard discards any error from the insert. Use
+/// [`commit`](UpdateGuard::commit) to find out whether the insert succeeded.
+pub struct UpdateGuard<'a, K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    map: &'a mut ObserverMap<K, V>,
+    // Taken when the value is inserted.
+    update: Option<(K, V)>,
+    // A copy of the value before it was edited, if the edit could be rejected.
+    previous: Option<V>,
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// A guard editing the key's value, or `None` if it has no value, or the map is closed
+    /// or write-once so that any edit would be rejected.
+    pub fn get_mut(&mut self, key: K) -> Option<UpdateGuard<'_, K, V>> {
+        if self.closed || self.write_once {
+            return None;
+        }
+        self.read(self.hashmap.get(&key)?)?;
+        let may_reject = self.may_reject();
+        let value = self.hashmap.get_mut(&key)?.value.take()?;
+        let previous = may_reject.then(|| value.clone());
+        Some(UpdateGuard {
+            map: self,
+            update: Some((key, value)),
+            previous,
+        })
+    }
+
+    // Whether an insert into an open map that isn't write-once could be rejected.
+    fn may_reject(&self) -> bool {
+        let may_reject = !self.interceptors.is_empty();
+        #[cfg(feature = "wal")]
+        let may_reject = may_reject || self.wal.is_some();
+        may_reject
+    }
+}
+
+impl<K, V> UpdateGuard<'_, K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    /// Inserts the edited value now, rather than when the guard is dropped.
+    pub fn commit(mut self) -> Result<(), InsertError<V>> {
+        self.insert()
+    }
+
+    fn insert(&mut self) -> Result<(), InsertError<V>> {
+        let (key, value) = self.update.take().expect("inserted only once");
+        if let Some(previous) = self.previous.take() {
+            if let Some(item) = self.map.hashmap.get_mut(&key) {
+                item.value = Some(previous);
+            }
+        }
+        self.map.insert(key, value)
+    }
+}
+
+impl<K, V> Deref for UpdateGuard<'_, K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.update.as_ref().expect("inserted only once").1
+    }
+}
+
+impl<K, V> DerefMut for UpdateGuard<'_, K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        &mut self.update.as_mut().expect("inserted only once").1
+    }
+}
+
+impl<K, V> Drop for UpdateGuard<'_, K, V>
+where
+    K: Hash + Eq,
+    V: Clone,
+{
+    fn drop(&mut self) {
+        if self.update.is_some() {
+            // A rejected edit is discarded, leaving the previous value in place.
+            let _ = self.insert();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Rejection;
+    use std::sync::Arc;
+
+    #[test]
+    fn observers_see_edits_when_the_guard_is_dropped() {
+        let mut map = ObserverMap::new();
+        map.insert("book", vec![1, 2]).unwrap();
+        let rx = map.observe_unbounded("book").unwrap();
+        assert!(map.get_mut("missing").is_none());
+
+        {
+            let mut book = map.get_mut("book").unwrap();
+            book.push(3);
+            book[0] = 0;
+        }
+        assert_eq!(rx.try_recv(), Ok(vec![0, 2, 3]));
+        assert_eq!(map.get("book"), Some(vec![0, 2, 3]));
+
+        map.add_interceptor(|_, book: &mut Vec<i32>| match book.len() {
+            0..=3 => Ok(()),
+            _ => Err("too long".into()),
+        });
+        let mut book = map.get_mut("book").unwrap();
+        book.push(4);
+        assert_eq!(
+            book.commit(),
+            Err(InsertError::Invalid(
+                vec![0, 2, 3, 4],
+                Rejection::new("too long")
+            ))
+        );
+        assert_eq!(map.get("book"), Some(vec![0, 2, 3]));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn edits_take_the_value_rather_than_copying_it() {
+        let mut map = ObserverMap::new();
+        map.insert("book", Arc::new(vec![1])).unwrap();
+
+        let mut book = map.get_mut("book").unwrap();
+        Arc::get_mut(&mut book).unwrap().push(2);
+        drop(book);
+        assert_eq!(map.get("book"), Some(Arc::new(vec![1, 2])));
+
+        map.close();
+        assert!(map.get_mut("book").is_none());
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "encryption")]
+use crate::EncryptionKey;
+
+// A change to the map. Removals are logged as well as inserts, so that a removed value isn't
+// restored when the log is replayed.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Record<K, V> {
+    Insert(K, V),
+    Remove(K),
+    Clear,
+}
+
+// An append-only log of JSON records, one per line, synced to disk as each is appended.
+// Encrypted records are hex encoded so that they can't contain a newline.
+pub(crate) struct WriteAheadLog<K, V> {
+    file: File,
+    // Monomorphised when the log is opened, so that the map needn't carry serde bounds.
+    encode: fn(Record<&K, &V>) -> serde_json::Result<Vec<u8>>,
+    #[cfg(feature = "encryption")]
+    key: Option<EncryptionKey>,
+}
+
+impl<K, V> WriteAheadLog<K, V> {
+    pub(crate) fn append(&mut self, record: Record<&K, &V>) -> io::Result<()> {
+        let line = (self.encode)(record)?;
+        #[cfg(feature = "encryption")]
+        let line = match &self.key {
+            Some(key) => to_hex(&key.encrypt(&line)),
+            None => line,
+        };
+        self.file.write_all(&[line.as_slice(), b"\n"].concat())?;
+        self.file.sync_data()
+    }
+}
+
+fn encode<K, V>(record: Record<&K, &V>) -> serde_json::Result<Vec<u8>>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    serde_json::to_vec(&record)
+}
+
+pub(crate) type Replay<K, V> = (Vec<Record<K, V>>, WriteAheadLog<K, V>);
+
+// Opens the log at `path`, creating it if necessary, and returns the changes it records in
+// order.
+pub(crate) fn open<K, V>(path: &Path) -> io::Result<Replay<K, V>>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    let (records, file) = replay(path, |line| Ok(line.to_vec()))?;
+    let log = WriteAheadLog {
+        file,
+        encode: encode::<K, V>,
+        #[cfg(feature = "encryption")]
+        key: None,
+    };
+    Ok((records, log))
+}
+
+// Like `open`, for a log whose records are encrypted with `key`.
+#[cfg(feature = "encryption")]
+pub(crate) fn open_encrypted<K, V>(path: &Path, key: EncryptionKey) -> io::Result<Replay<K, V>>
+where
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    let (records, file) = replay(path, |line| key.decrypt(&from_hex(line)?))?;
+    let log = WriteAheadLog {
+        file,
+        encode: encode::<K, V>,
+        key: Some(key),
+    };
+    Ok((records, log))
+}
+
+// Reads the records of the log at `path`, decoding each line with `decode`. A final record
+// without a trailing newline was torn by a crash whilst being appended, so it is discarded.
+fn replay<K, V>(
+    path: &Path,
+    decode: impl Fn(&[u8]) -> io::Result<Vec<u8>>,
+) -> io::Result<(Vec<Record<K, V>>, File)>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+
+    let complete = contents
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    if complete < contents.len() {
+        file.set_len(complete as u64)?;
+    }
+
+    let mut records = Vec::new();
+    for line in contents[..complete].split(|&byte| byte == b'\n') {
+        if line.is_empty() {
+            continue;
+        }
+        records.push(serde_json::from_slice(&decode(line)?)?);
+    }
+    Ok((records, file))
+}
+
+#[cfg(feature = "encryption")]
+fn to_hex(bytes: &[u8]) -> Vec<u8> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    bytes
+        .iter()
+        .flat_map(|byte| {
+            [
+                DIGITS[usize::from(byte >> 4)],
+                DIGITS[usize::from(byte & 0xf)],
+            ]
+        })
+        .collect()
+}
+
+#[cfg(feature = "encryption")]
+fn from_hex(hex: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid hex record");
+    let digit = |byte: u8| char::from(byte).to_digit(16).ok_or_else(invalid);
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    hex.chunks(2)
+        .map(|pair| Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
@@ -0,0 +1,113 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::hash::Hash;
+
+use crate::sync::lock::{Mutex, PoisonError};
+
+use crate::channel::{self, Sender};
+use crate::observer::Delivery;
+use crate::project::Projection;
+use crate::{ObservableMap, ObserveError, Observer, ObserverMap, Receiver, ThreadSafeObserverMap};
+
+// The latest values of both keys of a zip.
+type Latest<T> = Arc<Mutex<(Option<T>, Option<T>)>>;
+
+// Observes one key of a zip, sending the latest pair whenever it updates.
+struct ZipSide<T> {
+    first: bool,
+    latest: Latest<T>,
+    tx: Sender<(T, T)>,
+}
+
+impl<T> Projection<T> for ZipSide<T>
+where
+    T: Clone + Send,
+{
+    fn send(&self, value: &T) -> Delivery {
+        let mut latest = self.latest.lock().unwrap_or_else(PoisonError::into_inner);
+        match self.first {
+            true => latest.0 = Some(value.clone()),
+            false => latest.1 = Some(value.clone()),
+        }
+        let (Some(a), Some(b)) = &*latest else {
+            return Delivery::Delivered;
+        };
+        match self.tx.send((a.clone(), b.clone())) {
+            Ok(()) => Delivery::Delivered,
+            Err(_) => Delivery::Disconnected,
+        }
+    }
+
+    fn receivers(&self) -> usize {
+        usize::from(!self.tx.is_disconnected())
+    }
+
+    fn pending(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+impl<K, V> ObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone + Send + 'static,
+{
+    /// Observes the latest values of `a` and `b` together. Once both keys have values, every
+    /// update to either sends the pair of their latest values. Values they already have
+    /// count, but aren't sent until one of them updates. Pairs queue up without limit if the
+    /// receiver falls behind.
+    pub fn observe_zip(&mut self, a: K, b: K) -> Result<Receiver<(V, V)>, ObserveError> {
+        let latest = Arc::new(Mutex::new((self.get(a.clone()), self.get(b.clone()))));
+        let (tx, rx) = channel::unbounded();
+        // Counted as dropped if the second key can't be observed, unregistering the first.
+        let rx = rx.unregister_on_drop(&self.dropped);
+        for (key, first) in [(a, true), (b, false)] {
+            let side = ZipSide {
+                first,
+                latest: latest.clone(),
+                tx: tx.clone(),
+            };
+            self.add_observer(key, Observer::Projected(Box::new(side)))?;
+        }
+        Ok(rx)
+    }
+}
+
+impl<K, V> ThreadSafeObserverMap<K, V>
+where
+    K: Hash + Eq + PartialEq + Clone,
+    V: Clone + Send + 'static,
+{
+    /// See [`ObserverMap::observe_zip`]. Both keys are observed under one lock, so no update
+    /// is missed between them.
+    pub fn observe_zip(&self, a: K, b: K) -> Result<Receiver<(V, V)>, ObserveError> {
+        self.write().observe_zip(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_the_latest_pair_once_both_keys_have_values() {
+        let mut map = ObserverMap::new();
+        map.insert("bid", 99).unwrap();
+        let rx = map.observe_zip("bid", "ask").unwrap();
+        assert_eq!(map.observer_count("ask"), 1);
+
+        map.insert("bid", 100).unwrap();
+        assert!(rx.try_recv().is_err());
+        map.insert("ask", 102).unwrap();
+        map.insert("ask", 101).unwrap();
+        map.insert("bid", 101).unwrap();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            vec![(100, 102), (100, 101), (101, 101)]
+        );
+
+        drop(rx);
+        map.insert("bid", 1).unwrap();
+        assert_eq!(map.total_observers(), 0);
+    }
+}